@@ -10,24 +10,85 @@
 //! In order to perform a swap between the two desired tokens, the owner must first initialize
 //! both token pools, `initialize_pool_{a,b}`, by transferring an amount of tokens to both pools via a transfer call to
 //! the corresponding token contract. This will also initialize the (final) value of `k`.
+//! Both pools can also be seeded in a single transaction with `provide_liquidity_both`, which only opens the
+//! contract if both transfers succeed, and refunds the owner otherwise.
 //!
 //! User's (including the owner) can then `deposit` tokens to the contract, which can be used to
 //! exchange to the opposite token. This is done by calling `swap`. `swap` will calculate the
 //! amount of tokens to convert of the incoming token to the opposite token, based on the above formula.
+//! A fee of `fee_per_mille` is taken from the input of every swap and left in the pool, so `k` grows over time
+//! and liquidity providers earn from the swaps. A swapper holding `lp_shares` is rebated the part of the fee
+//! that would go to their own share of the pool, so the fee they pay is reduced by `lp_shares / total_shares`.
+//! The owner can also set a `protocol_fee_per_mille`: that slice of each swap's input is kept out of the pool
+//! and accrues in `protocol_fees`, which only the `fee_recipient` can `collect_protocol_fees`.
+//! Users can also `add_liquidity` from their deposited balances. This moves the tokens into the pools and
+//! recomputes `k`, so the pools get deeper: the spot price is unchanged, but swaps move it less.
+//! The depositor is minted `lp_shares` in proportion to the liquidity added, which can later be burned with
+//! `remove_liquidity` for the same share of both pools, including the fees earned in the meantime.
+//! Leaving the fees in the pools compounds them automatically. The owner can instead disable `auto_compound`:
+//! the liquidity providers' part of each swap fee is then kept out of the pools and accrued to each provider in
+//! proportion to their shares. A provider can `compound` their accrued fees, which adds them to the pools at the
+//! current ratio and mints the matching shares, and `remove_liquidity` pays them out.
+//! The pools must each be initialized with at least `MIN_INITIAL_LIQUIDITY` units, and
+//! `LOCKED_LIQUIDITY_SHARES` of the initial shares are credited to the contract's own address, so the
+//! pools can never be emptied by removing liquidity.
 //! A user may then `withdraw` the resulting tokens of the swap (or simply his own deposited tokens).
 //!
 //! Finally, the owner of the contract may close the pools, `close_pools`, by transferring both token pools to his own account,
 //! effectively closing the contract. Only valid withdrawals are allowed in the closed state.
+//! Closing starts a grace-withdrawal window of `grace_withdrawal_period`, until which the pools cannot be re-initialized.
 //!
 //! Both `deposit` and `withdraw` makes use of `transfer` calls to the token contract, which
 //! are ensured to be successful via callbacks.
+//! Every `deposit` records a `pending_deposits` intent under a fresh nonce, and `deposit_callback` only
+//! credits the user of a pending intent, so a callback the contract did not initiate cannot credit anyone.
+//!
+//! If the owner configures a price `oracle`, swaps must instead be made through `swap_with_oracle_check`,
+//! which queries the oracle for a reference price and rejects the swap in a callback if it would execute
+//! too far away from that price.
+//!
+//! Users may also take a `collateralized_loan` of pool tokens: the tokens are sent to a borrower contract,
+//! which is invoked and must then have the borrowed amount plus a fee pulled back via `transfer_from`.
+//! The loan is fully pre-funded: the borrowed amount plus the fee is held from the caller's deposited
+//! balance as collateral, and released in a callback once the repayment is confirmed. An unpaid loan is
+//! covered by the collateral, so the pools are never left short. An optimistic flash swap, repaid within
+//! the transaction that lends it, is not possible, as the calls of an event group are executed
+//! asynchronously and a failed repayment cannot revert the lending transfer.
+//!
+//! A swap can be tried out with `simulate_swap`, which runs the same checks and math as `swap` and reports
+//! whether it would succeed and its output, without changing the state. `quote_swap` only computes the
+//! output of a given input, without checking any balance or guard.
+//!
+//! Swaps can be routed across sibling liquidity-swap contracts with `swap_through`: the output of a swap
+//! here is approved for, deposited into and swapped on the next contract, and the final output is withdrawn
+//! and transferred to the user. Each of these steps waits for the callback of the previous one. A final
+//! output that cannot be transferred to the user is recorded in `routed_balances` if it is not one of the
+//! pool tokens, and can be claimed with `withdraw_routed`.
 //!
 //! Because the relative price of the two tokens can only be changed through swapping,
 //! divergences between the prices of the current contract and the prices of similar external contracts create arbitrage opportunities.
 //! This mechanism ensures that the contract's prices always trend toward the market-clearing price.
 //!
 //! The two token contracts linked to this contract must currently be owned by the same owner,
-//! as this contract.
+//! as this contract. `initialize` queries the owner and `decimals` of both tokens and the pools cannot be initialized
+//! until `verify_tokens_callback` has confirmed the ownership.
+//!
+//! `withdraw` checks its transfer in a callback, and the amount of a failed transfer is reclaimed into the
+//! user's balance. If the owner enables `retry_failed_withdrawals`, it is instead recorded in `pending_withdrawals`,
+//! and the user can `retry_withdraw` it later.
+//!
+//! A swap can lock its output in the user's balance until `lock_until`, e.g. for vesting-style distributions.
+//! Locked output cannot be withdrawn or swapped before it unlocks.
+//!
+//! Users can set an `auto_withdraw_threshold`: once a swap leaves them with more than the threshold of the
+//! output token, their whole balance of it is withdrawn as part of the swap.
+//!
+//! The owner can also `rebalance` the pools towards a target reserve ratio, by swapping from their own
+//! deposited balance. The rebalancing swap is computed so that it never moves the pools past the target.
+//!
+//! Tokens with different `decimals` are priced on a common scale: pool amounts are normalized to the
+//! smaller of the two precisions before the `constant product formula` is applied, and outputs are
+//! denormalized back to the precision of the output token.
 #![allow(unused_variables)]
 
 mod tests;
@@ -39,10 +100,11 @@ extern crate core;
 use create_type_spec_derive::CreateTypeSpec;
 use pbc_contract_common::address::{Address, AddressType, Shortname};
 use pbc_contract_common::context::{CallbackContext, ContractContext};
-use pbc_contract_common::events::EventGroup;
+use pbc_contract_common::events::{EventGroup, EventGroupBuilder};
+use pbc_traits::ReadWriteRPC;
 use read_write_rpc_derive::ReadWriteRPC;
 use read_write_state_derive::ReadWriteState;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, ReadWriteRPC)]
@@ -73,6 +135,30 @@ impl pbc_traits::CreateTypeSpec for Token {
 const TOKEN_A: Token = Token::A;
 const TOKEN_B: Token = Token::B;
 
+/// The number of basis points in a whole.
+const MAX_BPS: u64 = 10000;
+
+/// The number of per mille in a whole.
+const PER_MILLE: u64 = 1000;
+
+/// The fee paid on top of a collateralized loan, in basis points of the borrowed amount.
+const LOAN_FEE_BPS: u64 = 30;
+
+/// The fixed-point scale of oracle prices, which are quoted as units of token B per unit of token A.
+const ORACLE_PRICE_SCALE: u64 = 1_000_000;
+
+/// The fixed-point scale of spot prices, which are quoted as output-token units per input-token unit.
+const SPOT_PRICE_SCALE: u64 = 1_000_000;
+
+/// The smallest size of each pool, on the common scale, with which the contract can be opened.
+const MIN_INITIAL_LIQUIDITY: u64 = 1000;
+
+/// The fixed-point scale of `fee_growth`, the fees accrued per liquidity share.
+const FEE_GROWTH_SCALE: u128 = 1_000_000_000_000;
+
+/// The liquidity shares of the initial liquidity that are credited to the contract itself and never burned.
+const LOCKED_LIQUIDITY_SHARES: u64 = 100;
+
 /// A token pool that holds tokens which can be swapped by users.
 ///
 /// ### Fields:
@@ -80,10 +166,13 @@ const TOKEN_B: Token = Token::B;
 /// * `token_address`: [`Address`] - The address of the token contract.
 ///
 /// * `pool`: [`u64`] - The amount of tokens a token pool has.
+///
+/// * `min_seed`: [`u64`] - The smallest amount the owner may provide to the pool at once.
 #[derive(ReadWriteState, CreateTypeSpec, Clone, PartialEq, Eq)]
 pub struct TokenPool {
     token_address: Address,
     pool: u64,
+    min_seed: u64,
 }
 
 /// Holds user balances for the two tokens.
@@ -101,6 +190,14 @@ pub struct UserBalance {
 }
 
 impl UserBalance {
+    fn get_balance_for(&self, token: Token) -> u64 {
+        if token == TOKEN_A {
+            self.pool_a_balance
+        } else {
+            self.pool_b_balance
+        }
+    }
+
     fn get_mut_balance_for(&mut self, token: Token) -> &mut u64 {
         if token == TOKEN_A {
             &mut self.pool_a_balance
@@ -110,6 +207,99 @@ impl UserBalance {
     }
 }
 
+/// An amount of a token that cannot leave a user's balance before `unlock_at`.
+///
+/// ### Fields:
+///
+/// * `amount`: [`u64`] - the locked amount.
+///
+/// * `unlock_at`: [`i64`] - the block production time at which the amount unlocks.
+#[derive(ReadWriteState, CreateTypeSpec, Clone, PartialEq, Eq)]
+pub struct OutputLock {
+    amount: u64,
+    unlock_at: i64,
+}
+
+/// The swap fees accrued per liquidity share, scaled by [`FEE_GROWTH_SCALE`].
+///
+/// ### Fields:
+///
+/// * `token_a`: [`u128`] - the accrued fees of token A per share.
+///
+/// * `token_b`: [`u128`] - the accrued fees of token B per share.
+#[derive(ReadWriteState, CreateTypeSpec, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeGrowth {
+    token_a: u128,
+    token_b: u128,
+}
+
+impl FeeGrowth {
+    fn get_mut_growth_for(&mut self, token: Token) -> &mut u128 {
+        if token == TOKEN_A {
+            &mut self.token_a
+        } else {
+            &mut self.token_b
+        }
+    }
+}
+
+/// A deposit the contract has requested the transfer of, awaiting `deposit_callback`.
+///
+/// ### Fields:
+///
+/// * `user`: [`Address`] - the user to credit.
+///
+/// * `token_address`: [`Address`] - the address of the deposited token contract.
+///
+/// * `amount`: [`u64`] - the amount to credit.
+#[derive(ReadWriteState, CreateTypeSpec, Clone, PartialEq, Eq)]
+pub struct DepositIntent {
+    user: Address,
+    token_address: Address,
+    amount: u64,
+}
+
+/// A swap routed through a sibling liquidity-swap contract by `swap_through`, passed along its callbacks.
+///
+/// ### Fields:
+///
+/// * `user`: [`Address`] - the user who requested the swap.
+///
+/// * `hop_token`: [`Token`] - the token passed on to the next hop.
+///
+/// * `hop_amount`: [`u64`] - the output of the first hop.
+///
+/// * `next_contract`: [`Address`] - the liquidity-swap contract performing the next hop.
+///
+/// * `next_output_token`: [`Address`] - the token the next hop swaps to.
+///
+/// * `minimum_amount_out`: [`u64`] - the smallest final output the user accepts.
+///
+/// * `deadline_millis`: [`i64`] - the latest block production time at which the next hop may execute.
+#[derive(ReadWriteRPC, CreateTypeSpec, Clone, PartialEq, Eq)]
+pub struct SwapRoute {
+    user: Address,
+    hop_token: Token,
+    hop_amount: u64,
+    next_contract: Address,
+    next_output_token: Address,
+    minimum_amount_out: u64,
+    deadline_millis: i64,
+}
+
+/// The outcome of a swap, as reported by `simulate_swap`.
+///
+/// ### Fields:
+///
+/// * `output`: [`u64`] - the output the swap would yield, or zero if it would fail.
+///
+/// * `failure`: [`Option<String>`] - the reason the swap would fail, or `None` if it would succeed.
+#[derive(ReadWriteRPC, CreateTypeSpec, Clone, Debug, PartialEq, Eq)]
+pub struct SwapSimulation {
+    output: u64,
+    failure: Option<String>,
+}
+
 /// This is the state of the contract which is persisted on the chain.
 ///
 /// The #\[state\] macro generates serialization logic for the struct.
@@ -122,20 +312,115 @@ impl UserBalance {
 ///
 /// * `token_pool_b`: [`Address`] - The address of the second token contract.
 ///
-/// * `swap_constant`: [`u64`] - The invariant used to calculate exchange rates.
+/// * `swap_constant`: [`u128`] - The invariant used to calculate exchange rates, in normalized units.
 ///    It's based on the 'constant product formula': x * y = k, k being the swap_constant.
 ///
 /// * `user_balances`: [`BTreeMap<Address, UserBalance>`] - The map containing all token balances of all users of the contract.
 ///
 /// * `is_closed`: [`bool`] - Boolean indicating whether the contract is operable or not.
+///
+/// * `max_swap_bps`: [`u64`] - The maximum input of a single swap, in basis points of the from-pool.
+///    A value of zero disables the cap.
+///
+/// * `min_output_floor`: [`u64`] - The smallest output a swap may produce. A value of zero disables the floor.
+///
+/// * `max_pool_ratio`: [`u64`] - The largest ratio between the two pools a swap may leave behind,
+///    e.g. `1000` for 1000:1. A value of zero disables the guard.
+///
+/// * `close_authorities`: [`BTreeSet<Address>`] - Addresses besides the owner that may close the pools.
+///
+/// * `oracle`: [`Option<Address>`] - The contract queried for a reference price before swapping. `None` disables the check.
+///
+/// * `max_oracle_deviation_bps`: [`u64`] - The largest deviation, in basis points, between the execution price
+///    of a swap and the oracle price.
+///
+/// * `grace_withdrawal_period`: [`i64`] - The length, in milliseconds, of the withdrawal window started by `close_pools`.
+///
+/// * `withdraw_until`: [`i64`] - The block production time at which the current withdrawal window ends.
+///
+/// * `tokens_verified`: [`bool`] - Whether both tokens have been confirmed to be owned by the contract owner.
+///
+/// * `token_a_decimals`: [`u8`] - The `decimals` of token A.
+///
+/// * `token_b_decimals`: [`u8`] - The `decimals` of token B.
+///
+/// * `fee_per_mille`: [`u64`] - The fee taken from the input of each swap, in per mille, e.g. `3` for 0.3%.
+///
+/// * `retry_failed_withdrawals`: [`bool`] - Whether failed withdrawal transfers are recorded for a retry,
+///    rather than reclaimed into the user's balance.
+///
+/// * `pending_withdrawals`: [`BTreeMap<Address, UserBalance>`] - The amounts of failed withdrawals each user can retry.
+///
+/// * `auto_withdraw_thresholds`: [`BTreeMap<Address, u64>`] - The balance above which the output of a user's swap
+///    is withdrawn automatically.
+///
+/// * `output_locks`: [`BTreeMap<Address, BTreeMap<Address, OutputLock>>`] - The locked swap outputs of each user,
+///    per token contract address.
+///
+/// * `lp_shares`: [`BTreeMap<Address, u64>`] - The liquidity shares held by each liquidity provider.
+///
+/// * `total_shares`: [`u64`] - The sum of all `lp_shares`.
+///
+/// * `pending_deposits`: [`BTreeMap<u64, DepositIntent>`] - The deposits awaiting their callback, by nonce.
+///
+/// * `next_deposit_nonce`: [`u64`] - The nonce of the next deposit.
+///
+/// * `protocol_fee_per_mille`: [`u64`] - The slice of the input of each swap routed to `protocol_fees`
+///    rather than the pool, in per mille.
+///
+/// * `fee_recipient`: [`Address`] - The address allowed to collect the `protocol_fees`.
+///
+/// * `protocol_fees`: [`BTreeMap<Address, u64>`] - The accrued protocol fees, per token contract address.
+///
+/// * `routed_balances`: [`BTreeMap<Address, BTreeMap<Address, u64>>`] - The payouts of `swap_through` in tokens
+///    other than the pool tokens whose transfer failed, per user and token contract address.
+///
+/// * `auto_compound`: [`bool`] - Whether the liquidity providers' part of each swap fee is left in the pools.
+///
+/// * `fee_growth`: [`FeeGrowth`] - The fees kept out of the pools per liquidity share, while `auto_compound`
+///    is disabled.
+///
+/// * `fee_checkpoints`: [`BTreeMap<Address, FeeGrowth>`] - The `fee_growth` at which the fees of each liquidity
+///    provider were last accrued.
+///
+/// * `accrued_fees`: [`BTreeMap<Address, UserBalance>`] - The fees accrued to each liquidity provider, awaiting
+///    `compound` or `remove_liquidity`.
 #[state]
 pub struct LiquiditySwapContractState {
     contract_owner: Address,
     token_pool_a: TokenPool,
     token_pool_b: TokenPool,
-    swap_constant: u64,
+    swap_constant: u128,
     user_balances: BTreeMap<Address, UserBalance>,
     is_closed: bool,
+    max_swap_bps: u64,
+    min_output_floor: u64,
+    max_pool_ratio: u64,
+    close_authorities: BTreeSet<Address>,
+    oracle: Option<Address>,
+    max_oracle_deviation_bps: u64,
+    grace_withdrawal_period: i64,
+    withdraw_until: i64,
+    tokens_verified: bool,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    fee_per_mille: u64,
+    retry_failed_withdrawals: bool,
+    pending_withdrawals: BTreeMap<Address, UserBalance>,
+    auto_withdraw_thresholds: BTreeMap<Address, u64>,
+    output_locks: BTreeMap<Address, BTreeMap<Address, OutputLock>>,
+    lp_shares: BTreeMap<Address, u64>,
+    total_shares: u64,
+    pending_deposits: BTreeMap<u64, DepositIntent>,
+    next_deposit_nonce: u64,
+    protocol_fee_per_mille: u64,
+    fee_recipient: Address,
+    protocol_fees: BTreeMap<Address, u64>,
+    routed_balances: BTreeMap<Address, BTreeMap<Address, u64>>,
+    auto_compound: bool,
+    fee_growth: FeeGrowth,
+    fee_checkpoints: BTreeMap<Address, FeeGrowth>,
+    accrued_fees: BTreeMap<Address, UserBalance>,
 }
 
 impl LiquiditySwapContractState {
@@ -184,6 +469,263 @@ impl LiquiditySwapContractState {
         *token_balance = new_token_balance;
     }
 
+    /// Adds the amount of a failed withdrawal to the `pending_withdrawals` map of the contract.
+    /// If the user isn't already present, creates an entry with an empty UserBalance.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The key of the entry.
+    ///
+    /// * `token`: [`Token`] - The token of the withdrawal.
+    ///
+    /// * `amount`: [`u64`] - The amount to add.
+    ///
+    fn add_to_pending_withdrawal(&mut self, user: Address, token: Token, amount: u64) {
+        let pending = self.pending_withdrawals.entry(user).or_insert(UserBalance {
+            pool_a_balance: 0,
+            pool_b_balance: 0,
+        });
+
+        *pending.get_mut_balance_for(token) += amount;
+    }
+
+    /// Removes and returns the pending withdrawal of `token` for `user`.
+    /// The entry of the user is removed once nothing is pending for either token.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The key of the entry.
+    ///
+    /// * `token`: [`Token`] - The token of the withdrawal.
+    ///
+    /// # Returns
+    /// The pending amount of type [`u64`], zero if nothing is pending.
+    fn take_pending_withdrawal(&mut self, user: Address, token: Token) -> u64 {
+        let Some(pending) = self.pending_withdrawals.get_mut(&user) else {
+            return 0;
+        };
+        let amount = std::mem::take(pending.get_mut_balance_for(token));
+        if pending.pool_a_balance == 0 && pending.pool_b_balance == 0 {
+            self.pending_withdrawals.remove(&user);
+        }
+        amount
+    }
+
+    /// Adds the amount of a failed `swap_through` payout to the `routed_balances` map of the contract.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user of the payout.
+    ///
+    /// * `token_address`: [`Address`] - The token contract of the payout.
+    ///
+    /// * `amount`: [`u64`] - The amount to add.
+    ///
+    fn add_to_routed_balance(&mut self, user: Address, token_address: Address, amount: u64) {
+        *self
+            .routed_balances
+            .entry(user)
+            .or_default()
+            .entry(token_address)
+            .or_insert(0) += amount;
+    }
+
+    /// Removes and returns the routed balance of `token_address` for `user`.
+    /// The entry of the user is removed once no token is left.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The key of the entry.
+    ///
+    /// * `token_address`: [`Address`] - The token contract of the balance.
+    ///
+    /// # Returns
+    /// The routed amount of type [`u64`], zero if nothing is recorded.
+    fn take_routed_balance(&mut self, user: Address, token_address: Address) -> u64 {
+        let Some(balances) = self.routed_balances.get_mut(&user) else {
+            return 0;
+        };
+        let amount = balances.remove(&token_address).unwrap_or(0);
+        if balances.is_empty() {
+            self.routed_balances.remove(&user);
+        }
+        amount
+    }
+
+    /// Spreads `fee` of `token` over the liquidity shares by growing `fee_growth`.
+    /// The rounding dust stays in the contract.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token of the fee.
+    ///
+    /// * `fee`: [`u64`] - The fee kept out of the pool.
+    ///
+    fn accrue_fee(&mut self, token: Token, fee: u64) {
+        if self.total_shares == 0 {
+            return;
+        }
+        *self.fee_growth.get_mut_growth_for(token) +=
+            u128::from(fee) * FEE_GROWTH_SCALE / u128::from(self.total_shares);
+    }
+
+    /// Moves the fees accrued to `provider` since their checkpoint into `accrued_fees`, and moves the
+    /// checkpoint to the current `fee_growth`. Must be called before the shares of `provider` change.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `provider`: [`Address`] - The liquidity provider to settle.
+    ///
+    fn settle_fees(&mut self, provider: Address) {
+        let shares = u128::from(self.lp_shares.get(&provider).copied().unwrap_or(0));
+        let checkpoint = self
+            .fee_checkpoints
+            .insert(provider, self.fee_growth)
+            .unwrap_or(self.fee_growth);
+        if shares == 0 {
+            return;
+        }
+        let owed = |growth: u128, checkpoint: u128| {
+            let owed = shares
+                .checked_mul(growth - checkpoint)
+                .expect("Overflow in fee accounting")
+                / FEE_GROWTH_SCALE;
+            u64::try_from(owed).expect("Overflow in fee accounting")
+        };
+        let owed_a = owed(self.fee_growth.token_a, checkpoint.token_a);
+        let owed_b = owed(self.fee_growth.token_b, checkpoint.token_b);
+        if owed_a == 0 && owed_b == 0 {
+            return;
+        }
+        let accrued = self.accrued_fees.entry(provider).or_insert(UserBalance {
+            pool_a_balance: 0,
+            pool_b_balance: 0,
+        });
+        accrued.pool_a_balance += owed_a;
+        accrued.pool_b_balance += owed_b;
+    }
+
+    /// Retrieves the amount of `token` in the balance of `user` that is still locked at `now`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user to look up.
+    ///
+    /// * `token`: [`Token`] - The token to look up.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    /// # Returns
+    /// The locked amount of type [`u64`]
+    fn locked_amount(&self, user: Address, token: Token, now: i64) -> u64 {
+        self.output_locks
+            .get(&user)
+            .and_then(|locks| locks.get(&self.get_token_address_for(token)))
+            .filter(|lock| now < lock.unlock_at)
+            .map_or(0, |lock| lock.amount)
+    }
+
+    /// Retrieves the amount of `token` that `user` can move out of their balance at `now`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user to look up.
+    ///
+    /// * `token`: [`Token`] - The token to look up.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    /// # Returns
+    /// The unlocked balance of type [`u64`]
+    fn unlocked_balance(&self, user: Address, token: Token, now: i64) -> u64 {
+        let balance = self
+            .user_balances
+            .get(&user)
+            .map_or(0, |balance| balance.get_balance_for(token));
+        balance.saturating_sub(self.locked_amount(user, token, now))
+    }
+
+    /// Asserts that `amount` of `token` in the balance of `user` is not locked at `now`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user moving the tokens.
+    ///
+    /// * `token`: [`Token`] - The token being moved.
+    ///
+    /// * `amount`: [`u64`] - The amount being moved.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    fn assert_unlocked(&self, user: Address, token: Token, amount: u64, now: i64) {
+        self.check_unlocked(user, token, amount, now)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    /// Checks that `amount` of `token` in the balance of `user` is not locked at `now`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user moving the tokens.
+    ///
+    /// * `token`: [`Token`] - The token being moved.
+    ///
+    /// * `amount`: [`u64`] - The amount being moved.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    /// # Returns
+    /// The reason the amount cannot be moved, if it is locked, of type [`Result<(), String>`]
+    fn check_unlocked(
+        &self,
+        user: Address,
+        token: Token,
+        amount: u64,
+        now: i64,
+    ) -> Result<(), String> {
+        let locked = self.locked_amount(user, token, now);
+        if locked == 0 || amount <= self.unlocked_balance(user, token, now) {
+            return Ok(());
+        }
+        Err(format!(
+            "{} of the balance is locked until {}",
+            locked,
+            self.output_locks[&user][&self.get_token_address_for(token)].unlock_at
+        ))
+    }
+
+    /// Locks `amount` of `token` in the balance of `user` until `unlock_at`.
+    /// An active lock of the same token is extended to cover both amounts until the later unlock time.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user whose balance is locked.
+    ///
+    /// * `token`: [`Token`] - The token to lock.
+    ///
+    /// * `amount`: [`u64`] - The amount to lock.
+    ///
+    /// * `unlock_at`: [`i64`] - The block production time at which the amount unlocks.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    fn lock_output(&mut self, user: Address, token: Token, amount: u64, unlock_at: i64, now: i64) {
+        let still_locked = self.locked_amount(user, token, now);
+        let token_address = self.get_token_address_for(token);
+        let locks = self.output_locks.entry(user).or_default();
+        let unlock_at = match locks.get(&token_address) {
+            Some(lock) if still_locked > 0 => lock.unlock_at.max(unlock_at),
+            _ => unlock_at,
+        };
+        locks.insert(
+            token_address,
+            OutputLock {
+                amount: still_locked + amount,
+                unlock_at,
+            },
+        );
+    }
+
     /// Retrieves a copy of the pool that matches `token`.
     ///
     /// ### Parameters:
@@ -200,6 +742,36 @@ impl LiquiditySwapContractState {
         }
     }
 
+    /// Retrieves the address of the token contract matching `token`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token matching the desired pool.
+    ///
+    /// # Returns
+    /// The token contract address of type [`Address`]
+    fn get_token_address_for(&self, token: Token) -> Address {
+        match token {
+            Token::A => self.token_pool_a.token_address,
+            Token::B => self.token_pool_b.token_address,
+        }
+    }
+
+    /// Retrieves the minimum seed of the pool matching `token`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token matching the desired pool.
+    ///
+    /// # Returns
+    /// The minimum seed of type [`u64`]
+    fn get_min_seed_for(&self, token: Token) -> u64 {
+        match token {
+            Token::A => self.token_pool_a.min_seed,
+            Token::B => self.token_pool_b.min_seed,
+        }
+    }
+
     /// Retrieves a mutable reference to the pool that matches `token`.
     ///
     /// ### Parameters:
@@ -216,6 +788,24 @@ impl LiquiditySwapContractState {
         }
     }
 
+    /// Retrieves the pool token whose contract is `token_address`, if it is one of the pair.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token_address`: [`Address`] - The address of a token contract.
+    ///
+    /// # Returns
+    /// The matching token of type [`Option<Token>`]
+    fn pool_token_at(&self, token_address: Address) -> Option<Token> {
+        if token_address == self.token_pool_a.token_address {
+            Some(TOKEN_A)
+        } else if token_address == self.token_pool_b.token_address {
+            Some(TOKEN_B)
+        } else {
+            None
+        }
+    }
+
     /// Retrieves a pair of tokens with the `input_token_address` being the "from"-token
     /// and the remaining token being "to".
     /// Requires that `input_token_address` matches the contract's pools.
@@ -239,27 +829,619 @@ impl LiquiditySwapContractState {
             (TOKEN_B, TOKEN_A)
         }
     }
-}
 
-/// Initialize the contract.
-///
-/// # Parameters
-///
-///   * `context`: [`ContractContext`] - The contract context containing sender and chain information.
-///
-///   * `token_a_address`: [`Address`] - The address of token A.
-///
-///   * `token_b_address`: [`Address`] - The address of token B.
-///
-///
-/// The new state object of type [`LiquiditySwapContractState`] with all address fields initialized to their final state and remaining fields initialized to a default value.
-///
-#[init]
-pub fn initialize(
-    context: ContractContext,
-    token_a_address: Address,
-    token_b_address: Address,
+    /// Retrieves the factor between raw amounts of `token` and the common scale of both tokens.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token to get the scale of.
+    ///
+    /// # Returns
+    /// The scale of type [`u64`]
+    fn scale_for(&self, token: Token) -> u64 {
+        let common_decimals = self.token_a_decimals.min(self.token_b_decimals);
+        let decimals = match token {
+            Token::A => self.token_a_decimals,
+            Token::B => self.token_b_decimals,
+        };
+        10u64.pow(u32::from(decimals - common_decimals))
+    }
+
+    /// Converts a raw `amount` of `token` to the common scale, rounding down.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token of the amount.
+    ///
+    /// * `amount`: [`u64`] - The raw amount.
+    ///
+    /// # Returns
+    /// The normalized amount of type [`u64`]
+    fn normalize(&self, token: Token, amount: u64) -> u64 {
+        amount / self.scale_for(token)
+    }
+
+    /// Converts a normalized `amount` of `token` back to its raw precision.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token`: [`Token`] - The token of the amount.
+    ///
+    /// * `amount`: [`u64`] - The normalized amount.
+    ///
+    /// # Returns
+    /// The raw amount of type [`u64`]
+    fn denormalize(&self, token: Token, amount: u64) -> u64 {
+        amount * self.scale_for(token)
+    }
+
+    /// Recomputes `swap_constant` from the current pools on the common scale.
+    /// The product is taken in [`u128`], so pools of any [`u64`] size are supported.
+    fn update_swap_constant(&mut self) {
+        self.swap_constant = u128::from(self.normalize(TOKEN_A, self.token_pool_a.pool))
+            .checked_mul(u128::from(self.normalize(TOKEN_B, self.token_pool_b.pool)))
+            .expect("Overflow in swap constant - pools are too large");
+    }
+
+    /// Computes the liquidity shares minted for adding at most `amount_a` and `amount_b` to the pools,
+    /// and the amounts actually needed to keep the ratio of the pools.
+    /// The shares are rounded down and the needed amounts up, both in favour of the pool.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `amount_a`: [`u64`] - The largest amount of token A to add.
+    ///
+    /// * `amount_b`: [`u64`] - The largest amount of token B to add.
+    ///
+    /// # Returns
+    /// The minted shares and the needed amounts of token A and B, of type [`(u64, u64, u64)`]
+    fn compute_liquidity(&self, amount_a: u64, amount_b: u64) -> (u64, u64, u64) {
+        let total_shares = u128::from(self.total_shares);
+        let pool_a = u128::from(self.token_pool_a.pool);
+        let pool_b = u128::from(self.token_pool_b.pool);
+        let shares = (u128::from(amount_a) * total_shares / pool_a)
+            .min(u128::from(amount_b) * total_shares / pool_b);
+        let needed_a = u128_division_ceil(shares * pool_a, total_shares);
+        let needed_b = u128_division_ceil(shares * pool_b, total_shares);
+        // Each value is at most its u64 input
+        (shares as u64, needed_a as u64, needed_b as u64)
+    }
+
+    /// Asserts that the product of the pools on the common scale is at least `swap_constant`.
+    /// Swaps round in favour of the pool, so this only fails on an arithmetic error that would drain it.
+    fn assert_constant_product(&self) {
+        let product = u128::from(self.normalize(TOKEN_A, self.token_pool_a.pool))
+            * u128::from(self.normalize(TOKEN_B, self.token_pool_b.pool));
+        assert!(
+            product >= self.swap_constant,
+            "Pools {} and {} break the constant product invariant {}",
+            self.token_pool_a.pool,
+            self.token_pool_b.pool,
+            self.swap_constant
+        );
+    }
+
+    /// Computes the pools after `swapper` swaps `amount` of `token_from` to `token_to`.
+    /// The [`protocol_fee_for`](Self::protocol_fee_for) `amount` is kept out of the from-pool. The output is
+    /// priced on the rest less the [`swap_fee_for`](Self::swap_fee_for) the swapper. If `auto_compound` is
+    /// enabled the whole rest enters the from-pool, otherwise only the part the output was priced on.
+    /// The new to-pool is computed on the common scale in [`u128`] and denormalized, rounding in favour of the pool:
+    /// the part of the input below the common scale enters the from-pool without being priced, and the part of the
+    /// to-pool below the common scale stays in the to-pool. An input too small to buy a unit of the common scale
+    /// therefore has no output.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `swapper`: [`Option<Address>`] - The user making the swap, or `None` to price it without a rebate.
+    ///
+    /// * `token_from`: [`Token`] - The token being swapped from.
+    ///
+    /// * `token_to`: [`Token`] - The token being swapped to.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// # Returns
+    /// The new from-pool, the new to-pool and the output of the swap, of type [`(u64, u64, u64)`]
+    fn compute_swap(
+        &self,
+        swapper: Option<Address>,
+        token_from: Token,
+        token_to: Token,
+        amount: u64,
+    ) -> (u64, u64, u64) {
+        let from_pool_value = self.get_pool_for(token_from);
+        let pooled_amount = amount - self.protocol_fee_for(amount);
+        let (fee_numerator, fee_denominator) = self.swap_fee_for(swapper);
+        let effective_amount = (u128::from(pooled_amount) * (fee_denominator - fee_numerator)
+            / fee_denominator) as u64; // At most `amount`, so it fits in u64
+        let new_from_pool_value = from_pool_value
+            .checked_add(if self.auto_compound {
+                pooled_amount
+            } else {
+                effective_amount
+            })
+            .expect("Overflow in swap - pool would exceed u64::MAX");
+        let normalized_to_pool_value = u128_division_ceil(
+            self.swap_constant,
+            u128::from(self.normalize(token_from, from_pool_value + effective_amount)),
+        );
+        let to_pool_value = self.get_pool_for(token_to);
+        // The normalized to-pool only shrinks, so adding back its raw remainder stays within the pool
+        let new_to_pool_value = self.denormalize(
+            token_to,
+            u64::try_from(normalized_to_pool_value)
+                .expect("Overflow in swap - pool would exceed u64::MAX"),
+        ) + to_pool_value % self.scale_for(token_to);
+        let output = to_pool_value - new_to_pool_value;
+        (new_from_pool_value, new_to_pool_value, output)
+    }
+
+    /// Opens the contract if both pools have been initialized, setting the `swap_constant` and minting
+    /// the shares of the initial liquidity. [`LOCKED_LIQUIDITY_SHARES`] are credited to `contract_address`
+    /// and the rest to the owner.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `contract_address`: [`Address`] - The address of this contract.
+    fn open_if_initialized(&mut self, contract_address: Address) {
+        if self.token_pool_a.pool > 0u64 && self.token_pool_b.pool > 0u64 {
+            self.update_swap_constant();
+            self.is_closed = false;
+            self.total_shares = u128_sqrt(self.swap_constant) as u64;
+            self.lp_shares = BTreeMap::from([
+                (contract_address, LOCKED_LIQUIDITY_SHARES),
+                (
+                    self.contract_owner,
+                    self.total_shares - LOCKED_LIQUIDITY_SHARES,
+                ),
+            ]);
+            self.fee_checkpoints = BTreeMap::from([
+                (contract_address, self.fee_growth),
+                (self.contract_owner, self.fee_growth),
+            ]);
+        }
+    }
+
+    /// Computes the share of a swap input that `swapper` pays as the swap fee: the `fee_per_mille`, less a
+    /// rebate of the swapper's own share of the pool, `lp_shares / total_shares`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `swapper`: [`Option<Address>`] - The user making the swap, or `None` for no rebate.
+    ///
+    /// # Returns
+    /// The numerator and the non-zero denominator of the fee, of type [`(u128, u128)`]
+    fn swap_fee_for(&self, swapper: Option<Address>) -> (u128, u128) {
+        let fee = u128::from(self.fee_per_mille);
+        let shares = swapper
+            .and_then(|swapper| self.lp_shares.get(&swapper))
+            .copied()
+            .unwrap_or(0);
+        if shares == 0 || self.total_shares == 0 {
+            return (fee, u128::from(PER_MILLE));
+        }
+        let total_shares = u128::from(self.total_shares);
+        (
+            fee * (total_shares - u128::from(shares)),
+            u128::from(PER_MILLE) * total_shares,
+        )
+    }
+
+    /// Computes the slice of a swap input of `amount` that is routed to `protocol_fees`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// # Returns
+    /// The protocol fee of type [`u64`]
+    fn protocol_fee_for(&self, amount: u64) -> u64 {
+        let protocol_fee =
+            u128::from(amount) * u128::from(self.protocol_fee_per_mille) / u128::from(PER_MILLE);
+        protocol_fee as u64 // At most `amount`, so it fits in u64
+    }
+
+    /// Swaps `amount` of `token_from` in the balance of `user` to `token_to` at the exchange rate
+    /// dictated by `the constant product formula`, enforcing the configured swap guards.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user whose balances are swapped.
+    ///
+    /// * `token_from`: [`Token`] - The token being swapped from.
+    ///
+    /// * `token_to`: [`Token`] - The token being swapped to.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// # Returns
+    /// The output of the swap of type [`u64`]
+    fn execute_swap(
+        &mut self,
+        user: Address,
+        token_from: Token,
+        token_to: Token,
+        amount: u64,
+    ) -> u64 {
+        let (new_from_pool_value, new_to_pool_value, output) = self
+            .check_swap(user, token_from, token_to, amount)
+            .unwrap_or_else(|error| panic!("{}", error));
+
+        self.subtract_from_user_balance(user, token_from, amount);
+        self.add_to_user_balance(user, token_to, output);
+        let protocol_fee = self.protocol_fee_for(amount);
+        if protocol_fee > 0 {
+            *self
+                .protocol_fees
+                .entry(self.get_token_address_for(token_from))
+                .or_insert(0) += protocol_fee;
+        }
+        // The part of the input neither pooled nor paid as protocol fee is the providers' fee
+        let provider_fee =
+            amount - protocol_fee - (new_from_pool_value - self.get_pool_for(token_from));
+        if provider_fee > 0 {
+            self.accrue_fee(token_from, provider_fee);
+        }
+        *self.get_mut_pool_for(token_from) = new_from_pool_value; // Update from pool
+        *self.get_mut_pool_for(token_to) = new_to_pool_value; // Update to pool
+        self.assert_constant_product();
+        self.update_swap_constant(); // Keep the fee in the invariant
+
+        output
+    }
+
+    /// Computes the pools after `user` swaps `amount` of `token_from` to `token_to`, checking the
+    /// balance of the user and the configured swap guards. The state is unchanged.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user whose balances are swapped.
+    ///
+    /// * `token_from`: [`Token`] - The token being swapped from.
+    ///
+    /// * `token_to`: [`Token`] - The token being swapped to.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// # Returns
+    /// The new from-pool, the new to-pool and the output of the swap, or the reason the swap fails,
+    /// of type [`Result<(u64, u64, u64), String>`]
+    fn check_swap(
+        &self,
+        user: Address,
+        token_from: Token,
+        token_to: Token,
+        amount: u64,
+    ) -> Result<(u64, u64, u64), String> {
+        self.check_within_swap_limit(self.get_pool_for(token_from), amount)?;
+        let balance = self
+            .user_balances
+            .get(&user)
+            .map_or(0, |balance| balance.get_balance_for(token_from));
+        if balance < amount {
+            return Err("Insufficient funds".to_string());
+        }
+
+        let (new_from_pool_value, new_to_pool_value, output) =
+            self.compute_swap(Some(user), token_from, token_to, amount);
+        // An emptied pool leaves nothing to price future swaps against
+        if new_to_pool_value < 1 || output == self.get_pool_for(token_to) {
+            return Err("Swap too large".to_string());
+        }
+        if output == 0 {
+            return Err("Swap too small - the output is zero".to_string());
+        }
+        if output < self.min_output_floor {
+            return Err(format!(
+                "Swap output {} is below the minimum of {}",
+                output, self.min_output_floor
+            ));
+        }
+        self.check_within_pool_ratio(
+            self.normalize(token_from, new_from_pool_value),
+            self.normalize(token_to, new_to_pool_value),
+        )?;
+        Ok((new_from_pool_value, new_to_pool_value, output))
+    }
+
+    /// Simulates `user` calling `swap` with `amount` of the token at `input_token_address` at `now`,
+    /// through the same checks as the swap itself. The state is unchanged.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user swapping.
+    ///
+    /// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// * `now`: [`i64`] - The current block production time.
+    ///
+    /// # Returns
+    /// The outcome of the swap of type [`SwapSimulation`]
+    pub fn simulate_swap(
+        &self,
+        user: Address,
+        input_token_address: Address,
+        amount: u64,
+        now: i64,
+    ) -> SwapSimulation {
+        let (token_from, token_to) = self.deduce_from_to_tokens(input_token_address);
+        let result = self
+            .check_swap_allowed()
+            .and_then(|_| self.check_unlocked(user, token_from, amount, now))
+            .and_then(|_| self.check_swap(user, token_from, token_to, amount));
+        match result {
+            Ok((_, _, output)) => SwapSimulation {
+                output,
+                failure: None,
+            },
+            Err(failure) => SwapSimulation {
+                output: 0,
+                failure: Some(failure),
+            },
+        }
+    }
+
+    /// Checks that the contract accepts calls to `swap`.
+    ///
+    /// # Returns
+    /// The reason swaps are not accepted, if any, of type [`Result<(), String>`]
+    fn check_swap_allowed(&self) -> Result<(), String> {
+        if self.is_closed {
+            return Err("Cannot make a swap when the contract is closed".to_string());
+        }
+        if self.oracle.is_some() {
+            return Err(
+                "Swaps must be made through swap_with_oracle_check when an oracle is configured"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Computes the swap moving the share of pool A in the normalized reserves towards `target_bps`,
+    /// without moving past it. The input is rounded down, so the pools end at or before the target.
+    /// Panics if the target pool does not fit in a [`u64`].
+    ///
+    /// ### Parameters:
+    ///
+    /// * `target_bps`: [`u64`] - The target share of pool A, in basis points.
+    ///
+    /// # Returns
+    /// The from-token, the to-token and the input of the swap of type [`Option<(Token, Token, u64)>`],
+    /// or `None` if the pools are already at the target.
+    fn rebalance_swap(&self, target_bps: u64) -> Option<(Token, Token, u64)> {
+        // On the constant product curve a * b = k, the share t of pool A is reached at
+        // a = sqrt(k * t / (1 - t)) and b = sqrt(k * (1 - t) / t)
+        let swap_constant = self.swap_constant;
+        let target_bps = target_bps as u128;
+        let other_bps = MAX_BPS as u128 - target_bps;
+
+        let target_pool = |numerator: u128, denominator: u128| {
+            u128_mul_div(swap_constant, numerator, denominator)
+                .and_then(|square| u64::try_from(u128_sqrt(square)).ok())
+                .expect("Overflow in rebalance - target pool would exceed u64::MAX")
+        };
+
+        let pool_a = self.normalize(TOKEN_A, self.token_pool_a.pool);
+        let target_a = target_pool(target_bps, other_bps);
+        if target_a > pool_a {
+            return Some((
+                TOKEN_A,
+                TOKEN_B,
+                self.denormalize(TOKEN_A, target_a - pool_a),
+            ));
+        }
+
+        let pool_b = self.normalize(TOKEN_B, self.token_pool_b.pool);
+        let target_b = target_pool(other_bps, target_bps);
+        if target_b > pool_b {
+            return Some((
+                TOKEN_B,
+                TOKEN_A,
+                self.denormalize(TOKEN_B, target_b - pool_b),
+            ));
+        }
+        None
+    }
+
+    /// Asserts that swapping `amount` of `token_from` for `output` executes within
+    /// `max_oracle_deviation_bps` of `oracle_price`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `token_from`: [`Token`] - The token being swapped from.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// * `output`: [`u64`] - The output amount of the swap.
+    ///
+    /// * `oracle_price`: [`u64`] - The oracle price, scaled by [`ORACLE_PRICE_SCALE`].
+    fn assert_within_oracle_deviation(
+        &self,
+        token_from: Token,
+        amount: u64,
+        output: u64,
+        oracle_price: u64,
+    ) {
+        assert!(oracle_price > 0, "Oracle returned a zero price");
+        let (a_amount, b_amount) = match token_from {
+            Token::A => (amount, output),
+            Token::B => (output, amount),
+        };
+        let a_amount = self.normalize(TOKEN_A, a_amount);
+        let b_amount = self.normalize(TOKEN_B, b_amount);
+        assert!(a_amount > 0, "Swap output is zero");
+        let execution_price =
+            u128::from(b_amount) * u128::from(ORACLE_PRICE_SCALE) / u128::from(a_amount);
+        let oracle_price = u128::from(oracle_price);
+        let deviation_bps =
+            execution_price.abs_diff(oracle_price) * u128::from(MAX_BPS) / oracle_price;
+        assert!(
+            deviation_bps <= u128::from(self.max_oracle_deviation_bps),
+            "Swap price deviates {} basis points from the oracle price, exceeding the maximum of {}",
+            deviation_bps,
+            self.max_oracle_deviation_bps
+        );
+    }
+
+    /// Computes the largest amount `user` can swap from `input_token_address` in a single swap.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `user`: [`Address`] - The user swapping.
+    ///
+    /// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+    ///
+    /// # Returns
+    /// The smaller of the user's balance and the per-swap cap, of type [`u64`]
+    fn max_swap_for(&self, user: Address, input_token_address: Address) -> u64 {
+        let (token_from, _) = self.deduce_from_to_tokens(input_token_address);
+        let balance = self
+            .user_balances
+            .get(&user)
+            .map_or(0, |balance| balance.get_balance_for(token_from));
+        match self.max_swap_input(self.get_pool_for(token_from)) {
+            Some(max_swap_input) => balance.min(max_swap_input),
+            None => balance,
+        }
+    }
+
+    /// Computes the current exchange rate of the pools, i.e. the marginal price of a swap from
+    /// `input_token_address`, before fees and price impact.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+    ///
+    /// # Returns
+    /// The output-token units bought by one input-token unit, scaled by [`SPOT_PRICE_SCALE`], of type [`u64`]
+    pub fn spot_price(&self, input_token_address: Address) -> u64 {
+        assert!(!self.is_closed, "Pools not initialized");
+        let (token_from, token_to) = self.deduce_from_to_tokens(input_token_address);
+        let price = u128::from(self.get_pool_for(token_to)) * u128::from(SPOT_PRICE_SCALE)
+            / u128::from(self.get_pool_for(token_from));
+        u64::try_from(price).expect("Spot price exceeds u64::MAX")
+    }
+
+    /// Computes the output of swapping `amount` of the token at `input_token_address`, with the same
+    /// math and fees as `swap` by a swapper without `lp_shares`. Unlike [`simulate_swap`](Self::simulate_swap),
+    /// no user balance or swap guard is checked. The state is unchanged.
+    /// Panics if the contract is closed, like `swap`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    ///
+    /// # Returns
+    /// The output of the swap of type [`u64`]
+    pub fn quote_swap(&self, input_token_address: Address, amount: u64) -> u64 {
+        assert!(
+            !self.is_closed,
+            "Cannot make a swap when the contract is closed"
+        );
+        let (token_from, token_to) = self.deduce_from_to_tokens(input_token_address);
+        let (_, _, output) = self.compute_swap(None, token_from, token_to, amount);
+        output
+    }
+
+    /// Computes the largest input `max_swap_bps` allows into a pool of size `from_pool_value`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `from_pool_value`: [`u64`] - The size of the pool being swapped from.
+    ///
+    /// # Returns
+    /// The maximum input of type [`Option<u64>`], or `None` if the cap is disabled.
+    fn max_swap_input(&self, from_pool_value: u64) -> Option<u64> {
+        if self.max_swap_bps == 0 {
+            return None;
+        }
+        Some((from_pool_value as u128 * self.max_swap_bps as u128 / MAX_BPS as u128) as u64)
+    }
+
+    /// Checks that swapping `amount` into a pool of size `from_pool_value` is within `max_swap_bps`.
+    /// Always passes if the cap is disabled.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `from_pool_value`: [`u64`] - The size of the pool being swapped from.
+    ///
+    /// * `amount`: [`u64`] - The input amount of the swap.
+    /// # Returns
+    /// The reason the input is too large, if it is, of type [`Result<(), String>`]
+    fn check_within_swap_limit(&self, from_pool_value: u64, amount: u64) -> Result<(), String> {
+        match self.max_swap_input(from_pool_value) {
+            Some(max_swap_input) if amount > max_swap_input => Err(format!(
+                "Swap input {} exceeds the maximum of {}",
+                amount, max_swap_input
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that pools of normalized sizes `pool_x` and `pool_y` are within `max_pool_ratio` of each other.
+    /// Always passes if the guard is disabled.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `pool_x`: [`u64`] - The size of one pool.
+    ///
+    /// * `pool_y`: [`u64`] - The size of the other pool.
+    /// # Returns
+    /// The reason the pools are too imbalanced, if they are, of type [`Result<(), String>`]
+    fn check_within_pool_ratio(&self, pool_x: u64, pool_y: u64) -> Result<(), String> {
+        let larger = pool_x.max(pool_y) as u128;
+        let smaller = pool_x.min(pool_y) as u128;
+        if self.max_pool_ratio == 0 || larger <= smaller * self.max_pool_ratio as u128 {
+            return Ok(());
+        }
+        Err(format!(
+            "Swap would leave the pools more imbalanced than {}:1",
+            self.max_pool_ratio
+        ))
+    }
+}
+
+/// Initialize the contract.
+///
+/// # Parameters
+///
+///   * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///   * `token_a_address`: [`Address`] - The address of token A.
+///
+///   * `token_b_address`: [`Address`] - The address of token B.
+///
+///   * `max_pool_ratio`: [`u64`] - The largest ratio between the pools a swap may leave behind. Zero disables the guard.
+///
+///   * `min_pool_seed_a`: [`u64`] - The smallest amount of token A that may be provided to its pool at once.
+///
+///   * `min_pool_seed_b`: [`u64`] - The smallest amount of token B that may be provided to its pool at once.
+///
+///   * `fee_per_mille`: [`u64`] - The fee taken from the input of each swap, in per mille. Must be below `1000`.
+///
+///
+/// The new state object of type [`LiquiditySwapContractState`] with all address fields initialized to their final state and remaining fields initialized to a default value.
+///
+#[init]
+pub fn initialize(
+    context: ContractContext,
+    token_a_address: Address,
+    token_b_address: Address,
+    max_pool_ratio: u64,
+    min_pool_seed_a: u64,
+    min_pool_seed_b: u64,
+    fee_per_mille: u64,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        fee_per_mille < PER_MILLE,
+        "Swap fee must be below {} per mille",
+        PER_MILLE
+    );
     assert_eq!(
         token_a_address.address_type,
         AddressType::PublicContract,
@@ -280,21 +1462,112 @@ pub fn initialize(
         token_pool_a: TokenPool {
             token_address: token_a_address,
             pool: 0,
+            min_seed: min_pool_seed_a,
         },
         token_pool_b: TokenPool {
             token_address: token_b_address,
             pool: 0,
+            min_seed: min_pool_seed_b,
         },
         swap_constant: 0,
         user_balances: BTreeMap::new(),
         is_closed: true,
+        max_swap_bps: 0,
+        min_output_floor: 0,
+        max_pool_ratio,
+        close_authorities: BTreeSet::new(),
+        oracle: None,
+        max_oracle_deviation_bps: 0,
+        grace_withdrawal_period: 0,
+        withdraw_until: 0,
+        tokens_verified: false,
+        token_a_decimals: 0,
+        token_b_decimals: 0,
+        fee_per_mille,
+        retry_failed_withdrawals: false,
+        pending_withdrawals: BTreeMap::new(),
+        auto_withdraw_thresholds: BTreeMap::new(),
+        output_locks: BTreeMap::new(),
+        lp_shares: BTreeMap::new(),
+        total_shares: 0,
+        pending_deposits: BTreeMap::new(),
+        next_deposit_nonce: 0,
+        protocol_fee_per_mille: 0,
+        fee_recipient: context.sender,
+        protocol_fees: BTreeMap::new(),
+        routed_balances: BTreeMap::new(),
+        auto_compound: true,
+        fee_growth: FeeGrowth::default(),
+        fee_checkpoints: BTreeMap::new(),
+        accrued_fees: BTreeMap::new(),
     };
 
-    (new_state, vec![])
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(token_a_address, token_contract_owner())
+        .done();
+    event_group_builder
+        .call(token_b_address, token_contract_owner())
+        .done();
+    event_group_builder
+        .call(token_a_address, token_contract_decimals())
+        .done();
+    event_group_builder
+        .call(token_b_address, token_contract_decimals())
+        .done();
+    event_group_builder
+        .with_callback(SHORTNAME_VERIFY_TOKENS_CALLBACK)
+        .done();
+
+    (new_state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `initialize`.
+/// Reads the owners and `decimals` of token A and B from the return data of the queries and marks the tokens
+/// as verified if both are owned by the contract owner. Otherwise the callback fails.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the tokens verified.
+#[callback(shortname = 0x50)]
+pub fn verify_tokens_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(callback_context.success, "Token query failed");
+
+    let owner_a = Address::rpc_read_from(&mut callback_context.results[0].return_data.as_slice());
+    let owner_b = Address::rpc_read_from(&mut callback_context.results[1].return_data.as_slice());
+    assert_eq!(
+        owner_a, state.contract_owner,
+        "Token A is not owned by the contract owner"
+    );
+    assert_eq!(
+        owner_b, state.contract_owner,
+        "Token B is not owned by the contract owner"
+    );
+
+    state.token_a_decimals =
+        u8::rpc_read_from(&mut callback_context.results[2].return_data.as_slice());
+    state.token_b_decimals =
+        u8::rpc_read_from(&mut callback_context.results[3].return_data.as_slice());
+    state.tokens_verified = true;
+
+    (state, vec![])
 }
 
 /// Initialize pool {a, b} of the contract.
-/// This can only be done by the contract owner and the contract has to be in its closed state.
+/// This can only be done by the contract owner and the contract has to be in its closed state,
+/// after the grace-withdrawal window started by `close_pools` has passed.
 ///
 /// ### Parameters:
 ///
@@ -304,7 +1577,7 @@ pub fn initialize(
 ///
 ///  * `token_address`: [`Address`] - The address of the token {a, b}.
 ///
-///  * `pool_size`: [`u64`] - The desired size of token pool {a, b}.
+///  * `pool_size`: [`u64`] - The desired size of token pool {a, b}, at least the pool's `min_seed`.
 ///
 /// # Returns
 /// The unchanged state object of type [`LiquiditySwapContractState`].
@@ -315,16 +1588,9 @@ pub fn provide_liquidity(
     token_address: Address,
     pool_size: u64,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
-    assert_eq!(
-        context.sender, state.contract_owner,
-        "Only the contract owner can initialize its pools"
-    );
-    assert!(
-        state.is_closed,
-        "Can only initialize when the contract is closed"
-    );
-
     let (from_token, _) = state.deduce_from_to_tokens(token_address);
+    assert_can_provide_liquidity(&context, &state, from_token, pool_size);
+
     let mut event_group_builder = EventGroup::builder();
     event_group_builder
         .call(token_address, token_contract_transfer_from())
@@ -357,196 +1623,1946 @@ pub fn provide_liquidity(
 ///
 /// * `token`: [`Token`] - Indicating the token pool to initialize
 ///
-/// * `pool_size`: [`u64`] - The desired size of token pool {A, B}.
+/// * `pool_size`: [`u64`] - The desired size of token pool {A, B}.
+///
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`], with the corresponding pool initialized and the contract opened if meeting the requirements.
+#[callback(shortname = 0x10)]
+pub fn provide_liquidity_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    token: Token,
+    pool_size: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(callback_context.success, "Transfer did not succeed");
+
+    *state.get_mut_pool_for(token) += pool_size;
+    state.open_if_initialized(context.contract_address);
+
+    (state, vec![])
+}
+
+/// Initialize both pools of the contract in a single transaction.
+/// Both `transfer_from` calls are dispatched in one event group, and the pools are only initialized
+/// in `provide_liquidity_both_callback` if both of them succeed.
+/// This can only be done by the contract owner under the same conditions as `provide_liquidity`.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `amount_a`: [`u64`] - The desired size of token pool A, at least its `min_seed`.
+///
+///  * `amount_b`: [`u64`] - The desired size of token pool B, at least its `min_seed`.
+///
+/// # Returns
+/// The unchanged state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x1A)]
+pub fn provide_liquidity_both(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    amount_a: u64,
+    amount_b: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_can_provide_liquidity(&context, &state, TOKEN_A, amount_a);
+    assert_can_provide_liquidity(&context, &state, TOKEN_B, amount_b);
+
+    let mut event_group_builder = EventGroup::builder();
+    for (token, amount) in [(TOKEN_A, amount_a), (TOKEN_B, amount_b)] {
+        event_group_builder
+            .call(
+                state.get_token_address_for(token),
+                token_contract_transfer_from(),
+            )
+            .argument(context.sender)
+            .argument(context.contract_address)
+            .argument(amount)
+            .done();
+    }
+
+    event_group_builder
+        .with_callback(SHORTNAME_PROVIDE_LIQUIDITY_BOTH_CALLBACK)
+        .argument(amount_a)
+        .argument(amount_b)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `provide_liquidity_both`.
+/// If both transfers succeeded, both pools are initialized and the contract is opened.
+/// Otherwise the state is unchanged, and the transfer that did succeed, if any, is refunded to the owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `amount_a`: [`u64`] - The desired size of token pool A.
+///
+/// * `amount_b`: [`u64`] - The desired size of token pool B.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with both pools initialized, or
+/// the unchanged state and an event refunding a partial transfer.
+#[callback(shortname = 0x80)]
+pub fn provide_liquidity_both_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    amount_a: u64,
+    amount_b: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if callback_context.success {
+        state.token_pool_a.pool += amount_a;
+        state.token_pool_b.pool += amount_b;
+        state.open_if_initialized(context.contract_address);
+        return (state, vec![]);
+    }
+
+    let transfers = [(TOKEN_A, amount_a), (TOKEN_B, amount_b)];
+    let refunds: Vec<(Token, u64)> = transfers
+        .into_iter()
+        .zip(callback_context.results.iter())
+        .filter(|(_, result)| result.succeeded)
+        .map(|(transfer, _)| transfer)
+        .collect();
+    if refunds.is_empty() {
+        return (state, vec![]);
+    }
+
+    let mut event_group_builder = EventGroup::builder();
+    for (token, amount) in refunds {
+        event_group_builder
+            .call(
+                state.get_token_address_for(token),
+                token_contract_transfer(),
+            )
+            .argument(state.contract_owner)
+            .argument(amount)
+            .done();
+    }
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Deposit token A or B into the calling users balance on the contract.
+/// The deposit is recorded as pending under a fresh nonce until `deposit_callback` settles it.
+/// If the contract is closed, the action fails.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `token_address`: [`Address`] - The address of the deposited token contract.
+///
+///  * `amount`: [`u64`] - The amount to deposit.
+///
+/// # Returns
+/// The updated state object of type [`LiquiditySwapContractState`] with the pending deposit.
+#[action(shortname = 0x02)]
+pub fn deposit(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    token_address: Address,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot make a deposit when the contract is closed"
+    );
+
+    state.deduce_from_to_tokens(token_address);
+    let nonce = state.next_deposit_nonce;
+    state.next_deposit_nonce += 1;
+    state.pending_deposits.insert(
+        nonce,
+        DepositIntent {
+            user: context.sender,
+            token_address,
+            amount,
+        },
+    );
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(token_address, token_contract_transfer_from())
+        .argument(context.sender)
+        .argument(context.contract_address)
+        .argument(amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_DEPOSIT_CALLBACK)
+        .argument(nonce)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `deposit`.
+/// The pending deposit of `nonce` is cleared, and if the transfer event is successful its user is added to
+/// the `state.user_balances`, adding its amount to the balance of its token.
+/// The callback fails if no deposit is pending under `nonce`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `nonce`: [`u64`] - The nonce of the pending deposit.
+///
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with an updated entry for the caller of `deposit`.
+#[callback(shortname = 0x20)]
+pub fn deposit_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    nonce: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let intent = state
+        .pending_deposits
+        .remove(&nonce)
+        .expect("No pending deposit matches the callback");
+    if !callback_context.success {
+        return (state, vec![]);
+    }
+
+    let (token, _) = state.deduce_from_to_tokens(intent.token_address);
+    state.add_to_user_balance(intent.user, token, intent.amount);
+
+    (state, vec![])
+}
+
+/// Moves up to `amount_a` of token A and `amount_b` of token B from the calling user's balance into the pools,
+/// and recomputes `swap_constant` from the new pools.
+/// Only the amounts keeping the ratio of the pools are taken, the rest stays in the user's balance.
+/// The spot price is therefore unchanged, while the deeper pools let a swap of a given size move it less.
+/// The caller is minted `lp_shares` in proportion to the added liquidity.
+/// If the contract is closed, the caller does not have sufficient unlocked balances,
+/// or the amounts are too small to mint a share, the action fails.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `amount_a`: [`u64`] - The largest amount of token A to add.
+///
+///  * `amount_b`: [`u64`] - The largest amount of token B to add.
+///
+/// # Returns
+/// The updated state object of type [`LiquiditySwapContractState`] with deeper pools,
+/// and an event returning the minted shares.
+#[action(shortname = 0x14)]
+pub fn add_liquidity(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    amount_a: u64,
+    amount_b: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot add liquidity when the contract is closed"
+    );
+    assert!(
+        state.total_shares > 0,
+        "No liquidity shares have been minted"
+    );
+
+    let (shares, needed_a, needed_b) = state.compute_liquidity(amount_a, amount_b);
+    assert!(shares > 0, "Liquidity is too small to mint a share");
+
+    let now = context.block_production_time;
+    state.assert_unlocked(context.sender, TOKEN_A, needed_a, now);
+    state.assert_unlocked(context.sender, TOKEN_B, needed_b, now);
+    state.subtract_from_user_balance(context.sender, TOKEN_A, needed_a);
+    state.subtract_from_user_balance(context.sender, TOKEN_B, needed_b);
+
+    state.token_pool_a.pool += needed_a;
+    state.token_pool_b.pool += needed_b;
+    state.update_swap_constant();
+    state.settle_fees(context.sender);
+    *state.lp_shares.entry(context.sender).or_insert(0) += shares;
+    state.total_shares += shares;
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(shares);
+    (state, vec![event_group_builder.build()])
+}
+
+/// Burns `shares` of the calling user's `lp_shares` and moves the same share of both pools to the user's balance.
+/// The fees left in the pools are included in this share, and the fees accrued to the user while `auto_compound`
+/// was disabled are moved to the user's balance as well.
+/// The amounts are rounded down in favour of the remaining providers, and `swap_constant` is recomputed.
+/// Burning the last share empties the pools and closes the contract.
+/// If the contract is closed or the caller holds fewer than `shares`, the action fails.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `shares`: [`u64`] - The amount of shares to burn.
+///
+/// # Returns
+/// The updated state object of type [`LiquiditySwapContractState`] with shallower pools.
+#[action(shortname = 0x15)]
+pub fn remove_liquidity(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    shares: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot remove liquidity when the contract is closed"
+    );
+    let held_shares = state.lp_shares.get(&context.sender).copied().unwrap_or(0);
+    assert!(
+        shares <= held_shares,
+        "Cannot remove {} shares, only {} are held",
+        shares,
+        held_shares
+    );
+
+    let total_shares = u128::from(state.total_shares);
+    // Each share of a pool is at most the pool, so it fits in u64
+    let amount_a = (u128::from(state.token_pool_a.pool) * u128::from(shares) / total_shares) as u64;
+    let amount_b = (u128::from(state.token_pool_b.pool) * u128::from(shares) / total_shares) as u64;
+
+    state.settle_fees(context.sender);
+    if let Some(accrued) = state.accrued_fees.remove(&context.sender) {
+        state.add_to_user_balance(context.sender, TOKEN_A, accrued.pool_a_balance);
+        state.add_to_user_balance(context.sender, TOKEN_B, accrued.pool_b_balance);
+    }
+    if shares == held_shares {
+        state.lp_shares.remove(&context.sender);
+        state.fee_checkpoints.remove(&context.sender);
+    } else {
+        state.lp_shares.insert(context.sender, held_shares - shares);
+    }
+    state.total_shares -= shares;
+    state.token_pool_a.pool -= amount_a;
+    state.token_pool_b.pool -= amount_b;
+    state.add_to_user_balance(context.sender, TOKEN_A, amount_a);
+    state.add_to_user_balance(context.sender, TOKEN_B, amount_b);
+    state.update_swap_constant();
+    if state.total_shares == 0 {
+        state.is_closed = true;
+    }
+
+    (state, vec![])
+}
+
+/// Swap `amount` of token A or B to the opposite token at the exchange rate dictated by `the constant product formula`.
+/// The swap is executed on the user balances of tokens for the calling user.
+/// If the contract is closed or if the caller does not have a sufficient balance of the token, the action fails.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+///  * `amount`: [`u64`] - The amount to swap of the token matching `input_token`.
+///
+///  * `minimum_amount_out`: [`u64`] - The smallest output the caller accepts, protecting them from
+///    front-running. Zero accepts any output.
+///
+///  * `deadline_millis`: [`i64`] - The latest block production time at which the swap may execute,
+///    protecting the caller from a stale price. Zero disables the deadline.
+///
+///  * `lock_until`: [`i64`] - The block production time until which the output stays locked in the
+///    caller's balance. A time that has already passed leaves the output unlocked.
+///
+/// Locked tokens of the caller cannot be swapped.
+/// If the swap leaves the caller with more than their `auto_withdraw_threshold` of the output token,
+/// their whole balance of it is withdrawn in the same event group.
+///
+/// # Returns
+/// The updated state object of type [`LiquiditySwapContractState`] yielding the result of the swap,
+/// and an event returning the output of the swap.
+#[action(shortname = 0x03)]
+pub fn swap(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    input_token_address: Address,
+    amount: u64,
+    minimum_amount_out: u64,
+    deadline_millis: i64,
+    lock_until: i64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    state
+        .check_swap_allowed()
+        .unwrap_or_else(|error| panic!("{}", error));
+    assert!(
+        deadline_millis == 0 || context.block_production_time <= deadline_millis,
+        "Swap deadline {} has passed",
+        deadline_millis
+    );
+    let (token_from, token_to) = state.deduce_from_to_tokens(input_token_address);
+    let now = context.block_production_time;
+    state.assert_unlocked(context.sender, token_from, amount, now);
+    let output = state.execute_swap(context.sender, token_from, token_to, amount);
+    assert!(output >= minimum_amount_out, "Slippage exceeded");
+    if lock_until > now {
+        state.lock_output(context.sender, token_to, output, lock_until, now);
+    }
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(output);
+
+    let threshold = state
+        .auto_withdraw_thresholds
+        .get(&context.sender)
+        .copied()
+        .unwrap_or(0);
+    let balance = state.unlocked_balance(context.sender, token_to, now);
+    if threshold > 0 && balance > threshold {
+        state.subtract_from_user_balance(context.sender, token_to, balance);
+        add_withdraw_transfer(
+            &mut event_group_builder,
+            &state,
+            context.sender,
+            token_to,
+            balance,
+        );
+    }
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports whether a call to `swap` by the caller with `amount` of the token at `input_token_address`
+/// would succeed, and its output, as the return data of the call. The swap is checked by the same code
+/// path as `swap`, including the fee, the swap guards and locked balances. The state is unchanged.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+///  * `amount`: [`u64`] - The amount to swap of the token matching `input_token`.
+///
+/// # Returns
+/// The unchanged state object of type [`LiquiditySwapContractState`] and an event returning the [`SwapSimulation`].
+#[action(shortname = 0x16)]
+pub fn simulate_swap(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    input_token_address: Address,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let simulation = state.simulate_swap(
+        context.sender,
+        input_token_address,
+        amount,
+        context.block_production_time,
+    );
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(simulation);
+    (state, vec![event_group_builder.build()])
+}
+
+/// Withdraw `amount` of token A or B from the contract for the calling user.
+/// This fails if `amount` is larger than the unlocked user balance of the corresponding token.
+///
+/// It preemptively updates the state of the user's balance before making the transfer.
+/// The transfer is checked in `withdraw_callback`, which reclaims the amount of a failed transfer into the
+/// user's balance, or records it for `retry_withdraw` if `retry_failed_withdrawals` is enabled.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `token_address`: [`Address`] - The address of the token contract to withdraw to.
+///
+///  * `amount`: [`u64`] - The amount to withdraw.
+///
+/// # Returns
+/// The unchanged state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x04)]
+pub fn withdraw(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    token_address: Address,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let (token_from, _) = state.deduce_from_to_tokens(token_address);
+
+    state.assert_unlocked(
+        context.sender,
+        token_from,
+        amount,
+        context.block_production_time,
+    );
+    state.subtract_from_user_balance(context.sender, token_from, amount);
+
+    let mut event_group_builder = EventGroup::builder();
+    add_withdraw_transfer(
+        &mut event_group_builder,
+        &state,
+        context.sender,
+        token_from,
+        amount,
+    );
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `withdraw`, `retry_withdraw` and the auto-withdrawal of `swap`.
+/// If the transfer failed, the optimistically subtracted `amount` is returned to the `user_balances` of `user`.
+/// If `retry_failed_withdrawals` is enabled, it is instead added to the `pending_withdrawals` of `user`,
+/// so it can be retried.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user who withdrew.
+///
+/// * `token`: [`Token`] - The token that was withdrawn.
+///
+/// * `amount`: [`u64`] - The amount that was withdrawn.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with any failed withdrawal reclaimed or recorded.
+#[callback(shortname = 0x70)]
+pub fn withdraw_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    user: Address,
+    token: Token,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if callback_context.success {
+        return (state, vec![]);
+    }
+    if state.retry_failed_withdrawals {
+        state.add_to_pending_withdrawal(user, token, amount);
+    } else {
+        state.add_to_user_balance(user, token, amount);
+    }
+
+    (state, vec![])
+}
+
+/// Retries the failed withdrawals of `token_address` recorded for the calling user.
+/// The whole pending amount is transferred again, and recorded again if the transfer fails.
+/// Fails if nothing is pending for the token.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `token_address`: [`Address`] - The address of the token contract to retry the withdrawal of.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the pending withdrawal cleared.
+#[action(shortname = 0x12)]
+pub fn retry_withdraw(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    token_address: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let (token, _) = state.deduce_from_to_tokens(token_address);
+    let amount = state.take_pending_withdrawal(context.sender, token);
+    assert!(amount > 0, "No pending withdrawal to retry");
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(token_address, token_contract_transfer())
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_WITHDRAW_CALLBACK)
+        .argument(context.sender)
+        .argument(token)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Sets the balance above which the output of the caller's swaps is withdrawn automatically.
+/// A value of zero disables the auto-withdrawal.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `auto_withdraw_threshold`: [`u64`] - The new threshold of the caller.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x13)]
+pub fn set_auto_withdraw_threshold(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    auto_withdraw_threshold: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if auto_withdraw_threshold == 0 {
+        state.auto_withdraw_thresholds.remove(&context.sender);
+    } else {
+        state
+            .auto_withdraw_thresholds
+            .insert(context.sender, auto_withdraw_threshold);
+    }
+
+    (state, vec![])
+}
+
+/// Enables or disables recording failed withdrawal transfers for a retry.
+/// When disabled, failed withdrawals are reclaimed into the user's balance.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `retry_failed_withdrawals`: [`bool`] - Whether failed withdrawals should be recorded.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x11)]
+pub fn set_retry_failed_withdrawals(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    retry_failed_withdrawals: bool,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can configure withdrawal retries"
+    );
+
+    state.retry_failed_withdrawals = retry_failed_withdrawals;
+
+    (state, vec![])
+}
+
+/// Empties the pools into the balances of the liquidity providers and closes the contract.
+/// Each holder of `lp_shares` is credited `pool * shares / total_shares` of both pools, and the shares
+/// are burned, together with the fees accrued to the holder. The rest, the part of the locked shares held by the
+/// contract and the rounding dust, goes to the contract owner.
+/// Users are guaranteed `grace_withdrawal_period` to withdraw before the pools can be re-initialized.
+/// Fails if called by anyone but the contract owner or a member of `close_authorities`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x05)]
+pub fn close_pools(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        context.sender == state.contract_owner || state.close_authorities.contains(&context.sender),
+        "Only the contract owner or a close authority can close the pools"
+    );
+    assert!(!state.is_closed, "The contract is already closed");
+
+    let total_shares = u128::from(state.total_shares);
+    let holders: Vec<(Address, u64)> = state
+        .lp_shares
+        .iter()
+        .filter(|(holder, _)| **holder != context.contract_address)
+        .map(|(holder, shares)| (*holder, *shares))
+        .collect();
+    let providers: Vec<Address> = state.lp_shares.keys().copied().collect();
+    for provider in providers {
+        state.settle_fees(provider);
+    }
+    for (provider, accrued) in std::mem::take(&mut state.accrued_fees) {
+        let holder = if provider == context.contract_address {
+            state.contract_owner
+        } else {
+            provider
+        };
+        state.add_to_user_balance(holder, TOKEN_A, accrued.pool_a_balance);
+        state.add_to_user_balance(holder, TOKEN_B, accrued.pool_b_balance);
+    }
+    for token in [TOKEN_A, TOKEN_B] {
+        let pool = state.get_pool_for(token);
+        let mut remaining = pool;
+        for (holder, shares) in holders.iter() {
+            // At most `pool`, as `shares` is at most `total_shares`
+            let amount = (u128::from(pool) * u128::from(*shares) / total_shares) as u64;
+            state.add_to_user_balance(*holder, token, amount);
+            remaining -= amount;
+        }
+        state.add_to_user_balance(state.contract_owner, token, remaining);
+    }
+
+    // Close contract
+    state.token_pool_a.pool = 0;
+    state.token_pool_b.pool = 0;
+    state.lp_shares.clear();
+    state.fee_checkpoints.clear();
+    state.total_shares = 0;
+    state.is_closed = true;
+    state.withdraw_until = context.block_production_time + state.grace_withdrawal_period;
+
+    (state, vec![])
+}
+
+/// Sets the maximum input of a single swap, in basis points of the pool being swapped from.
+/// A value of zero disables the cap.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `max_swap_bps`: [`u64`] - The new cap, at most `10000`.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x06)]
+pub fn set_max_swap_bps(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    max_swap_bps: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set the swap limit"
+    );
+    assert!(
+        max_swap_bps <= MAX_BPS,
+        "Swap limit cannot exceed {} basis points",
+        MAX_BPS
+    );
+
+    state.max_swap_bps = max_swap_bps;
+
+    (state, vec![])
+}
+
+/// Lends `amount` of token A or B from the pool to `borrower_contract` against collateral of the caller.
+/// The tokens are transferred to `borrower_contract`, which is then invoked with `data`, after which
+/// `amount` plus a fee of [`LOAN_FEE_BPS`] is pulled back from it via `transfer_from`.
+/// The borrower must therefore approve this contract for the repayment before returning.
+///
+/// `amount` plus the fee is debited from the caller's balance of the token as collateral, and settled in
+/// `collateralized_loan_callback`. The pool itself is left untouched, so swaps made while the loan is in flight
+/// are priced on the full pool.
+///
+/// ### Parameters:
+///
+///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+///
+///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+///  * `token_address`: [`Address`] - The address of the token contract to borrow.
+///
+///  * `amount`: [`u64`] - The amount to borrow.
+///
+///  * `borrower_contract`: [`Address`] - The contract receiving the tokens and handling `data`.
+///
+///  * `data`: [`Vec<u8>`] - Arbitrary data forwarded to the borrower contract.
+///
+/// # Returns
+/// The updated state object of type [`LiquiditySwapContractState`] with the collateral debited.
+#[action(shortname = 0x07)]
+pub fn collateralized_loan(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    token_address: Address,
+    amount: u64,
+    borrower_contract: Address,
+    data: Vec<u8>,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot take a loan when the contract is closed"
+    );
+    assert_eq!(
+        borrower_contract.address_type,
+        AddressType::PublicContract,
+        "Loans can only be made to a public contract"
+    );
+    let (token, _) = state.deduce_from_to_tokens(token_address);
+    let pool_value = state.get_pool_for(token);
+    assert!(
+        amount > 0 && amount < pool_value,
+        "Loan amount must be positive and less than the pool"
+    );
+
+    let fee = u128_division_ceil(
+        u128::from(amount) * u128::from(LOAN_FEE_BPS),
+        u128::from(MAX_BPS),
+    ) as u64;
+    let collateral = amount
+        .checked_add(fee)
+        .expect("Overflow in loan - the collateral exceeds u64::MAX");
+    state.assert_unlocked(
+        context.sender,
+        token,
+        collateral,
+        context.block_production_time,
+    );
+    state.subtract_from_user_balance(context.sender, token, collateral);
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(token_address, token_contract_transfer())
+        .argument(borrower_contract)
+        .argument(amount)
+        .done();
+
+    event_group_builder
+        .call(borrower_contract, loan_receiver())
+        .argument(token_address)
+        .argument(amount)
+        .argument(fee)
+        .argument(data)
+        .done();
+
+    event_group_builder
+        .call(token_address, token_contract_transfer_from())
+        .argument(borrower_contract)
+        .argument(context.contract_address)
+        .argument(collateral)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_COLLATERALIZED_LOAN_CALLBACK)
+        .argument(context.sender)
+        .argument(token)
+        .argument(amount)
+        .argument(fee)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `collateralized_loan` and settles the collateral of `user`.
+/// If the tokens were lent, the pool is credited with the fee and the `swap_constant` grows accordingly.
+/// If the pools were closed while the loan was in flight, there is no pool to earn the fee, and it is
+/// returned to `user` instead.
+/// The collateral is returned to `user` together with the repayment, if any, less the lent amount and
+/// the fee. A repaid loan therefore returns the whole collateral, while an unpaid one forfeits it.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user who took out the loan.
+///
+/// * `token`: [`Token`] - Indicating the pool that was borrowed from.
+///
+/// * `amount`: [`u64`] - The borrowed amount.
+///
+/// * `fee`: [`u64`] - The fee paid on top of the borrowed amount.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the loan settled.
+#[callback(shortname = 0x30)]
+pub fn collateralized_loan_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    user: Address,
+    token: Token,
+    amount: u64,
+    fee: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let lent = callback_context.results[0].succeeded;
+    let repaid = callback_context.results[2].succeeded;
+
+    let collateral = amount + fee;
+    let mut returned = match (lent, repaid) {
+        (true, true) | (false, false) => collateral,
+        (true, false) => 0,
+        (false, true) => collateral
+            .checked_mul(2)
+            .expect("Overflow in loan - the returned collateral exceeds u64::MAX"),
+    };
+    if lent && state.is_closed {
+        returned += fee;
+    } else if lent {
+        *state.get_mut_pool_for(token) += fee;
+        state.update_swap_constant();
+    }
+    state.add_to_user_balance(user, token, returned);
+
+    (state, vec![])
+}
+
+/// Sets the smallest output a swap may produce, protecting users from swaps whose output
+/// rounds down to dust. A value of zero disables the floor.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `min_output_floor`: [`u64`] - The new output floor.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x08)]
+pub fn set_min_output_floor(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    min_output_floor: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set the output floor"
+    );
+
+    state.min_output_floor = min_output_floor;
+
+    (state, vec![])
+}
+
+/// Allows `authority` to close the pools.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `authority`: [`Address`] - The address to add to `close_authorities`.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x09)]
+pub fn add_close_authority(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    authority: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can add close authorities"
+    );
+    assert!(
+        state.close_authorities.insert(authority),
+        "Address is already a close authority"
+    );
+
+    (state, vec![])
+}
+
+/// Revokes the permission of `authority` to close the pools.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `authority`: [`Address`] - The address to remove from `close_authorities`.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x0A)]
+pub fn remove_close_authority(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    authority: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can remove close authorities"
+    );
+    assert!(
+        state.close_authorities.remove(&authority),
+        "Address is not a close authority"
+    );
+
+    (state, vec![])
+}
+
+/// Sets the oracle queried by `swap_with_oracle_check` and the largest deviation allowed from its price.
+/// While an oracle is configured, plain `swap` is disabled. Passing `None` disables the check.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `oracle`: [`Option<Address>`] - The oracle contract, or `None`.
+///
+/// * `max_oracle_deviation_bps`: [`u64`] - The largest allowed deviation in basis points.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x0B)]
+pub fn set_oracle(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    oracle: Option<Address>,
+    max_oracle_deviation_bps: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set the oracle"
+    );
+    if let Some(oracle) = oracle {
+        assert_eq!(
+            oracle.address_type,
+            AddressType::PublicContract,
+            "The oracle must be a public contract"
+        );
+    }
+
+    state.oracle = oracle;
+    state.max_oracle_deviation_bps = max_oracle_deviation_bps;
+
+    (state, vec![])
+}
+
+/// Swaps `amount` of `input_token_address` like `swap`, but only after querying the configured
+/// `oracle` for a reference price. The swap is executed in `oracle_swap_callback`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+/// * `amount`: [`u64`] - The amount to swap of the token matching `input_token_address`.
+///
+/// * `minimum_amount_out`: [`u64`] - The smallest output the caller accepts. Zero accepts any output.
+///
+/// * `deadline_millis`: [`i64`] - The latest block production time at which the swap may execute,
+///   checked again when the oracle answers. Zero disables the deadline.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x0C)]
+pub fn swap_with_oracle_check(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    input_token_address: Address,
+    amount: u64,
+    minimum_amount_out: u64,
+    deadline_millis: i64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot make a swap when the contract is closed"
+    );
+    assert!(
+        deadline_millis == 0 || context.block_production_time <= deadline_millis,
+        "Swap deadline {} has passed",
+        deadline_millis
+    );
+    let oracle = state.oracle.expect("No oracle is configured");
+    let (token_from, _) = state.deduce_from_to_tokens(input_token_address);
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.call(oracle, oracle_get_price()).done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_ORACLE_SWAP_CALLBACK)
+        .argument(context.sender)
+        .argument(token_from)
+        .argument(amount)
+        .argument(minimum_amount_out)
+        .argument(deadline_millis)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `swap_with_oracle_check`.
+/// Reads the oracle price from the return data of the query and executes the swap for `user`,
+/// failing if the execution price deviates more than `max_oracle_deviation_bps` from it, if the
+/// output is below `minimum_amount_out` or if `deadline_millis` has passed.
+/// Locked tokens of `user` cannot be swapped, as in `swap`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user who requested the swap.
+///
+/// * `token_from`: [`Token`] - The token being swapped from.
+///
+/// * `amount`: [`u64`] - The input amount of the swap.
+///
+/// * `minimum_amount_out`: [`u64`] - The smallest output the user accepts.
+///
+/// * `deadline_millis`: [`i64`] - The latest block production time at which the swap may execute.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the swap applied.
+#[callback(shortname = 0x40)]
+pub fn oracle_swap_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    user: Address,
+    token_from: Token,
+    amount: u64,
+    minimum_amount_out: u64,
+    deadline_millis: i64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(callback_context.success, "Oracle price query failed");
+    assert!(
+        !state.is_closed,
+        "Cannot make a swap when the contract is closed"
+    );
+    assert!(
+        deadline_millis == 0 || context.block_production_time <= deadline_millis,
+        "Swap deadline {} has passed",
+        deadline_millis
+    );
+    let oracle_price = read_oracle_price(&callback_context.results[0].return_data);
+    let token_to = match token_from {
+        Token::A => TOKEN_B,
+        Token::B => TOKEN_A,
+    };
+    state.assert_unlocked(user, token_from, amount, context.block_production_time);
+
+    let (_, _, output) = state.compute_swap(Some(user), token_from, token_to, amount);
+    state.assert_within_oracle_deviation(token_from, amount, output, oracle_price);
+    let output = state.execute_swap(user, token_from, token_to, amount);
+    assert!(output >= minimum_amount_out, "Slippage exceeded");
+
+    (state, vec![])
+}
+
+/// Sets the length of the grace-withdrawal window started when the pools are closed.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `grace_withdrawal_period`: [`i64`] - The new window length in milliseconds.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x0D)]
+pub fn set_grace_withdrawal_period(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    grace_withdrawal_period: i64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set the withdrawal window"
+    );
+    assert!(
+        grace_withdrawal_period >= 0,
+        "The withdrawal window cannot be negative"
+    );
+
+    state.grace_withdrawal_period = grace_withdrawal_period;
+
+    (state, vec![])
+}
+
+/// Reports the largest amount `user` can swap from `input_token_address` in one go: their deposited
+/// balance, bounded by the per-swap cap of `max_swap_bps`. The amount is the return data of the call,
+/// and the state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user to report for.
+///
+/// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`LiquiditySwapContractState`] and an event returning the amount.
+#[action(shortname = 0x0E)]
+pub fn query_max_swap(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    user: Address,
+    input_token_address: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let max_swap = state.max_swap_for(user, input_token_address);
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(max_swap);
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports the [`spot_price`](LiquiditySwapContractState::spot_price) of swapping from `input_token_address`
+/// as the return data of the call. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`LiquiditySwapContractState`] and an event returning the price.
+#[action(shortname = 0x17)]
+pub fn query_spot_price(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    input_token_address: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let spot_price = state.spot_price(input_token_address);
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(spot_price);
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports the [`quote_swap`](LiquiditySwapContractState::quote_swap) output of swapping `amount` of
+/// `input_token_address` as the return data of the call. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+/// * `amount`: [`u64`] - The input amount of the swap.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`LiquiditySwapContractState`] and an event returning the output.
+#[action(shortname = 0x1B)]
+pub fn quote_swap(
+    context: ContractContext,
+    state: LiquiditySwapContractState,
+    input_token_address: Address,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let output = state.quote_swap(input_token_address, amount);
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(output);
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Swaps `amount` of `input_token_address` like `swap`, then routes the output through the sibling
+/// liquidity-swap contract `next_contract`, swapping it there for `next_output_token`.
+/// The output of the first swap leaves the caller's balance and is approved for `next_contract`.
+/// The route continues in `swap_through_approve_callback`, each step waiting for the previous one.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+///
+/// * `amount`: [`u64`] - The amount to swap of the token matching `input_token_address`.
+///
+/// * `next_contract`: [`Address`] - The liquidity-swap contract performing the next hop.
+///
+/// * `next_output_token`: [`Address`] - The token the next hop swaps to.
+///
+/// * `minimum_amount_out`: [`u64`] - The smallest final output the caller accepts, enforced by the
+///   next hop. Zero accepts any output.
+///
+/// * `deadline_millis`: [`i64`] - The latest block production time at which either hop may execute.
+///   Zero disables the deadline.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the first hop applied.
+#[action(shortname = 0x0F)]
+pub fn swap_through(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    input_token_address: Address,
+    amount: u64,
+    next_contract: Address,
+    next_output_token: Address,
+    minimum_amount_out: u64,
+    deadline_millis: i64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert!(
+        !state.is_closed,
+        "Cannot make a swap when the contract is closed"
+    );
+    assert!(
+        deadline_millis == 0 || context.block_production_time <= deadline_millis,
+        "Swap deadline {} has passed",
+        deadline_millis
+    );
+    assert!(
+        state.oracle.is_none(),
+        "Swaps must be made through swap_with_oracle_check when an oracle is configured"
+    );
+    assert!(
+        next_contract.address_type == AddressType::PublicContract
+            && next_contract != context.contract_address,
+        "The next hop must be another public contract"
+    );
+    let (token_from, token_to) = state.deduce_from_to_tokens(input_token_address);
+    let hop_token = state.get_token_address_for(token_to);
+    assert_ne!(
+        next_output_token, hop_token,
+        "The next hop must swap to a different token"
+    );
+
+    state.assert_unlocked(
+        context.sender,
+        token_from,
+        amount,
+        context.block_production_time,
+    );
+    let output = state.execute_swap(context.sender, token_from, token_to, amount);
+    state.subtract_from_user_balance(context.sender, token_to, output);
+
+    let route = SwapRoute {
+        user: context.sender,
+        hop_token: token_to,
+        hop_amount: output,
+        next_contract,
+        next_output_token,
+        minimum_amount_out,
+        deadline_millis,
+    };
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(hop_token, token_contract_approve())
+        .argument(next_contract)
+        .argument(output)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_SWAP_THROUGH_APPROVE_CALLBACK)
+        .argument(route)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from the approval of `swap_through`.
+/// If the approval succeeded, the output of the first hop is deposited into the next contract.
+/// Otherwise it is credited back to the balance of the user.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `route`: [`SwapRoute`] - The route being executed.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] and the event depositing into the next hop.
+#[callback(shortname = 0xA0)]
+pub fn swap_through_approve_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    route: SwapRoute,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if !callback_context.success {
+        state.add_to_user_balance(route.user, route.hop_token, route.hop_amount);
+        return (state, vec![]);
+    }
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(route.next_contract, swap_contract_deposit())
+        .argument(state.get_token_address_for(route.hop_token))
+        .argument(route.hop_amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_SWAP_THROUGH_DEPOSIT_CALLBACK)
+        .argument(route)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from the deposit into the next hop.
+/// If the deposit succeeded, the deposited output of the first hop is swapped on the next contract.
+/// Otherwise it is credited back to the balance of the user.
+/// A deposit whose transfer failed on the next contract makes the swap fail, which is handled by
+/// `swap_through_callback`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `route`: [`SwapRoute`] - The route being executed.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] and the event swapping on the next hop.
+#[callback(shortname = 0xB0)]
+pub fn swap_through_deposit_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    route: SwapRoute,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if !callback_context.success {
+        state.add_to_user_balance(route.user, route.hop_token, route.hop_amount);
+        return (state, vec![]);
+    }
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(route.next_contract, swap_contract_swap())
+        .argument(state.get_token_address_for(route.hop_token))
+        .argument(route.hop_amount)
+        .argument(route.minimum_amount_out)
+        .argument(route.deadline_millis)
+        .argument(0i64)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_SWAP_THROUGH_CALLBACK)
+        .argument(route)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from the swap on the next hop.
+/// If the swap succeeded, its output is withdrawn from the next contract. Otherwise the deposit
+/// is withdrawn instead. The payout continues in `swap_through_withdraw_callback`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `route`: [`SwapRoute`] - The route being executed.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`LiquiditySwapContractState`] and the event withdrawing from the next hop.
+#[callback(shortname = 0x60)]
+pub fn swap_through_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    state: LiquiditySwapContractState,
+    route: SwapRoute,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let (payout_token, payout_amount) = if callback_context.success {
+        let final_output =
+            u64::rpc_read_from(&mut callback_context.results[0].return_data.as_slice());
+        (route.next_output_token, final_output)
+    } else {
+        (
+            state.get_token_address_for(route.hop_token),
+            route.hop_amount,
+        )
+    };
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(route.next_contract, swap_contract_withdraw())
+        .argument(payout_token)
+        .argument(payout_amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_SWAP_THROUGH_WITHDRAW_CALLBACK)
+        .argument(route)
+        .argument(payout_token)
+        .argument(payout_amount)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from the withdrawal from the next hop.
+/// If the withdrawal succeeded, the payout is transferred to the user, which is checked by
+/// `swap_through_payout_callback`. If the deposit of the first hop could not be withdrawn, it never
+/// reached the next contract, and is credited back to the balance of the user. If the output of a
+/// successful next hop could not be withdrawn, it stays deposited for this contract on the next contract.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `route`: [`SwapRoute`] - The route being executed.
+///
+/// * `payout_token`: [`Address`] - The token contract of the payout.
+///
+/// * `payout_amount`: [`u64`] - The amount withdrawn.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] and the event paying out the user.
+#[callback(shortname = 0xC0)]
+pub fn swap_through_withdraw_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    route: SwapRoute,
+    payout_token: Address,
+    payout_amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if !callback_context.success {
+        if payout_token == state.get_token_address_for(route.hop_token) {
+            state.add_to_user_balance(route.user, route.hop_token, route.hop_amount);
+        }
+        return (state, vec![]);
+    }
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(payout_token, token_contract_transfer())
+        .argument(route.user)
+        .argument(payout_amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_SWAP_THROUGH_PAYOUT_CALLBACK)
+        .argument(route.user)
+        .argument(payout_token)
+        .argument(payout_amount)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from the payout of `swap_through_withdraw_callback`.
+/// If the transfer to `user` failed, the payout is credited to the balance of `user` if it is one of
+/// the tokens of this contract, and to the `routed_balances` of `user` otherwise, to be withdrawn later.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user who requested the swap.
+///
+/// * `payout_token`: [`Address`] - The token contract of the payout.
+///
+/// * `payout_amount`: [`u64`] - The amount paid out.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with any failed payout credited.
+#[callback(shortname = 0x90)]
+pub fn swap_through_payout_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    user: Address,
+    payout_token: Address,
+    payout_amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if callback_context.success {
+        return (state, vec![]);
+    }
+    match state.pool_token_at(payout_token) {
+        Some(token) => state.add_to_user_balance(user, token, payout_amount),
+        None => state.add_to_routed_balance(user, payout_token, payout_amount),
+    }
+
+    (state, vec![])
+}
+
+/// Transfers the whole `routed_balances` of `token_address` recorded for the calling user.
+/// A failed transfer is recorded again by `withdraw_routed_callback`.
+/// Fails if nothing is recorded for the token.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `token_address`: [`Address`] - The address of the token contract to withdraw.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with the routed balance cleared.
+#[action(shortname = 0x1C)]
+pub fn withdraw_routed(
+    context: ContractContext,
+    mut state: LiquiditySwapContractState,
+    token_address: Address,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    let amount = state.take_routed_balance(context.sender, token_address);
+    assert!(amount > 0, "No routed balance to withdraw");
+
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder
+        .call(token_address, token_contract_transfer())
+        .argument(context.sender)
+        .argument(amount)
+        .done();
+
+    event_group_builder
+        .with_callback(SHORTNAME_WITHDRAW_ROUTED_CALLBACK)
+        .argument(context.sender)
+        .argument(token_address)
+        .argument(amount)
+        .done();
+
+    (state, vec![event_group_builder.build()])
+}
+
+/// Handles callback from `withdraw_routed`.
+/// If the transfer failed, the amount is recorded in the `routed_balances` of `user` again.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The contractContext for the callback.
+///
+/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user who withdrew.
+///
+/// * `token_address`: [`Address`] - The token contract that was withdrawn.
+///
+/// * `amount`: [`u64`] - The amount that was withdrawn.
+///
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`] with any failed withdrawal recorded.
+#[callback(shortname = 0xD0)]
+pub fn withdraw_routed_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: LiquiditySwapContractState,
+    user: Address,
+    token_address: Address,
+    amount: u64,
+) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    if !callback_context.success {
+        state.add_to_routed_balance(user, token_address, amount);
+    }
+
+    (state, vec![])
+}
+
+/// Swaps from the owner's deposited balance to move the pools towards holding `target_bps` of their
+/// normalized reserves in pool A, e.g. `8000` for an 80/20 pool. The swap is subject to the same guards
+/// as any other swap, and never moves the pools past the target. Nothing happens if the pools are
+/// already at the target.
+/// Fails if called by anyone but the contract owner.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`] - The context for the action call.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
+/// * `target_bps`: [`u64`] - The target share of pool A, strictly between 0 and [`MAX_BPS`].
 ///
 /// ### Returns
 ///
-/// The updated state object of type [`LiquiditySwapContractState`], with the corresponding pool initialized and the contract opened if meeting the requirements.
-#[callback(shortname = 0x10)]
-pub fn provide_liquidity_callback(
+/// The updated state object of type [`LiquiditySwapContractState`] with the rebalanced pools.
+#[action(shortname = 0x10)]
+pub fn rebalance(
     context: ContractContext,
-    callback_context: CallbackContext,
     mut state: LiquiditySwapContractState,
-    token: Token,
-    pool_size: u64,
+    target_bps: u64,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
-    assert!(callback_context.success, "Transfer did not succeed");
-
-    *state.get_mut_pool_for(token) += pool_size;
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can rebalance the pools"
+    );
+    assert!(
+        !state.is_closed,
+        "Cannot make a swap when the contract is closed"
+    );
+    assert!(
+        target_bps > 0 && target_bps < MAX_BPS,
+        "The target ratio must be strictly between 0 and {} basis points",
+        MAX_BPS
+    );
 
-    // Check if both pools has been initialized. If so, open the contract and set the contract constant.
-    if state.token_pool_a.pool > 0u64 && state.token_pool_b.pool > 0u64 {
-        state.swap_constant = state.token_pool_a.pool * state.token_pool_b.pool;
-        state.is_closed = false;
+    if let Some((token_from, token_to, amount)) = state.rebalance_swap(target_bps) {
+        state.execute_swap(context.sender, token_from, token_to, amount);
     }
 
     (state, vec![])
 }
 
-/// Deposit token A or B into the calling users balance on the contract.
-/// If the contract is closed, the action fails.
+/// Sets the slice of each swap input routed to `protocol_fees`, and the address that may collect them.
+/// Fails if called by anyone but the contract owner, or if `protocol_fee_per_mille` is not below `1000`.
 ///
 /// ### Parameters:
 ///
-///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+/// * `context`: [`ContractContext`] - The context for the action call.
 ///
-///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
-///  * `token_address`: [`Address`] - The address of the deposited token contract.
+/// * `protocol_fee_per_mille`: [`u64`] - The protocol fee, in per mille.
 ///
-///  * `amount`: [`u64`] - The amount to deposit.
+/// * `fee_recipient`: [`Address`] - The address allowed to collect the protocol fees.
 ///
-/// # Returns
-/// The unchanged state object of type [`LiquiditySwapContractState`].
-#[action(shortname = 0x02)]
-pub fn deposit(
+/// ### Returns
+///
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x18)]
+pub fn set_protocol_fee(
     context: ContractContext,
-    state: LiquiditySwapContractState,
-    token_address: Address,
-    amount: u64,
+    mut state: LiquiditySwapContractState,
+    protocol_fee_per_mille: u64,
+    fee_recipient: Address,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set the protocol fee"
+    );
     assert!(
-        !state.is_closed,
-        "Cannot make a deposit when the contract is closed"
+        protocol_fee_per_mille < PER_MILLE,
+        "Protocol fee must be below {} per mille",
+        PER_MILLE
     );
 
-    let (from_token, _) = state.deduce_from_to_tokens(token_address);
-    let mut event_group_builder = EventGroup::builder();
-    event_group_builder
-        .call(token_address, token_contract_transfer_from())
-        .argument(context.sender)
-        .argument(context.contract_address)
-        .argument(amount)
-        .done();
-
-    event_group_builder
-        .with_callback(SHORTNAME_DEPOSIT_CALLBACK)
-        .argument(from_token)
-        .argument(amount)
-        .done();
+    state.protocol_fee_per_mille = protocol_fee_per_mille;
+    state.fee_recipient = fee_recipient;
 
-    (state, vec![event_group_builder.build()])
+    (state, vec![])
 }
 
-/// Handles callback from `deposit`.
-/// If the transfer event is successful the caller of `deposit` is added to the `state.user_balances`
-/// adding `amount` to the `token` pool balance.
+/// Sets whether the liquidity providers' part of each swap fee is left in the pools, compounding it automatically,
+/// or kept out of the pools and accrued to the providers, to be realized with `compound`.
+/// Fails if called by anyone but the contract owner.
 ///
 /// ### Parameters:
 ///
-/// * `context`: [`ContractContext`] - The contractContext for the callback.
-///
-/// * `callback_context`: [`CallbackContext`] - The callbackContext.
+/// * `context`: [`ContractContext`] - The context for the action call.
 ///
 /// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
-/// * `token`: [`Token`] - Indicating the token pool balance of which to add `amount` to.
-/// * `amount`: [`u64`] - The desired amount to add to `token_type` pool balance.
-///
+/// * `auto_compound`: [`bool`] - Whether the fees are left in the pools.
 ///
 /// ### Returns
 ///
-/// The updated state object of type [`LiquiditySwapContractState`] with an updated entry for the caller of `deposit`.
-#[callback(shortname = 0x20)]
-pub fn deposit_callback(
+/// The updated state object of type [`LiquiditySwapContractState`].
+#[action(shortname = 0x1D)]
+pub fn set_auto_compound(
     context: ContractContext,
-    callback_context: CallbackContext,
     mut state: LiquiditySwapContractState,
-    token: Token,
-    amount: u64,
+    auto_compound: bool,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
-    assert!(callback_context.success, "Transfer did not succeed");
+    assert_eq!(
+        context.sender, state.contract_owner,
+        "Only the contract owner can set auto-compounding"
+    );
 
-    state.add_to_user_balance(context.sender, token, amount);
+    state.auto_compound = auto_compound;
 
     (state, vec![])
 }
 
-/// Swap `amount` of token A or B to the opposite token at the exchange rate dictated by `the constant product formula`.
-/// The swap is executed on the user balances of tokens for the calling user.
-/// If the contract is closed or if the caller does not have a sufficient balance of the token, the action fails.
+/// Realizes the fees accrued to the calling liquidity provider into additional `lp_shares`, without withdrawing.
+/// The accrued fees are added to the pools at their current ratio, like `add_liquidity`, and the matching shares
+/// are minted. The part of the fees that does not keep the ratio stays accrued.
+/// If the contract is closed or the accrued fees are too small to mint a share, the action fails.
 ///
 /// ### Parameters:
 ///
-///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
-///
-///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+/// * `context`: [`ContractContext`] - The context for the action call.
 ///
-///  * `input_token_address`: [`Address`] - The address of the token contract being swapped from.
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
-///  * `amount`: [`u64`] - The amount to swap of the token matching `input_token`.
+/// ### Returns
 ///
-/// # Returns
-/// The updated state object of type [`LiquiditySwapContractState`] yielding the result of the swap.
-#[action(shortname = 0x03)]
-pub fn swap(
+/// The updated state object of type [`LiquiditySwapContractState`] with deeper pools,
+/// and an event returning the minted shares.
+#[action(shortname = 0x1E)]
+pub fn compound(
     context: ContractContext,
     mut state: LiquiditySwapContractState,
-    input_token_address: Address,
-    amount: u64,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
     assert!(
         !state.is_closed,
-        "Cannot make a swap when the contract is closed"
+        "Cannot compound when the contract is closed"
     );
-    let (token_from, token_to) = state.deduce_from_to_tokens(input_token_address);
-    let from_pool_value = state.get_pool_for(token_from);
-    let to_pool_value = state.get_pool_for(token_to);
 
-    state.subtract_from_user_balance(context.sender, token_from, amount);
-    let new_from_pool_value = from_pool_value + amount;
-    let new_to_pool_value = u64_division_ceil(state.swap_constant, new_from_pool_value);
+    state.settle_fees(context.sender);
+    let accrued = state
+        .accrued_fees
+        .get(&context.sender)
+        .cloned()
+        .unwrap_or(UserBalance {
+            pool_a_balance: 0,
+            pool_b_balance: 0,
+        });
+    let (shares, needed_a, needed_b) =
+        state.compute_liquidity(accrued.pool_a_balance, accrued.pool_b_balance);
+    assert!(shares > 0, "Accrued fees are too small to mint a share");
 
-    state.add_to_user_balance(context.sender, token_to, to_pool_value - new_to_pool_value);
-    *state.get_mut_pool_for(token_from) = new_from_pool_value; // Update from pool
-    *state.get_mut_pool_for(token_to) = new_to_pool_value; // Update to pool
+    let remaining = UserBalance {
+        pool_a_balance: accrued.pool_a_balance - needed_a,
+        pool_b_balance: accrued.pool_b_balance - needed_b,
+    };
+    if remaining.pool_a_balance == 0 && remaining.pool_b_balance == 0 {
+        state.accrued_fees.remove(&context.sender);
+    } else {
+        state.accrued_fees.insert(context.sender, remaining);
+    }
+    state.token_pool_a.pool += needed_a;
+    state.token_pool_b.pool += needed_b;
+    state.update_swap_constant();
+    *state.lp_shares.entry(context.sender).or_insert(0) += shares;
+    state.total_shares += shares;
 
-    (state, vec![])
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(shares);
+    (state, vec![event_group_builder.build()])
 }
 
-/// Withdraw `amount` of token A or B from the contract for the calling user.
-/// This fails if `amount` is larger than the user balance of the corresponding token.
-///
-/// It preemptively updates the state of the user's balance before making the transfer.
-/// This means that if the transfer fails, the contract could end up with more money than it has registered, which is acceptable.
-/// This is to incentivize the user to spend enough gas to complete the transfer.
+/// Transfers the `protocol_fees` accrued in `token_address` to the `fee_recipient`.
+/// Fails if called by anyone but the `fee_recipient`, or if no fees have accrued in the token.
 ///
 /// ### Parameters:
 ///
-///  * `context`: [`ContractContext`] - The contract context containing sender and chain information.
+/// * `context`: [`ContractContext`] - The context for the action call.
 ///
-///  * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
-///  * `token_address`: [`Address`] - The address of the token contract to withdraw to.
+/// * `token_address`: [`Address`] - The address of the token contract to collect the fees of.
 ///
-///  * `amount`: [`u64`] - The amount to withdraw.
+/// ### Returns
 ///
-/// # Returns
-/// The unchanged state object of type [`LiquiditySwapContractState`].
-#[action(shortname = 0x04)]
-pub fn withdraw(
+/// The updated state object of type [`LiquiditySwapContractState`] and an event transferring the fees.
+#[action(shortname = 0x19)]
+pub fn collect_protocol_fees(
     context: ContractContext,
     mut state: LiquiditySwapContractState,
     token_address: Address,
-    amount: u64,
 ) -> (LiquiditySwapContractState, Vec<EventGroup>) {
-    let (token_from, _) = state.deduce_from_to_tokens(token_address);
-
-    state.subtract_from_user_balance(context.sender, token_from, amount);
+    assert_eq!(
+        context.sender, state.fee_recipient,
+        "Only the fee recipient can collect the protocol fees"
+    );
+    state.deduce_from_to_tokens(token_address);
+    let amount = state
+        .protocol_fees
+        .remove(&token_address)
+        .expect("No protocol fees to collect");
 
     let mut event_group_builder = EventGroup::builder();
     event_group_builder
         .call(token_address, token_contract_transfer())
-        .argument(context.sender)
+        .argument(state.fee_recipient)
         .argument(amount)
         .done();
 
     (state, vec![event_group_builder.build()])
 }
 
-/// Empties the pools into the contract owner's balance and closes the contract.
-/// Fails if called by anyone but the contract owner.
+/// * HELPER FUNCTIONS *
+
+/// Asserts that the owner may currently initialize the pool of `token` with `pool_size`.
+/// The sender must be the contract owner, the contract must be closed with verified tokens and past
+/// its withdrawal window, and `pool_size` must be at least the pool's `min_seed` and, on the common
+/// scale, [`MIN_INITIAL_LIQUIDITY`]. Checking before the transfer keeps a callback from rejecting
+/// tokens that have already been moved.
 ///
 /// ### Parameters:
 ///
@@ -554,33 +3570,87 @@ pub fn withdraw(
 ///
 /// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
 ///
-/// ### Returns
+/// * `token`: [`Token`] - The token of the pool to initialize.
 ///
-/// The updated state object of type [`LiquiditySwapContractState`].
-#[action(shortname = 0x05)]
-pub fn close_pools(
-    context: ContractContext,
-    mut state: LiquiditySwapContractState,
-) -> (LiquiditySwapContractState, Vec<EventGroup>) {
+/// * `pool_size`: [`u64`] - The desired size of the pool.
+fn assert_can_provide_liquidity(
+    context: &ContractContext,
+    state: &LiquiditySwapContractState,
+    token: Token,
+    pool_size: u64,
+) {
     assert_eq!(
         context.sender, state.contract_owner,
-        "Only the contract owner can close the pools"
+        "Only the contract owner can initialize its pools"
+    );
+    assert!(
+        state.is_closed,
+        "Can only initialize when the contract is closed"
+    );
+    assert!(
+        state.tokens_verified,
+        "Token ownership has not been verified"
+    );
+    assert!(
+        context.block_production_time >= state.withdraw_until,
+        "Cannot initialize the pools before the withdrawal window ends at {}",
+        state.withdraw_until
     );
-    assert!(!state.is_closed, "The contract is already closed");
 
-    state.add_to_user_balance(state.contract_owner, TOKEN_A, state.token_pool_a.pool);
-    state.add_to_user_balance(state.contract_owner, TOKEN_B, state.token_pool_b.pool);
+    let min_seed = state.get_min_seed_for(token);
+    assert!(
+        pool_size >= min_seed,
+        "Pool size {} is below the minimum seed of {}",
+        pool_size,
+        min_seed
+    );
+    let normalized_pool_size = state.normalize(token, pool_size);
+    assert!(
+        normalized_pool_size >= MIN_INITIAL_LIQUIDITY,
+        "Initial pool of {} is below the minimum initial liquidity of {}",
+        normalized_pool_size,
+        MIN_INITIAL_LIQUIDITY
+    );
+}
 
-    // Close contract
-    state.token_pool_a.pool = 0;
-    state.token_pool_b.pool = 0;
-    state.is_closed = true;
+/// Adds the transfer of a withdrawal of `amount` of `token` to `user` to `event_group_builder`.
+/// The transfer is checked in `withdraw_callback`.
+///
+/// ### Parameters:
+///
+/// * `event_group_builder`: [`EventGroupBuilder`] - The builder to add the transfer to.
+///
+/// * `state`: [`LiquiditySwapContractState`] - The current state of the contract.
+///
+/// * `user`: [`Address`] - The user withdrawing.
+///
+/// * `token`: [`Token`] - The token being withdrawn.
+///
+/// * `amount`: [`u64`] - The amount to withdraw.
+fn add_withdraw_transfer(
+    event_group_builder: &mut EventGroupBuilder,
+    state: &LiquiditySwapContractState,
+    user: Address,
+    token: Token,
+    amount: u64,
+) {
+    event_group_builder
+        .call(
+            state.get_token_address_for(token),
+            token_contract_transfer(),
+        )
+        .argument(user)
+        .argument(amount)
+        .done();
 
-    (state, vec![])
+    event_group_builder
+        .with_callback(SHORTNAME_WITHDRAW_CALLBACK)
+        .argument(user)
+        .argument(token)
+        .argument(amount)
+        .done();
 }
 
-/// * HELPER FUNCTIONS *
-
 /// Creates the `Shortname` corresponding to the `transfer` action of a token contract.
 /// This is utilized in combination with an `EventGroupBuilder`'s `call` function.
 ///
@@ -603,17 +3673,162 @@ fn token_contract_transfer_from() -> Shortname {
     Shortname::from_u32(0x03)
 }
 
-/// Divides two [`u64`] types and rounds up.
+/// Creates the `Shortname` corresponding to the `approve` action of a token contract.
+/// This is utilized in combination with an `EventGroupBuilder`'s `call` function.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the `approve` action of a token contract.
+#[inline]
+fn token_contract_approve() -> Shortname {
+    Shortname::from_u32(0x05)
+}
+
+/// Creates the `Shortname` corresponding to the `deposit` action of a sibling liquidity-swap contract.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the `deposit` action of a liquidity-swap contract.
+#[inline]
+fn swap_contract_deposit() -> Shortname {
+    Shortname::from_u32(0x02)
+}
+
+/// Creates the `Shortname` corresponding to the `swap` action of a sibling liquidity-swap contract.
+/// The action returns the output of the swap.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the `swap` action of a liquidity-swap contract.
+#[inline]
+fn swap_contract_swap() -> Shortname {
+    Shortname::from_u32(0x03)
+}
+
+/// Creates the `Shortname` corresponding to the `withdraw` action of a sibling liquidity-swap contract.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the `withdraw` action of a liquidity-swap contract.
+#[inline]
+fn swap_contract_withdraw() -> Shortname {
+    Shortname::from_u32(0x04)
+}
+
+/// Creates the `Shortname` of the owner query action a token contract must expose.
+/// The action must return the [`Address`] of the token contract's owner.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the owner query action of a token contract.
+#[inline]
+fn token_contract_owner() -> Shortname {
+    Shortname::from_u32(0x08)
+}
+
+/// Creates the `Shortname` of the decimals query action a token contract must expose.
+/// The action must return the `decimals` of the token as a [`u8`].
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the decimals query action of a token contract.
+#[inline]
+fn token_contract_decimals() -> Shortname {
+    Shortname::from_u32(0x09)
+}
+
+/// Creates the `Shortname` of the action a borrower contract of a collateralized loan must expose.
+/// The action receives the borrowed token address, the amount, the fee and the forwarded data.
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the loan receiver action of a borrower contract.
+#[inline]
+fn loan_receiver() -> Shortname {
+    Shortname::from_u32(0x40)
+}
+
+/// Creates the `Shortname` of the price query action an oracle contract must expose.
+/// The action must return the price of token A in token B, scaled by [`ORACLE_PRICE_SCALE`].
+///
+/// ### Returns:
+///
+/// The `Shortname` corresponding to the price query action of an oracle contract.
+#[inline]
+fn oracle_get_price() -> Shortname {
+    Shortname::from_u32(0x01)
+}
+
+/// Decodes the price returned by an oracle contract, a big-endian [`u64`].
+///
+/// ### Parameters:
+///
+/// * `return_data`: [`&[u8]`] - The return data of the oracle query.
+///
+/// ### Returns:
+///
+/// The oracle price of type [`u64`].
+fn read_oracle_price(return_data: &[u8]) -> u64 {
+    let bytes: [u8; 8] = return_data
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .expect("Oracle returned a malformed price");
+    u64::from_be_bytes(bytes)
+}
+
+/// Divides two [`u128`] types and rounds up.
+///
+/// ### Parameters:
+///
+/// * `numerator`: [`u128`] - The numerator for the division.
+///
+/// * `denominator`: [`u128`] - The denominator for the division.
+///
+/// ### Returns:
+///
+/// The result of the division, rounded up, of type [`u128`].
+fn u128_division_ceil(numerator: u128, denominator: u128) -> u128 {
+    numerator / denominator + u128::from(numerator % denominator > 0)
+}
+
+/// Multiplies a [`u128`] by a fraction, rounding down. The value is divided before it is multiplied,
+/// such that only a result that does not fit in a [`u128`] overflows.
 ///
 /// ### Parameters:
 ///
-/// * `numerator`: [`u64`] - The numerator for the division.
+/// * `value`: [`u128`] - The value to multiply.
+///
+/// * `numerator`: [`u128`] - The numerator of the fraction.
+///
+/// * `denominator`: [`u128`] - The denominator of the fraction.
+///
+/// ### Returns:
+///
+/// The product rounded down, or `None` on overflow, of type [`Option<u128>`].
+fn u128_mul_div(value: u128, numerator: u128, denominator: u128) -> Option<u128> {
+    let whole = (value / denominator).checked_mul(numerator)?;
+    let remainder = (value % denominator).checked_mul(numerator)? / denominator;
+    whole.checked_add(remainder)
+}
+
+/// Computes the integer square root of a [`u128`], rounded down.
 ///
-/// * `denominator`: [`u64`] - The denominator for the division.
+/// ### Parameters:
+///
+/// * `value`: [`u128`] - The value to take the square root of.
 ///
 /// ### Returns:
 ///
-/// The result of the division, rounded up, of type [`u64`].
-fn u64_division_ceil(numerator: u64, denominator: u64) -> u64 {
-    numerator / denominator + u64::from(numerator % denominator > 0)
+/// The largest integer whose square is at most `value`, of type [`u128`].
+fn u128_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut root = value;
+    let mut next = (root + 1) / 2;
+    while next < root {
+        root = next;
+        next = (root + value / root) / 2;
+    }
+    root
 }
@@ -1,17 +1,2643 @@
 #[cfg(test)]
 mod utility_tests {
-    use crate::u64_division_ceil;
+    use crate::{u128_division_ceil, u128_mul_div, u128_sqrt};
 
     #[test]
-    pub fn test_u64_division_ceil() {
-        // Division by 0 cases is guarded against by u64 type and the source code
+    pub fn test_u128_division_ceil() {
+        assert_eq!(u128_division_ceil(10, 2), 5);
+        assert_eq!(u128_division_ceil(15, 4), 4);
+        assert_eq!(
+            u128_division_ceil(u128::from(u64::MAX) * 3 + 1, 3),
+            u128::from(u64::MAX) + 1
+        );
+    }
+
+    #[test]
+    pub fn test_u128_mul_div() {
+        assert_eq!(u128_mul_div(10, 3, 4), Some(7));
+        assert_eq!(u128_mul_div(u128::MAX, 1, 2), Some(u128::MAX / 2));
+        assert_eq!(u128_mul_div(u128::MAX / 2, 3, 2), None);
+    }
+
+    #[test]
+    pub fn test_u128_sqrt() {
+        assert_eq!(u128_sqrt(0), 0);
+        assert_eq!(u128_sqrt(1), 1);
+        assert_eq!(u128_sqrt(15), 3);
+        assert_eq!(u128_sqrt(16), 4);
+        assert_eq!(u128_sqrt(4_000_000), 2000);
+    }
+}
+
+#[cfg(test)]
+mod test_utils {
+    use crate::{
+        initialize, u128_sqrt, verify_tokens_callback, FeeGrowth, LiquiditySwapContractState,
+        TokenPool, UserBalance,
+    };
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::{CallbackContext, ContractContext, ExecutionResult};
+    use pbc_traits::ReadWriteRPC;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    pub fn address(address_type: AddressType, id: u8) -> Address {
+        Address {
+            address_type,
+            identifier: [id; 20],
+        }
+    }
+
+    pub fn context(sender: Address) -> ContractContext {
+        context_at(sender, 0)
+    }
+
+    pub fn context_at(sender: Address, block_production_time: i64) -> ContractContext {
+        ContractContext {
+            contract_address: address(AddressType::PublicContract, 0xff),
+            sender,
+            block_time: 0,
+            block_production_time,
+            current_transaction: [0; 32],
+            original_transaction: [0; 32],
+        }
+    }
+
+    pub fn open_state(owner: Address, pool_a: u64, pool_b: u64) -> LiquiditySwapContractState {
+        let total_shares = u128_sqrt(u128::from(pool_a) * u128::from(pool_b)) as u64;
+        LiquiditySwapContractState {
+            contract_owner: owner,
+            token_pool_a: TokenPool {
+                token_address: address(AddressType::PublicContract, 0xa),
+                pool: pool_a,
+                min_seed: 0,
+            },
+            token_pool_b: TokenPool {
+                token_address: address(AddressType::PublicContract, 0xb),
+                pool: pool_b,
+                min_seed: 0,
+            },
+            swap_constant: u128::from(pool_a) * u128::from(pool_b),
+            user_balances: BTreeMap::new(),
+            is_closed: false,
+            max_swap_bps: 0,
+            min_output_floor: 0,
+            max_pool_ratio: 0,
+            close_authorities: BTreeSet::new(),
+            oracle: None,
+            max_oracle_deviation_bps: 0,
+            grace_withdrawal_period: 0,
+            withdraw_until: 0,
+            tokens_verified: true,
+            token_a_decimals: 0,
+            token_b_decimals: 0,
+            fee_per_mille: 0,
+            retry_failed_withdrawals: false,
+            pending_withdrawals: BTreeMap::new(),
+            auto_withdraw_thresholds: BTreeMap::new(),
+            output_locks: BTreeMap::new(),
+            lp_shares: BTreeMap::from([(owner, total_shares)]),
+            total_shares,
+            pending_deposits: BTreeMap::new(),
+            next_deposit_nonce: 0,
+            protocol_fee_per_mille: 0,
+            fee_recipient: owner,
+            protocol_fees: BTreeMap::new(),
+            routed_balances: BTreeMap::new(),
+            auto_compound: true,
+            fee_growth: FeeGrowth::default(),
+            fee_checkpoints: BTreeMap::new(),
+            accrued_fees: BTreeMap::new(),
+        }
+    }
+
+    pub fn verified_state(owner: Address) -> LiquiditySwapContractState {
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+        let (state, _) = initialize(context(owner), token_a, token_b, 0, 0, 0, 0);
+        verify_tokens_callback(
+            context(owner),
+            token_query_callback_context(owner, owner, 0, 0),
+            state,
+        )
+        .0
+    }
+
+    pub fn with_balance(
+        mut state: LiquiditySwapContractState,
+        user: Address,
+        pool_a_balance: u64,
+        pool_b_balance: u64,
+    ) -> LiquiditySwapContractState {
+        state.user_balances.insert(
+            user,
+            UserBalance {
+                pool_a_balance,
+                pool_b_balance,
+            },
+        );
+        state
+    }
+
+    pub fn callback_context(success: bool) -> CallbackContext {
+        CallbackContext {
+            success,
+            results: vec![],
+        }
+    }
+
+    pub fn token_query_callback_context(
+        owner_a: Address,
+        owner_b: Address,
+        decimals_a: u8,
+        decimals_b: u8,
+    ) -> CallbackContext {
+        let mut results = vec![];
+        for owner in [owner_a, owner_b] {
+            let mut return_data = vec![];
+            owner.rpc_write_to(&mut return_data).unwrap();
+            results.push(ExecutionResult {
+                succeeded: true,
+                return_data,
+            });
+        }
+        for decimals in [decimals_a, decimals_b] {
+            results.push(ExecutionResult {
+                succeeded: true,
+                return_data: vec![decimals],
+            });
+        }
+        CallbackContext {
+            success: true,
+            results,
+        }
+    }
+
+    pub fn oracle_callback_context(price: u64) -> CallbackContext {
+        CallbackContext {
+            success: true,
+            results: vec![ExecutionResult {
+                succeeded: true,
+                return_data: price.to_be_bytes().to_vec(),
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod swap_tests {
+    use super::test_utils::{address, context, context_at, open_state, with_balance};
+    use crate::swap;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_swap_within_limit() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        state.max_swap_bps = 1000;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 100, 0, 0, 0);
+
+        assert_eq!(state.token_pool_a.pool, 1100);
+        assert_eq!(state.token_pool_b.pool, 910);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_swap_with_pools_near_half_u64_max() {
+        // The product of the pools does not fit in a u64
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let pool = u64::MAX / 2;
+        let state = with_balance(open_state(owner, pool, pool), user, 1_000_000, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1_000_000, 0, 0, 0);
+
+        assert_eq!(state.user_balances[&user].pool_b_balance, 999_999);
+        assert_eq!(state.token_pool_a.pool, pool + 1_000_000);
+        assert_eq!(state.token_pool_b.pool, pool - 999_999);
+        assert_eq!(
+            state.swap_constant,
+            u128::from(pool + 1_000_000) * u128::from(pool - 999_999)
+        );
+    }
+
+    #[test]
+    pub fn test_swap_within_deadline() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context_at(user, 5_000), state, token_a, 100, 0, 5_000, 0);
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap deadline 5000 has passed")]
+    pub fn test_swap_past_deadline_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context_at(user, 5_001), state, token_a, 100, 0, 5_000, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap input 101 exceeds the maximum of 100")]
+    pub fn test_swap_over_limit_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 101, 0);
+        state.max_swap_bps = 1000;
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context(user), state, token_a, 101, 0, 0, 0);
+    }
+
+    #[test]
+    pub fn test_swap_limit_disabled_when_zero() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 1000, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1000, 0, 0, 0);
+
+        assert_eq!(state.token_pool_a.pool, 2000);
+        assert_eq!(state.token_pool_b.pool, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap output 0 is below the minimum of 1")]
+    pub fn test_dust_swap_rejected_by_output_floor() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 10), user, 50, 0);
+        state.min_output_floor = 1;
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context(user), state, token_a, 50, 0, 0, 0);
+    }
+
+    #[test]
+    pub fn test_dust_swap_allowed_without_output_floor() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 10), user, 50, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 50, 0, 0, 0);
+
+        assert_eq!(state.token_pool_a.pool, 1050);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+    }
+
+    #[test]
+    pub fn test_swap_within_pool_ratio() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 1000, 0);
+        state.max_pool_ratio = 4;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1000, 0, 0, 0);
+
+        assert_eq!(state.token_pool_a.pool, 2000);
+        assert_eq!(state.token_pool_b.pool, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap would leave the pools more imbalanced than 4:1")]
+    pub fn test_swap_breaching_pool_ratio_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 1500, 0);
+        state.max_pool_ratio = 4;
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context(user), state, token_a, 1500, 0, 0, 0);
+    }
+
+    #[test]
+    pub fn test_huge_swap_leaves_dust_in_pool() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 1_000_000_000_000, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1_000_000_000_000, 0, 0, 0);
+
+        assert_eq!(state.token_pool_b.pool, 1);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 999);
+    }
+
+    #[test]
+    pub fn test_swap_keeps_to_pool_remainder_below_common_scale() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 100_050), user, 10, 0);
+        state.token_b_decimals = 2;
+        state.swap_constant = 1000 * 1000;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 10, 0, 0, 0);
+
+        assert_eq!(state.token_pool_b.pool, 99_150);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 900);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap too small - the output is zero")]
+    pub fn test_swap_below_common_scale_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 100_050), user, 0, 49);
+        state.token_b_decimals = 2;
+        state.swap_constant = 1000 * 1000;
+        let token_b = state.token_pool_b.token_address;
+
+        swap(context(user), state, token_b, 49, 0, 0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap too small - the output is zero")]
+    pub fn test_swap_against_pool_below_common_scale_rejected() {
+        // Pool B holds less than one unit on the common scale, so the swap constant is zero
+        // and the pool keeps its remainder
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 50), user, 10, 0);
+        state.token_b_decimals = 2;
+        state.swap_constant = 0;
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context(user), state, token_a, 10, 0, 0, 0);
+    }
+}
+
+#[cfg(test)]
+mod collateralized_loan_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{close_pools, collateralized_loan, collateralized_loan_callback, swap, Token};
+    use pbc_contract_common::address::AddressType;
+    use pbc_contract_common::context::{CallbackContext, ExecutionResult};
+
+    fn loan_callback_context(lent: bool, repaid: bool) -> CallbackContext {
+        let result = |succeeded: bool| ExecutionResult {
+            succeeded,
+            return_data: vec![],
+        };
+        CallbackContext {
+            success: lent && repaid,
+            results: vec![result(lent), result(lent), result(repaid)],
+        }
+    }
+
+    #[test]
+    pub fn test_repaid_loan_credits_fee() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 1003, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, events) =
+            collateralized_loan(context(user), state, token_a, 1000, borrower, vec![]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.token_pool_a.pool, 10000);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+
+        let (state, _) = collateralized_loan_callback(
+            context(user),
+            loan_callback_context(true, true),
+            state,
+            user,
+            Token::A,
+            1000,
+            3,
+        );
+
+        assert_eq!(state.token_pool_a.pool, 10003);
+        assert_eq!(state.token_pool_b.pool, 10000);
+        assert_eq!(state.swap_constant, 10003 * 10000);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 1003);
+    }
+
+    #[test]
+    pub fn test_unpaid_loan_forfeits_collateral() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 1003, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = collateralized_loan(context(user), state, token_a, 1000, borrower, vec![]);
+        let (state, _) = collateralized_loan_callback(
+            context(user),
+            loan_callback_context(true, false),
+            state,
+            user,
+            Token::A,
+            1000,
+            3,
+        );
+
+        assert_eq!(state.token_pool_a.pool, 10003);
+        assert_eq!(state.swap_constant, 10003 * 10000);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+    }
+
+    #[test]
+    pub fn test_failed_loan_returns_collateral() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 1003, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = collateralized_loan(context(user), state, token_a, 1000, borrower, vec![]);
+        let (state, _) = collateralized_loan_callback(
+            context(user),
+            loan_callback_context(false, false),
+            state,
+            user,
+            Token::A,
+            1000,
+            3,
+        );
+
+        assert_eq!(state.token_pool_a.pool, 10000);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 1003);
+    }
+
+    #[test]
+    pub fn test_swap_during_loan_is_priced_on_full_pool() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 9027, 100);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = collateralized_loan(context(user), state, token_a, 9000, borrower, vec![]);
+        let (state, _) = swap(context(user), state, token_b, 100, 0, 0, 0);
+
+        // 10000 - ceil(100000000 / 10100) = 99
+        assert_eq!(state.user_balances[&user].pool_a_balance, 99);
+    }
+
+    #[test]
+    pub fn test_fee_of_loan_settled_after_close_is_returned() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 1003, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = collateralized_loan(context(user), state, token_a, 1000, borrower, vec![]);
+        let (state, _) = close_pools(context(owner), state);
+        let (state, _) = collateralized_loan_callback(
+            context(user),
+            loan_callback_context(true, true),
+            state,
+            user,
+            Token::A,
+            1000,
+            3,
+        );
+
+        assert_eq!(state.token_pool_a.pool, 0);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 1006);
+    }
+
+    #[test]
+    pub fn test_fee_of_large_loan_does_not_wrap() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let amount = u64::MAX / 20;
+        // ceil(amount * 30 / 10000), where `amount * 30` exceeds u64::MAX
+        let fee = 2_767_011_611_056_433;
+        let state = with_balance(
+            open_state(owner, u64::MAX / 10, 10000),
+            user,
+            amount + fee + 5,
+            0,
+        );
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) =
+            collateralized_loan(context(user), state, token_a, amount, borrower, vec![]);
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient funds")]
+    pub fn test_loan_without_collateral_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 10000, 10000), user, 1002, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        collateralized_loan(context(user), state, token_a, 1000, borrower, vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Loan amount must be positive and less than the pool")]
+    pub fn test_loan_cannot_drain_pool() {
+        let owner = address(AddressType::Account, 1);
+        let borrower = address(AddressType::PublicContract, 3);
+        let state = open_state(owner, 10000, 10000);
+        let token_a = state.token_pool_a.token_address;
+
+        collateralized_loan(context(owner), state, token_a, 10000, borrower, vec![]);
+    }
+}
+
+#[cfg(test)]
+mod close_authority_tests {
+    use super::test_utils::{address, context, open_state};
+    use crate::{add_close_authority, close_pools, remove_close_authority};
+    use pbc_contract_common::address::AddressType;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_close_authority_can_close() {
+        let owner = address(AddressType::Account, 1);
+        let authority = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 2000);
+
+        let (state, _) = add_close_authority(context(owner), state, authority);
+        let (state, _) = close_pools(context(authority), state);
+
+        assert!(state.is_closed);
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 1000);
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 2000);
+    }
+
+    #[test]
+    pub fn test_close_pays_out_providers_by_share() {
+        let owner = address(AddressType::Account, 1);
+        let provider = address(AddressType::Account, 2);
+        let this_contract = address(AddressType::PublicContract, 0xff);
+        let mut state = open_state(owner, 1000, 2000);
+        state.lp_shares = BTreeMap::from([(owner, 1000), (provider, 2000), (this_contract, 100)]);
+        state.total_shares = 3100;
+
+        let (state, _) = close_pools(context(owner), state);
+
+        assert_eq!(state.user_balances[&provider].pool_a_balance, 645);
+        assert_eq!(state.user_balances[&provider].pool_b_balance, 1290);
+        // The owner's share, 322 and 645, plus the part of the locked shares and the dust
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 355);
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 710);
+        assert!(!state.user_balances.contains_key(&this_contract));
+        assert!(state.lp_shares.is_empty());
+        assert_eq!(state.total_shares, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner or a close authority can close the pools")]
+    pub fn test_unauthorized_address_cannot_close() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1000, 2000);
+
+        close_pools(context(address(AddressType::Account, 3)), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner or a close authority can close the pools")]
+    pub fn test_removed_authority_cannot_close() {
+        let owner = address(AddressType::Account, 1);
+        let authority = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 2000);
+
+        let (state, _) = add_close_authority(context(owner), state, authority);
+        let (state, _) = remove_close_authority(context(owner), state, authority);
+        close_pools(context(authority), state);
+    }
+}
+
+#[cfg(test)]
+mod oracle_tests {
+    use super::test_utils::{
+        address, context, context_at, open_state, oracle_callback_context, with_balance,
+    };
+    use crate::{oracle_swap_callback, set_oracle, swap, swap_with_oracle_check, Token};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_swap_near_oracle_price_allowed() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        let token_a = state.token_pool_a.token_address;
+        let (state, events) = swap_with_oracle_check(context(user), state, token_a, 1_000, 999, 0);
+        assert_eq!(events.len(), 1);
+
+        // The pools are balanced, so the oracle price is 1 token B per token A.
+        let (state, _) = oracle_swap_callback(
+            context(user),
+            oracle_callback_context(1_000_000),
+            state,
+            user,
+            Token::A,
+            1_000,
+            999,
+            0,
+        );
+
+        let balance = state.user_balances.get(&user).unwrap();
+        assert_eq!(balance.pool_a_balance, 0);
+        assert_eq!(balance.pool_b_balance, 999);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeding the maximum of 100")]
+    pub fn test_swap_far_from_oracle_price_blocked() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        // The oracle values token A at 2 token B, so the pool is mispriced.
+        oracle_swap_callback(
+            context(user),
+            oracle_callback_context(2_000_000),
+            state,
+            user,
+            Token::A,
+            1_000,
+            0,
+            0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Slippage exceeded")]
+    pub fn test_oracle_swap_below_minimum_output_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        oracle_swap_callback(
+            context(user),
+            oracle_callback_context(1_000_000),
+            state,
+            user,
+            Token::A,
+            1_000,
+            1_000,
+            0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap deadline 5000 has passed")]
+    pub fn test_oracle_answer_after_deadline_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        // The swap was requested in time, but the oracle answered too late
+        oracle_swap_callback(
+            context_at(user, 5_001),
+            oracle_callback_context(1_000_000),
+            state,
+            user,
+            Token::A,
+            1_000,
+            0,
+            5_000,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "999 of the balance is locked until 10000")]
+    pub fn test_locked_output_cannot_be_swapped_through_oracle() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let token_a = state.token_pool_a.token_address;
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 10_000);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        oracle_swap_callback(
+            context_at(user, 1),
+            oracle_callback_context(1_000_000),
+            state,
+            user,
+            Token::B,
+            999,
+            0,
+            0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swaps must be made through swap_with_oracle_check")]
+    pub fn test_plain_swap_disabled_with_oracle() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let oracle = address(AddressType::PublicContract, 3);
+        let state = with_balance(open_state(owner, 1_000_000, 1_000_000), user, 1_000, 0);
+        let (state, _) = set_oracle(context(owner), state, Some(oracle), 100);
+
+        let token_a = state.token_pool_a.token_address;
+        swap(context(user), state, token_a, 1_000, 0, 0, 0);
+    }
+}
+
+#[cfg(test)]
+mod grace_withdrawal_tests {
+    use super::test_utils::{address, context, context_at, open_state};
+    use crate::{close_pools, provide_liquidity, set_grace_withdrawal_period, withdraw};
+    use pbc_contract_common::address::AddressType;
+
+    const PERIOD: i64 = 86_400_000;
+
+    #[test]
+    pub fn test_close_sets_withdrawal_window() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1_000, 1_000);
+        let (state, _) = set_grace_withdrawal_period(context(owner), state, PERIOD);
+
+        let (state, _) = close_pools(context_at(owner, 500), state);
+
+        assert_eq!(state.withdraw_until, 500 + PERIOD);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot initialize the pools before the withdrawal window ends")]
+    pub fn test_reinitialize_during_window_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1_000, 1_000);
+        let (state, _) = set_grace_withdrawal_period(context(owner), state, PERIOD);
+        let (state, _) = close_pools(context_at(owner, 0), state);
+
+        let token_a = state.token_pool_a.token_address;
+        provide_liquidity(context_at(owner, PERIOD - 1), state, token_a, 1_000);
+    }
+
+    #[test]
+    pub fn test_reinitialize_after_window_allowed() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1_000, 1_000);
+        let (state, _) = set_grace_withdrawal_period(context(owner), state, PERIOD);
+        let (state, _) = close_pools(context_at(owner, 0), state);
+
+        let token_a = state.token_pool_a.token_address;
+        let (_, events) = provide_liquidity(context_at(owner, PERIOD), state, token_a, 1_000);
+
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_withdraw_during_window_succeeds() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1_000, 1_000);
+        let (state, _) = set_grace_withdrawal_period(context(owner), state, PERIOD);
+        let (state, _) = close_pools(context_at(owner, 0), state);
+
+        let token_a = state.token_pool_a.token_address;
+        let (state, events) = withdraw(context_at(owner, 1), state, token_a, 1_000);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.user_balances.get(&owner).unwrap().pool_a_balance, 0);
+    }
+}
+
+#[cfg(test)]
+mod token_owner_tests {
+    use super::test_utils::{address, context, token_query_callback_context};
+    use crate::{initialize, provide_liquidity, verify_tokens_callback};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_initialize_queries_token_owners() {
+        let owner = address(AddressType::Account, 1);
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+
+        let (state, events) = initialize(context(owner), token_a, token_b, 0, 0, 0, 0);
+
+        assert!(!state.tokens_verified);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_matching_token_owners_verified() {
+        let owner = address(AddressType::Account, 1);
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+        let (state, _) = initialize(context(owner), token_a, token_b, 0, 0, 0, 0);
+
+        let (state, _) = verify_tokens_callback(
+            context(owner),
+            token_query_callback_context(owner, owner, 0, 0),
+            state,
+        );
+        assert!(state.tokens_verified);
+
+        let (_, events) = provide_liquidity(context(owner), state, token_a, 1_000);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token B is not owned by the contract owner")]
+    pub fn test_mismatched_token_owner_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let other = address(AddressType::Account, 2);
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+        let (state, _) = initialize(context(owner), token_a, token_b, 0, 0, 0, 0);
+
+        verify_tokens_callback(
+            context(owner),
+            token_query_callback_context(owner, other, 0, 0),
+            state,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token ownership has not been verified")]
+    pub fn test_unverified_pools_cannot_be_initialized() {
+        let owner = address(AddressType::Account, 1);
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+        let (state, _) = initialize(context(owner), token_a, token_b, 0, 0, 0, 0);
+
+        provide_liquidity(context(owner), state, token_a, 1_000);
+    }
+}
+
+#[cfg(test)]
+mod decimals_tests {
+    use super::test_utils::{
+        address, context, open_state, token_query_callback_context, with_balance,
+    };
+    use crate::{swap, verify_tokens_callback, LiquiditySwapContractState};
+    use pbc_contract_common::address::{Address, AddressType};
+
+    const A_UNIT: u64 = 1_000_000;
+    const B_UNIT: u64 = 1_000_000_000_000_000_000;
+
+    // A pool of 10 token A with 6 decimals and 10 token B with 18 decimals.
+    fn mixed_decimals_state(owner: Address) -> LiquiditySwapContractState {
+        let state = open_state(owner, 0, 0);
+        let (mut state, _) = verify_tokens_callback(
+            context(owner),
+            token_query_callback_context(owner, owner, 6, 18),
+            state,
+        );
+        state.token_pool_a.pool = 10 * A_UNIT;
+        state.token_pool_b.pool = 10 * B_UNIT;
+        state.update_swap_constant();
+        state
+    }
+
+    #[test]
+    pub fn test_swap_constant_is_normalized() {
+        let owner = address(AddressType::Account, 1);
+        let state = mixed_decimals_state(owner);
+
+        assert_eq!(state.swap_constant, u128::from(10 * A_UNIT * 10 * A_UNIT));
+    }
+
+    #[test]
+    pub fn test_swap_6_to_18_decimals() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(mixed_decimals_state(owner), user, A_UNIT, 0);
+        // Equal-valued pools must pass a tight ratio guard despite their raw amounts differing by 10^12
+        state.max_pool_ratio = 2;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, A_UNIT, 0, 0, 0);
+
+        // 10 * 10 / 11 = 9.090910 (rounded up) B remains, so 0.909090 B is paid out
+        assert_eq!(
+            state.user_balances[&user].pool_b_balance,
+            909_090 * 1_000_000_000_000
+        );
+        assert_eq!(state.token_pool_b.pool, 9_090_910 * 1_000_000_000_000);
+    }
+
+    #[test]
+    pub fn test_swap_18_to_6_decimals() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(mixed_decimals_state(owner), user, 0, B_UNIT);
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context(user), state, token_b, B_UNIT, 0, 0, 0);
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 909_090);
+        assert_eq!(state.token_pool_a.pool, 9_090_910);
+    }
+}
+
+#[cfg(test)]
+mod min_seed_tests {
+    use super::test_utils::{address, context, token_query_callback_context};
+    use crate::{
+        initialize, provide_liquidity, verify_tokens_callback, LiquiditySwapContractState,
+    };
+    use pbc_contract_common::address::{Address, AddressType};
+
+    fn seeded_state(owner: Address) -> LiquiditySwapContractState {
+        let token_a = address(AddressType::PublicContract, 0xa);
+        let token_b = address(AddressType::PublicContract, 0xb);
+        let (state, _) = initialize(context(owner), token_a, token_b, 0, 1_000, 2_000, 0);
+        verify_tokens_callback(
+            context(owner),
+            token_query_callback_context(owner, owner, 0, 0),
+            state,
+        )
+        .0
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool size 1999 is below the minimum seed of 2000")]
+    pub fn test_seed_below_minimum_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = seeded_state(owner);
+        let token_b = state.token_pool_b.token_address;
+
+        provide_liquidity(context(owner), state, token_b, 1_999);
+    }
+
+    #[test]
+    pub fn test_adequate_seed_accepted() {
+        let owner = address(AddressType::Account, 1);
+        let state = seeded_state(owner);
+        let token_a = state.token_pool_a.token_address;
+
+        let (_, events) = provide_liquidity(context(owner), state, token_a, 1_000);
+
+        assert_eq!(events.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod max_swap_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::query_max_swap;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_max_swap_limited_by_balance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 50, 0);
+        state.max_swap_bps = 1000;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, events) = query_max_swap(context(user), state, user, token_a);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.max_swap_for(user, token_a), 50);
+    }
+
+    #[test]
+    pub fn test_max_swap_limited_by_cap() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 2000), user, 0, 500);
+        state.max_swap_bps = 1000;
+        let token_b = state.token_pool_b.token_address;
+
+        assert_eq!(state.max_swap_for(user, token_b), 200);
+    }
+
+    #[test]
+    pub fn test_max_swap_without_balance_is_zero() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_a = state.token_pool_a.token_address;
+
+        assert_eq!(state.max_swap_for(user, token_a), 0);
+    }
+}
+
+#[cfg(test)]
+mod swap_through_tests {
+    use super::test_utils::{
+        address, callback_context, context, context_at, open_state, with_balance,
+    };
+    use crate::{
+        deposit, deposit_callback, swap, swap_through, swap_through_approve_callback,
+        swap_through_callback, swap_through_deposit_callback, swap_through_payout_callback,
+        swap_through_withdraw_callback, withdraw_routed, withdraw_routed_callback, SwapRoute,
+        Token,
+    };
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::{CallbackContext, ExecutionResult};
+
+    fn route(user: Address, next_output_token: Address) -> SwapRoute {
+        SwapRoute {
+            user,
+            hop_token: Token::B,
+            hop_amount: 90,
+            next_contract: address(AddressType::PublicContract, 0xee),
+            next_output_token,
+            minimum_amount_out: 40,
+            deadline_millis: 0,
+        }
+    }
+
+    fn next_swap_callback_context(final_output: u64) -> CallbackContext {
+        CallbackContext {
+            success: true,
+            results: vec![ExecutionResult {
+                succeeded: true,
+                return_data: final_output.to_be_bytes().to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    pub fn test_two_contract_hop() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let this_contract = address(AddressType::PublicContract, 0xff);
+        let next_contract = address(AddressType::PublicContract, 0xee);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, events) = swap_through(
+            context(user),
+            state,
+            token_a,
+            100,
+            next_contract,
+            token_c,
+            40,
+            0,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.token_pool_b.pool, 910);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+
+        let (state, events) = swap_through_approve_callback(
+            context(this_contract),
+            callback_context(true),
+            state,
+            route(user, token_c),
+        );
+        assert_eq!(events.len(), 1);
+
+        // The next contract pairs token B with token C, and credits the deposit in its own callback
+        // before the deposit callback of this contract continues the route
+        let mut next_state = open_state(owner, 2000, 1000);
+        next_state.token_pool_a.token_address = token_b;
+        next_state.token_pool_b.token_address = token_c;
+        let (next_state, _) = deposit(context(this_contract), next_state, token_b, 90);
+        let (next_state, _) = deposit_callback(
+            context(this_contract),
+            callback_context(true),
+            next_state,
+            0,
+        );
+        let (state, events) = swap_through_deposit_callback(
+            context(this_contract),
+            callback_context(true),
+            state,
+            route(user, token_c),
+        );
+        assert_eq!(events.len(), 1);
+
+        let (next_state, _) = swap(context(this_contract), next_state, token_b, 90, 40, 0, 0);
+        let final_output = next_state.user_balances[&this_contract].pool_b_balance;
+        assert_eq!(final_output, 43);
+
+        let (state, events) = swap_through_callback(
+            context(this_contract),
+            next_swap_callback_context(final_output),
+            state,
+            route(user, token_c),
+        );
+        assert_eq!(events.len(), 1);
+
+        let (state, events) = swap_through_withdraw_callback(
+            context(this_contract),
+            callback_context(true),
+            state,
+            route(user, token_c),
+            token_c,
+            final_output,
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+    }
+
+    #[test]
+    pub fn test_failed_approval_refunds_first_hop() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 0, 0);
+
+        let (state, events) = swap_through_approve_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            route(user, token_c),
+        );
+
+        assert!(events.is_empty());
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_failed_deposit_refunds_first_hop() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 0, 0);
+
+        let (state, events) = swap_through_deposit_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            route(user, token_c),
+        );
+
+        assert!(events.is_empty());
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_failed_next_swap_withdraws_deposit() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, events) = swap_through_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            route(user, token_c),
+        );
+        assert_eq!(events.len(), 1);
+
+        let (state, events) = swap_through_withdraw_callback(
+            context(owner),
+            callback_context(true),
+            state,
+            route(user, token_c),
+            token_b,
+            90,
+        );
+        assert_eq!(events.len(), 1);
+        assert!(!state.user_balances.contains_key(&user));
+    }
+
+    #[test]
+    pub fn test_deposit_that_never_arrived_refunds_first_hop() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, events) = swap_through_withdraw_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            route(user, token_c),
+            token_b,
+            90,
+        );
+
+        assert!(events.is_empty());
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_failed_output_withdrawal_credits_nothing() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+
+        let (state, events) = swap_through_withdraw_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            route(user, token_c),
+            token_c,
+            43,
+        );
+
+        assert!(events.is_empty());
+        assert!(!state.user_balances.contains_key(&user));
+        assert!(!state.routed_balances.contains_key(&user));
+    }
+
+    #[test]
+    pub fn test_failed_refund_transfer_credits_balance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, events) = swap_through_payout_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            user,
+            token_b,
+            90,
+        );
+
+        assert!(events.is_empty());
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_failed_foreign_payout_is_routed() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+
+        let (state, _) = swap_through_payout_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            user,
+            token_c,
+            43,
+        );
+        let (state, _) = swap_through_payout_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            user,
+            token_c,
+            7,
+        );
+
+        assert!(!state.user_balances.contains_key(&user));
+        assert_eq!(state.routed_balances[&user][&token_c], 50);
+    }
+
+    #[test]
+    pub fn test_successful_payout_credits_nothing() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+
+        let (state, _) = swap_through_payout_callback(
+            context(owner),
+            callback_context(true),
+            state,
+            user,
+            token_c,
+            43,
+        );
+
+        assert!(!state.user_balances.contains_key(&user));
+        assert!(!state.routed_balances.contains_key(&user));
+    }
+
+    #[test]
+    pub fn test_withdraw_routed_clears_balance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+        let (state, _) = swap_through_payout_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            user,
+            token_c,
+            43,
+        );
+
+        let (state, events) = withdraw_routed(context(user), state, token_c);
+
+        assert_eq!(events.len(), 1);
+        assert!(!state.routed_balances.contains_key(&user));
+    }
+
+    #[test]
+    pub fn test_failed_routed_withdrawal_is_recorded_again() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+
+        let (state, _) = withdraw_routed_callback(
+            context(owner),
+            callback_context(false),
+            state,
+            user,
+            token_c,
+            43,
+        );
+
+        assert_eq!(state.routed_balances[&user][&token_c], 43);
+    }
+
+    #[test]
+    #[should_panic(expected = "No routed balance to withdraw")]
+    pub fn test_withdraw_routed_without_balance_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = open_state(owner, 1000, 1000);
+
+        withdraw_routed(context(user), state, token_c);
+    }
+
+    #[test]
+    #[should_panic(expected = "The next hop must swap to a different token")]
+    pub fn test_hop_back_to_same_token_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let next_contract = address(AddressType::PublicContract, 0xee);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        swap_through(
+            context(user),
+            state,
+            token_a,
+            100,
+            next_contract,
+            token_b,
+            0,
+            0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap deadline 5000 has passed")]
+    pub fn test_swap_through_past_deadline_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let next_contract = address(AddressType::PublicContract, 0xee);
+        let token_c = address(AddressType::PublicContract, 0xc);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        swap_through(
+            context_at(user, 5_001),
+            state,
+            token_a,
+            100,
+            next_contract,
+            token_c,
+            0,
+            5_000,
+        );
+    }
+}
+
+#[cfg(test)]
+mod rebalance_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::rebalance;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_rebalance_to_weighted_ratio() {
+        let owner = address(AddressType::Account, 1);
+        let state = with_balance(open_state(owner, 1000, 1000), owner, 2000, 0);
+
+        let (state, _) = rebalance(context(owner), state, 8000);
+
+        assert_eq!(state.token_pool_a.pool, 2000);
+        assert_eq!(state.token_pool_b.pool, 500);
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 1000);
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 500);
+    }
+
+    #[test]
+    pub fn test_rebalance_never_passes_target() {
+        let owner = address(AddressType::Account, 1);
+        let state = with_balance(open_state(owner, 3000, 700), owner, 0, 5000);
+
+        let (state, _) = rebalance(context(owner), state, 6000);
+
+        let pool_a = state.token_pool_a.pool;
+        let share_bps = pool_a * 10000 / (pool_a + state.token_pool_b.pool);
+        assert!(share_bps >= 6000);
+        assert!(share_bps <= 6010);
+    }
+
+    #[test]
+    pub fn test_rebalance_at_target_is_noop() {
+        let owner = address(AddressType::Account, 1);
+        let state = with_balance(open_state(owner, 1000, 1000), owner, 100, 100);
+
+        let (state, _) = rebalance(context(owner), state, 5000);
+
+        assert_eq!(state.token_pool_a.pool, 1000);
+        assert_eq!(state.token_pool_b.pool, 1000);
+    }
+
+    #[test]
+    pub fn test_rebalance_with_pools_near_half_u64_max() {
+        // The swap constant times the target share does not fit in a u128
+        let owner = address(AddressType::Account, 1);
+        let pool = u64::MAX / 2;
+        let state = with_balance(open_state(owner, pool, pool), owner, u64::MAX / 4, 0);
+
+        let (state, _) = rebalance(context(owner), state, 6000);
+
+        let pool_a = u128::from(state.token_pool_a.pool);
+        let share_bps = pool_a * 10000 / (pool_a + u128::from(state.token_pool_b.pool));
+        assert_eq!(share_bps, 5999);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in rebalance - target pool would exceed u64::MAX")]
+    pub fn test_rebalance_past_u64_max_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let pool = u64::MAX / 2;
+        let state = with_balance(open_state(owner, pool, pool), owner, u64::MAX / 2, 0);
+
+        rebalance(context(owner), state, 9999);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can rebalance the pools")]
+    pub fn test_only_owner_can_rebalance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+
+        rebalance(context(user), state, 8000);
+    }
+}
+
+#[cfg(test)]
+mod withdraw_retry_tests {
+    use super::test_utils::{address, callback_context, context, open_state, with_balance};
+    use crate::{retry_withdraw, set_retry_failed_withdrawals, withdraw, withdraw_callback, Token};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_failed_transfer_enqueues_retry() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let (state, _) = set_retry_failed_withdrawals(context(owner), state, true);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = withdraw(context(user), state, token_a, 60);
+        let (state, _) = withdraw_callback(
+            context(token_a),
+            callback_context(false),
+            state,
+            user,
+            Token::A,
+            60,
+        );
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 40);
+        assert_eq!(state.pending_withdrawals[&user].pool_a_balance, 60);
+    }
+
+    #[test]
+    pub fn test_retry_succeeds() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let (state, _) = set_retry_failed_withdrawals(context(owner), state, true);
+        let token_a = state.token_pool_a.token_address;
+        let (state, _) = withdraw(context(user), state, token_a, 60);
+        let (state, _) = withdraw_callback(
+            context(token_a),
+            callback_context(false),
+            state,
+            user,
+            Token::A,
+            60,
+        );
+
+        let (state, events) = retry_withdraw(context(user), state, token_a);
+        assert_eq!(events.len(), 1);
+        assert!(state.pending_withdrawals.is_empty());
+
+        let (state, _) = withdraw_callback(
+            context(token_a),
+            callback_context(true),
+            state,
+            user,
+            Token::A,
+            60,
+        );
+        assert!(state.pending_withdrawals.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "No pending withdrawal to retry")]
+    pub fn test_retry_without_pending_withdrawal_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_a = state.token_pool_a.token_address;
+
+        retry_withdraw(context(user), state, token_a);
+    }
+
+    #[test]
+    pub fn test_failed_transfer_is_reclaimed() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = withdraw(context(user), state, token_a, 60);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 40);
+        let (state, _) = withdraw_callback(
+            context(token_a),
+            callback_context(false),
+            state,
+            user,
+            Token::A,
+            60,
+        );
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 100);
+        assert!(state.pending_withdrawals.is_empty());
+    }
+
+    #[test]
+    pub fn test_successful_transfer_is_not_reclaimed() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = withdraw(context(user), state, token_a, 60);
+        let (state, _) = withdraw_callback(
+            context(token_a),
+            callback_context(true),
+            state,
+            user,
+            Token::A,
+            60,
+        );
+
+        assert_eq!(state.user_balances[&user].pool_a_balance, 40);
+    }
+}
+
+#[cfg(test)]
+mod auto_withdraw_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{set_auto_withdraw_threshold, swap};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_crossing_threshold_withdraws_output() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 20);
+        let (state, _) = set_auto_withdraw_threshold(context(user), state, 100);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, events) = swap(context(user), state, token_a, 100, 0, 0, 0);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.token_pool_b.pool, 910);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+    }
+
+    #[test]
+    pub fn test_staying_under_threshold_keeps_output() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let (state, _) = set_auto_withdraw_threshold(context(user), state, 100);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 100, 0, 0, 0);
+
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    pub fn test_zero_threshold_disables_auto_withdraw() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let (state, _) = set_auto_withdraw_threshold(context(user), state, 10);
+        let (state, _) = set_auto_withdraw_threshold(context(user), state, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 100, 0, 0, 0);
+
+        assert!(state.auto_withdraw_thresholds.is_empty());
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+}
+
+#[cfg(test)]
+mod swap_fee_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{swap, LiquiditySwapContractState};
+    use pbc_contract_common::address::AddressType;
+
+    fn swap_back_and_forth(fee_per_mille: u64) -> LiquiditySwapContractState {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = fee_per_mille;
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        for _ in 0..5 {
+            let a_balance = state.user_balances[&user].pool_a_balance;
+            let (next, _) = swap(context(user), state, token_a, a_balance, 0, 0, 0);
+            let b_balance = next.user_balances[&user].pool_b_balance;
+            let (next, _) = swap(context(user), next, token_b, b_balance, 0, 0, 0);
+            state = next;
+        }
+        state
+    }
+
+    #[test]
+    pub fn test_fee_reduces_output() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = 3;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+
+        // Priced on 997 of the 1000 input: 10000 - ceil(100000000 / 10997) = 906
+        assert_eq!(state.user_balances[&user].pool_b_balance, 906);
+        assert_eq!(state.token_pool_a.pool, 11_000);
+        assert_eq!(state.swap_constant, 11_000 * 9_094);
+    }
+
+    #[test]
+    pub fn test_fees_leave_pool_richer() {
+        let without_fee = swap_back_and_forth(0);
+        let with_fee = swap_back_and_forth(3);
+
+        assert!(with_fee.swap_constant > without_fee.swap_constant);
+        assert!(with_fee.token_pool_a.pool > without_fee.token_pool_a.pool);
+    }
+
+    #[test]
+    pub fn test_liquidity_provider_fee_rebated_by_share() {
+        let owner = address(AddressType::Account, 1);
+        let provider = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), provider, 1_000, 0);
+        state.fee_per_mille = 3;
+        state.lp_shares.insert(owner, 5_000);
+        state.lp_shares.insert(provider, 5_000);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(provider), state, token_a, 1_000, 0, 0, 0);
+
+        // Half the fee is rebated, so priced on 998 of the 1000 input: 10000 - ceil(100000000 / 10998) = 907
+        assert_eq!(state.user_balances[&provider].pool_b_balance, 907);
+        assert_eq!(state.token_pool_a.pool, 11_000);
+    }
+
+    #[test]
+    pub fn test_sole_liquidity_provider_pays_no_fee() {
+        let owner = address(AddressType::Account, 1);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), owner, 1_000, 0);
+        state.fee_per_mille = 3;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(owner), state, token_a, 1_000, 0, 0, 0);
+
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 909);
+    }
+}
+
+#[cfg(test)]
+mod slippage_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::swap;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_swap_meeting_minimum_output() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 100, 90, 0, 0);
+
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+
+    #[test]
+    #[should_panic(expected = "Slippage exceeded")]
+    pub fn test_swap_below_minimum_output_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        swap(context(user), state, token_a, 100, 91, 0, 0);
+    }
+}
+
+#[cfg(test)]
+mod output_lock_tests {
+    use super::test_utils::{address, context_at, open_state, with_balance};
+    use crate::{swap, withdraw};
+    use pbc_contract_common::address::AddressType;
+
+    const UNLOCK_AT: i64 = 10_000;
+
+    #[test]
+    #[should_panic(expected = "90 of the balance is locked until 10000")]
+    pub fn test_locked_output_cannot_be_withdrawn() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context_at(user, 0), state, token_a, 100, 0, 0, UNLOCK_AT);
+        withdraw(context_at(user, UNLOCK_AT - 1), state, token_b, 90);
+    }
+
+    #[test]
+    #[should_panic(expected = "90 of the balance is locked until 10000")]
+    pub fn test_locked_output_cannot_be_swapped() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context_at(user, 0), state, token_a, 100, 0, 0, UNLOCK_AT);
+        swap(context_at(user, 1), state, token_b, 90, 0, 0, 0);
+    }
+
+    #[test]
+    pub fn test_locked_output_can_be_withdrawn_after_unlock() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context_at(user, 0), state, token_a, 100, 0, 0, UNLOCK_AT);
+        let (state, events) = withdraw(context_at(user, UNLOCK_AT), state, token_b, 90);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+    }
+
+    #[test]
+    pub fn test_unlocked_part_of_balance_can_be_withdrawn() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 30);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context_at(user, 0), state, token_a, 100, 0, 0, UNLOCK_AT);
+        let (state, _) = withdraw(context_at(user, 1), state, token_b, 30);
+
+        assert_eq!(state.user_balances[&user].pool_b_balance, 90);
+    }
+}
+
+#[cfg(test)]
+mod add_liquidity_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{add_liquidity, swap};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_proportional_liquidity_preserves_spot_price() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 2000), user, 100, 200);
+
+        let (state, _) = add_liquidity(context(user), state, 100, 200);
+
+        assert_eq!(state.token_pool_a.pool, 1100);
+        assert_eq!(state.token_pool_b.pool, 2200);
+        assert_eq!(state.swap_constant, 1100 * 2200);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 0);
+        // sqrt(1000 * 2000) = 1414 initial shares, of which 100 / 1000 are minted
+        assert_eq!(state.lp_shares[&user], 141);
+        assert_eq!(state.total_shares, 1414 + 141);
+    }
+
+    #[test]
+    pub fn test_excess_stays_in_balance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 500);
+
+        let (state, _) = add_liquidity(context(user), state, 100, 500);
+
+        assert_eq!(state.token_pool_a.pool, 1100);
+        assert_eq!(state.token_pool_b.pool, 1100);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 400);
+        assert_eq!(state.lp_shares[&user], 100);
+    }
+
+    #[test]
+    pub fn test_added_liquidity_deepens_the_pools() {
+        let owner = address(AddressType::Account, 1);
+        let provider = address(AddressType::Account, 2);
+        let trader = address(AddressType::Account, 3);
+        let shallow = with_balance(open_state(owner, 1000, 1000), trader, 100, 0);
+        let token_a = shallow.token_pool_a.token_address;
+        let deep = with_balance(open_state(owner, 1000, 1000), trader, 100, 0);
+        let deep = with_balance(deep, provider, 1000, 1000);
+        let (deep, _) = add_liquidity(context(provider), deep, 1000, 1000);
+
+        let (shallow, _) = swap(context(trader), shallow, token_a, 100, 0, 0, 0);
+        let (deep, _) = swap(context(trader), deep, token_a, 100, 0, 0, 0);
+
+        // 1000 - ceil(1000000 / 1100) = 90 against 2000 - ceil(4000000 / 2100) = 95
+        assert_eq!(shallow.user_balances[&trader].pool_b_balance, 90);
+        assert_eq!(deep.user_balances[&trader].pool_b_balance, 95);
+    }
+
+    #[test]
+    #[should_panic(expected = "Liquidity is too small to mint a share")]
+    pub fn test_dust_liquidity_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+
+        add_liquidity(context(user), state, 100, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add liquidity when the contract is closed")]
+    pub fn test_closed_contract_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 100, 100);
+        state.is_closed = true;
+
+        add_liquidity(context(user), state, 100, 100);
+    }
+}
+
+#[cfg(test)]
+mod remove_liquidity_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{add_liquidity, remove_liquidity, swap};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_two_providers_get_their_share_of_the_fees() {
+        let owner = address(AddressType::Account, 1);
+        let provider = address(AddressType::Account, 2);
+        let trader = address(AddressType::Account, 3);
+        let mut state = with_balance(open_state(owner, 1000, 1000), provider, 1000, 1000);
+        state.fee_per_mille = 3;
+        let state = with_balance(state, trader, 200, 0);
+        let (token_a, token_b) = (
+            state.token_pool_a.token_address,
+            state.token_pool_b.token_address,
+        );
+        let (state, _) = add_liquidity(context(provider), state, 1000, 1000);
+
+        // A round trip leaves its fees in the pools: 2000 / 2000 becomes 2004 / 2000
+        let (state, _) = swap(context(trader), state, token_a, 200, 0, 0, 0);
+        let (state, _) = swap(context(trader), state, token_b, 180, 0, 0, 0);
+        assert_eq!(state.token_pool_a.pool, 2004);
+        assert_eq!(state.token_pool_b.pool, 2000);
+
+        let (state, _) = remove_liquidity(context(provider), state, 1000);
+        assert_eq!(state.user_balances[&provider].pool_a_balance, 1002);
+        assert_eq!(state.user_balances[&provider].pool_b_balance, 1000);
+        assert!(!state.lp_shares.contains_key(&provider));
+
+        let (state, _) = remove_liquidity(context(owner), state, 1000);
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 1002);
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 1000);
+        assert_eq!(state.total_shares, 0);
+        assert!(state.is_closed);
+    }
+
+    #[test]
+    pub fn test_partial_removal_keeps_spot_price() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1000, 4000);
+
+        // sqrt(1000 * 4000) = 2000 shares, of which a quarter is removed
+        let (state, _) = remove_liquidity(context(owner), state, 500);
+
+        assert_eq!(state.token_pool_a.pool, 750);
+        assert_eq!(state.token_pool_b.pool, 3000);
+        assert_eq!(state.swap_constant, 750 * 3000);
+        assert_eq!(state.lp_shares[&owner], 1500);
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 250);
+        assert!(!state.is_closed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot remove 1 shares, only 0 are held")]
+    pub fn test_remove_without_shares_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+
+        remove_liquidity(context(user), open_state(owner, 1000, 1000), 1);
+    }
+}
+
+#[cfg(test)]
+mod simulate_swap_tests {
+    use super::test_utils::{address, context, context_at, open_state, with_balance};
+    use crate::{swap, SwapSimulation};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_simulation_matches_swap() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = 3;
+        let token_a = state.token_pool_a.token_address;
+
+        let simulation = state.simulate_swap(user, token_a, 1_000, 0);
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+
+        assert_eq!(
+            simulation,
+            SwapSimulation {
+                output: state.user_balances[&user].pool_b_balance,
+                failure: None,
+            }
+        );
+        assert_eq!(simulation.output, 906);
+    }
+
+    #[test]
+    pub fn test_simulation_reports_guard_failure() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 101, 0);
+        state.max_swap_bps = 1000;
+        let token_a = state.token_pool_a.token_address;
+
+        let simulation = state.simulate_swap(user, token_a, 101, 0);
+
+        assert_eq!(simulation.output, 0);
+        assert_eq!(
+            simulation.failure,
+            Some("Swap input 101 exceeds the maximum of 100".to_string())
+        );
+        assert_eq!(state.token_pool_a.pool, 1000);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 101);
+    }
+
+    #[test]
+    pub fn test_simulation_reports_insufficient_funds() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 50, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let simulation = state.simulate_swap(user, token_a, 100, 0);
+
+        assert_eq!(simulation.failure, Some("Insufficient funds".to_string()));
+    }
+
+    #[test]
+    pub fn test_simulation_reports_locked_balance() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        let (token_a, token_b) = (
+            state.token_pool_a.token_address,
+            state.token_pool_b.token_address,
+        );
+        let (state, _) = swap(context_at(user, 0), state, token_a, 100, 0, 0, 1000);
+
+        let locked = state.simulate_swap(user, token_b, 90, 999);
+        let unlocked = state.simulate_swap(user, token_b, 90, 1000);
+
+        assert_eq!(
+            locked.failure,
+            Some("90 of the balance is locked until 1000".to_string())
+        );
+        assert_eq!(unlocked.failure, None);
+    }
+
+    #[test]
+    pub fn test_simulation_reports_closed_contract() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 1000, 1000), user, 100, 0);
+        state.is_closed = true;
+        let token_a = state.token_pool_a.token_address;
+
+        let simulation = state.simulate_swap(user, token_a, 100, 0);
+
+        assert_eq!(
+            simulation.failure,
+            Some("Cannot make a swap when the contract is closed".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod spot_price_tests {
+    use super::test_utils::{address, context, open_state};
+    use crate::query_spot_price;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_spot_price_a_to_b() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1000, 2500);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, events) = query_spot_price(context(owner), state, token_a);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.spot_price(token_a), 2_500_000);
+    }
+
+    #[test]
+    pub fn test_spot_price_b_to_a() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1000, 2500);
+        let token_b = state.token_pool_b.token_address;
+
+        assert_eq!(state.spot_price(token_b), 400_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pools not initialized")]
+    pub fn test_spot_price_of_closed_contract_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let mut state = open_state(owner, 0, 0);
+        state.is_closed = true;
+        let token_a = state.token_pool_a.token_address;
+
+        state.spot_price(token_a);
+    }
+}
+
+#[cfg(test)]
+mod constant_product_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::swap;
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_invariant_holds_at_rounding_boundaries() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        // 1000 * 999 leaves every remainder modulo the new from-pools 1001..=1050
+        for amount in 1..=50 {
+            let state = with_balance(open_state(owner, 1000, 999), user, amount, 0);
+            let swap_constant = state.swap_constant;
+            let token_a = state.token_pool_a.token_address;
+
+            let (state, _) = swap(context(user), state, token_a, amount, 0, 0, 0);
+
+            let product = u128::from(state.token_pool_a.pool) * u128::from(state.token_pool_b.pool);
+            assert!(product >= swap_constant);
+            assert_eq!(state.swap_constant, product);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Pools 1000 and 1000 break the constant product invariant 1000001")]
+    pub fn test_broken_invariant_detected() {
+        let owner = address(AddressType::Account, 1);
+        let mut state = open_state(owner, 1000, 1000);
+        state.swap_constant += 1;
+
+        state.assert_constant_product();
+    }
+}
+
+#[cfg(test)]
+mod deposit_tests {
+    use super::test_utils::{address, callback_context, context, open_state};
+    use crate::{deposit, deposit_callback};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_deposit_credits_pending_intent() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, events) = deposit(context(user), state, token_b, 50);
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.pending_deposits.len(), 1);
+        let (state, _) = deposit_callback(context(user), callback_context(true), state, 0);
+
+        assert_eq!(state.user_balances[&user].pool_b_balance, 50);
+        assert!(state.pending_deposits.is_empty());
+    }
+
+    #[test]
+    pub fn test_concurrent_deposits_use_distinct_nonces() {
+        let owner = address(AddressType::Account, 1);
+        let (alice, bob) = (
+            address(AddressType::Account, 2),
+            address(AddressType::Account, 3),
+        );
+        let state = open_state(owner, 1000, 1000);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = deposit(context(alice), state, token_a, 10);
+        let (state, _) = deposit(context(bob), state, token_a, 20);
+        let (state, _) = deposit_callback(context(bob), callback_context(true), state, 1);
+        let (state, _) = deposit_callback(context(alice), callback_context(true), state, 0);
+
+        assert_eq!(state.user_balances[&alice].pool_a_balance, 10);
+        assert_eq!(state.user_balances[&bob].pool_a_balance, 20);
+    }
+
+    #[test]
+    pub fn test_failed_transfer_clears_intent_without_credit() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = deposit(context(user), state, token_a, 50);
+        let (state, _) = deposit_callback(context(user), callback_context(false), state, 0);
+
+        assert!(!state.user_balances.contains_key(&user));
+        assert!(state.pending_deposits.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "No pending deposit matches the callback")]
+    pub fn test_callback_without_intent_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+
+        deposit_callback(
+            context(user),
+            callback_context(true),
+            open_state(owner, 1000, 1000),
+            0,
+        );
+    }
 
-        let div1 = u64_division_ceil(10, 2);
-        let div2 = u64_division_ceil(999, 66);
-        let div3 = u64_division_ceil(15, 4);
+    #[test]
+    #[should_panic(expected = "No pending deposit matches the callback")]
+    pub fn test_callback_replay_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 1000, 1000);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = deposit(context(user), state, token_a, 50);
+        let (state, _) = deposit_callback(context(user), callback_context(true), state, 0);
+        deposit_callback(context(user), callback_context(true), state, 0);
+    }
+}
+
+#[cfg(test)]
+mod protocol_fee_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{collect_protocol_fees, set_protocol_fee, swap};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_fees_split_between_pool_and_protocol() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let recipient = address(AddressType::Account, 3);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = 3;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = set_protocol_fee(context(owner), state, 2, recipient);
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+
+        // 2 of the 1000 input go to the protocol, the output is priced on 998 * 997 / 1000 = 995 of the rest:
+        // 10000 - ceil(100000000 / 10995) = 904
+        assert_eq!(state.protocol_fees[&token_a], 2);
+        assert_eq!(state.token_pool_a.pool, 10_998);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 904);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 0);
+        assert_eq!(state.swap_constant, 10_998 * 9_096);
+    }
+
+    #[test]
+    pub fn test_fees_accrue_per_token() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 1_000);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = set_protocol_fee(context(owner), state, 10, owner);
+        let (state, _) = swap(context(user), state, token_a, 500, 0, 0, 0);
+        let (state, _) = swap(context(user), state, token_a, 500, 0, 0, 0);
+        let (state, _) = swap(context(user), state, token_b, 300, 0, 0, 0);
+
+        assert_eq!(state.protocol_fees[&token_a], 10);
+        assert_eq!(state.protocol_fees[&token_b], 3);
+    }
+
+    #[test]
+    pub fn test_collect_transfers_fees_to_recipient() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let recipient = address(AddressType::Account, 3);
+        let state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = set_protocol_fee(context(owner), state, 2, recipient);
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+        let (state, events) = collect_protocol_fees(context(recipient), state, token_a);
+
+        assert_eq!(events.len(), 1);
+        assert!(state.protocol_fees.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the fee recipient can collect the protocol fees")]
+    pub fn test_collect_by_non_recipient_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let recipient = address(AddressType::Account, 3);
+        let state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = set_protocol_fee(context(owner), state, 2, recipient);
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+        collect_protocol_fees(context(owner), state, token_a);
+    }
+
+    #[test]
+    #[should_panic(expected = "No protocol fees to collect")]
+    pub fn test_collect_without_fees_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 10_000, 10_000);
+        let token_a = state.token_pool_a.token_address;
+
+        collect_protocol_fees(context(owner), state, token_a);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can set the protocol fee")]
+    pub fn test_set_protocol_fee_by_non_owner_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+
+        set_protocol_fee(context(user), open_state(owner, 10_000, 10_000), 2, user);
+    }
+}
+
+#[cfg(test)]
+mod provide_liquidity_both_tests {
+    use super::test_utils::{address, context, verified_state};
+    use crate::{provide_liquidity_both, provide_liquidity_both_callback};
+    use pbc_contract_common::address::AddressType;
+    use pbc_contract_common::context::{CallbackContext, ExecutionResult};
+
+    fn transfers_callback_context(a_succeeded: bool, b_succeeded: bool) -> CallbackContext {
+        let result = |succeeded: bool| ExecutionResult {
+            succeeded,
+            return_data: vec![],
+        };
+        CallbackContext {
+            success: a_succeeded && b_succeeded,
+            results: vec![result(a_succeeded), result(b_succeeded)],
+        }
+    }
+
+    #[test]
+    pub fn test_both_transfers_succeed_opens_pools() {
+        let owner = address(AddressType::Account, 1);
+        let state = verified_state(owner);
+
+        let (state, events) = provide_liquidity_both(context(owner), state, 1_000, 4_000);
+        assert_eq!(events.len(), 1);
+        let (state, events) = provide_liquidity_both_callback(
+            context(owner),
+            transfers_callback_context(true, true),
+            state,
+            1_000,
+            4_000,
+        );
+
+        assert!(events.is_empty());
+        assert!(!state.is_closed);
+        assert_eq!(state.token_pool_a.pool, 1_000);
+        assert_eq!(state.token_pool_b.pool, 4_000);
+        assert_eq!(state.swap_constant, 4_000_000);
+        assert_eq!(state.total_shares, 2_000);
+        assert_eq!(state.lp_shares[&owner], 1_900);
+    }
+
+    #[test]
+    pub fn test_one_transfer_fails_refunds_the_other() {
+        let owner = address(AddressType::Account, 1);
+        let state = verified_state(owner);
+
+        let (state, events) = provide_liquidity_both_callback(
+            context(owner),
+            transfers_callback_context(true, false),
+            state,
+            1_000,
+            4_000,
+        );
+
+        assert_eq!(events.len(), 1);
+        assert!(state.is_closed);
+        assert_eq!(state.token_pool_a.pool, 0);
+        assert_eq!(state.token_pool_b.pool, 0);
+        assert_eq!(state.swap_constant, 0);
+    }
+
+    #[test]
+    pub fn test_both_transfers_fail_leaves_state_unchanged() {
+        let owner = address(AddressType::Account, 1);
+        let state = verified_state(owner);
+
+        let (state, events) = provide_liquidity_both_callback(
+            context(owner),
+            transfers_callback_context(false, false),
+            state,
+            1_000,
+            4_000,
+        );
+
+        assert!(events.is_empty());
+        assert!(state.is_closed);
+        assert_eq!(state.token_pool_a.pool, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can initialize its pools")]
+    pub fn test_non_owner_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+
+        provide_liquidity_both(context(user), verified_state(owner), 1_000, 4_000);
+    }
+}
+
+#[cfg(test)]
+mod min_initial_liquidity_tests {
+    use super::test_utils::{address, callback_context, context, verified_state};
+    use crate::{
+        provide_liquidity, provide_liquidity_both, provide_liquidity_callback, remove_liquidity,
+        LiquiditySwapContractState, Token,
+    };
+    use pbc_contract_common::address::{Address, AddressType};
+
+    fn seed(
+        owner: Address,
+        state: LiquiditySwapContractState,
+        pool_a: u64,
+        pool_b: u64,
+    ) -> LiquiditySwapContractState {
+        let (state, _) = provide_liquidity_callback(
+            context(owner),
+            callback_context(true),
+            state,
+            Token::A,
+            pool_a,
+        );
+        provide_liquidity_callback(
+            context(owner),
+            callback_context(true),
+            state,
+            Token::B,
+            pool_b,
+        )
+        .0
+    }
+
+    #[test]
+    #[should_panic(expected = "Initial pool of 999 is below the minimum initial liquidity of 1000")]
+    pub fn test_below_minimum_initialization_rejected_before_transfer() {
+        let owner = address(AddressType::Account, 1);
+        let state = verified_state(owner);
+        let token_b = state.token_pool_b.token_address;
+
+        provide_liquidity(context(owner), state, token_b, 999);
+    }
+
+    #[test]
+    #[should_panic(expected = "Initial pool of 999 is below the minimum initial liquidity of 1000")]
+    pub fn test_below_minimum_joint_initialization_rejected_before_transfer() {
+        let owner = address(AddressType::Account, 1);
+
+        provide_liquidity_both(context(owner), verified_state(owner), 100_000, 999);
+    }
+
+    #[test]
+    pub fn test_initial_shares_partly_locked() {
+        let owner = address(AddressType::Account, 1);
+        let this_contract = address(AddressType::PublicContract, 0xff);
+
+        let state = seed(owner, verified_state(owner), 1_000, 1_000);
+
+        assert!(!state.is_closed);
+        assert_eq!(state.total_shares, 1_000);
+        assert_eq!(state.lp_shares[&this_contract], 100);
+        assert_eq!(state.lp_shares[&owner], 900);
+    }
+
+    #[test]
+    pub fn test_removing_all_owner_shares_keeps_pools_open() {
+        let owner = address(AddressType::Account, 1);
+        let state = seed(owner, verified_state(owner), 1_000, 4_000);
+
+        let (state, _) = remove_liquidity(context(owner), state, 1_900);
+
+        assert!(!state.is_closed);
+        assert_eq!(state.total_shares, 100);
+        assert_eq!(state.token_pool_a.pool, 50);
+        assert_eq!(state.token_pool_b.pool, 200);
+        assert_eq!(state.swap_constant, 10_000);
+    }
+}
+
+#[cfg(test)]
+mod quote_swap_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{quote_swap, swap};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_quote_matches_swap() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = 3;
+        state.protocol_fee_per_mille = 2;
+        let token_a = state.token_pool_a.token_address;
+
+        let quote = state.quote_swap(token_a, 1_000);
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+
+        assert_eq!(quote, 904);
+        assert_eq!(quote, state.user_balances[&user].pool_b_balance);
+    }
+
+    #[test]
+    pub fn test_quote_in_both_directions() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = with_balance(open_state(owner, 1_000, 4_000), user, 100, 100);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let quote_a = state.quote_swap(token_a, 100);
+        let (state, _) = swap(context(user), state, token_a, 100, 0, 0, 0);
+        assert_eq!(quote_a, state.user_balances[&user].pool_b_balance - 100);
+
+        let quote_b = state.quote_swap(token_b, 100);
+        let (state, _) = swap(context(user), state, token_b, 100, 0, 0, 0);
+        assert_eq!(quote_b, state.user_balances[&user].pool_a_balance);
+    }
+
+    #[test]
+    pub fn test_quote_action_leaves_state_unchanged() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 1_000, 1_000);
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, events) = quote_swap(context(owner), state, token_a, 100);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.token_pool_a.pool, 1_000);
+        assert_eq!(state.token_pool_b.pool, 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot make a swap when the contract is closed")]
+    pub fn test_quote_on_closed_contract_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let mut state = open_state(owner, 1_000, 1_000);
+        state.is_closed = true;
+        let token_a = state.token_pool_a.token_address;
+
+        state.quote_swap(token_a, 100);
+    }
+}
+
+#[cfg(test)]
+mod compound_tests {
+    use super::test_utils::{address, context, open_state, with_balance};
+    use crate::{compound, remove_liquidity, set_auto_compound, swap, LiquiditySwapContractState};
+    use pbc_contract_common::address::{Address, AddressType};
+
+    fn swapped_both_ways(owner: Address, user: Address) -> LiquiditySwapContractState {
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 1_000);
+        state.fee_per_mille = 10;
+        let (state, _) = set_auto_compound(context(owner), state, false);
+        let token_a = state.token_pool_a.token_address;
+        let token_b = state.token_pool_b.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+        let (state, _) = swap(context(user), state, token_b, 1_000, 0, 0, 0);
+        state
+    }
+
+    #[test]
+    pub fn test_fees_are_kept_out_of_the_pools_when_disabled() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = swapped_both_ways(owner, user);
+
+        assert_eq!(state.token_pool_a.pool, 9_912);
+        assert_eq!(state.token_pool_b.pool, 10_090);
+        assert_eq!(state.user_balances[&user].pool_a_balance, 1_078);
+        assert_eq!(state.user_balances[&user].pool_b_balance, 900);
+    }
+
+    #[test]
+    pub fn test_compound_mints_shares_from_accrued_fees() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = swapped_both_ways(owner, user);
+
+        let (state, events) = compound(context(owner), state);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.lp_shares[&owner], 10_009);
+        assert_eq!(state.total_shares, 10_009);
+        assert_eq!(state.token_pool_a.pool, 9_921);
+        assert_eq!(state.token_pool_b.pool, 10_100);
+        assert_eq!(state.accrued_fees[&owner].pool_a_balance, 1);
+        assert_eq!(state.accrued_fees[&owner].pool_b_balance, 0);
+    }
+
+    #[test]
+    pub fn test_remove_liquidity_pays_accrued_fees() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let mut state = with_balance(open_state(owner, 10_000, 10_000), user, 1_000, 0);
+        state.fee_per_mille = 10;
+        state.auto_compound = false;
+        let token_a = state.token_pool_a.token_address;
+
+        let (state, _) = swap(context(user), state, token_a, 1_000, 0, 0, 0);
+        let (state, _) = remove_liquidity(context(owner), state, 1_000);
+
+        assert_eq!(state.user_balances[&owner].pool_a_balance, 1_109);
+        assert_eq!(state.user_balances[&owner].pool_b_balance, 910);
+        assert!(!state.accrued_fees.contains_key(&owner));
+    }
+
+    #[test]
+    #[should_panic(expected = "Accrued fees are too small to mint a share")]
+    pub fn test_compound_without_accrued_fees_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = open_state(owner, 10_000, 10_000);
+
+        compound(context(owner), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the contract owner can set auto-compounding")]
+    pub fn test_set_auto_compound_by_non_owner_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let user = address(AddressType::Account, 2);
+        let state = open_state(owner, 10_000, 10_000);
 
-        assert_eq!(div1, 5);
-        assert_eq!(div2, 16);
-        assert_eq!(div3, 4);
+        set_auto_compound(context(user), state, false);
     }
 }
@@ -3,13 +3,26 @@
 //! ids, such that voters can vote on them. It also supports batch voting allowing you to vote on
 //! multiple voting contracts at once. The contract also keeps a list of eligible voters, which the
 //! owner of the contract can add to and remove from.
+//!
+//! Votes cast through this contract are also recorded locally together with a per-proposal tally,
+//! once their voting contract has accepted them in `batch_vote_callback`. A voter may change their
+//! vote as long as the voting contract accepts it, and the tally follows the latest vote. The tallies
+//! are only published in `revealed_results` once the owner calls `reveal_results` after
+//! `reveal_threshold` proposals have been closed. Note that contract state is readable on chain, so
+//! the recorded votes and tallies are public; this only withholds the aggregate from the published
+//! results. Use the zk-voting contract where the votes themselves must remain secret.
+//!
+//! The results reported by closed voting contracts can be combined into a single decision with
+//! `composite_result`, in which each proposal counts with its configured weight.
 #![allow(unused_variables)]
 
+mod tests;
+
 #[macro_use]
 extern crate pbc_contract_codegen;
 extern crate pbc_contract_common;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use create_type_spec_derive::CreateTypeSpec;
 use pbc_contract_common::address::{Address, AddressType, Shortname};
@@ -27,6 +40,12 @@ const PUB_DEPLOY_ADDRESS: Address = Address {
     ],
 };
 
+/// The number of votes a single `batch_vote` may contain until the owner changes it.
+const DEFAULT_MAX_BATCH_VOTES: u32 = 32;
+
+/// The number of vote calls a single event group of `batch_vote` may contain until the owner changes it.
+const DEFAULT_MAX_VOTES_PER_EVENT_GROUP: u32 = 8;
+
 #[inline]
 fn voting_contract_vote() -> Shortname {
     Shortname::from_be_bytes(&[0xf4, 0x88, 0x9d, 0xd9, 0x0a]).unwrap()
@@ -44,6 +63,18 @@ pub struct Vote {
     vote: u8,
 }
 
+/// The aggregated votes of a single proposal.
+///
+/// ### Fields:
+///
+/// * `yes`: [`u64`], the number of "yes" votes, or their summed weight in a reported result.
+/// * `no`: [`u64`], the number of "no" votes, or their summed weight in a reported result.
+#[derive(ReadWriteRPC, ReadWriteState, CreateTypeSpec, Clone, PartialEq, Debug, Default)]
+pub struct Tally {
+    yes: u64,
+    no: u64,
+}
+
 /// Contract state.
 ///
 /// ### Fields:
@@ -53,6 +84,21 @@ pub struct Vote {
 /// * `voting_contracts`: [`BTreeMap<u64, Option<Address>`], A map from proposal ids to voting contracts.
 /// * `voting_contract_wasm`: [`Vec<u8>`], bytes of the voting contract wasm.
 /// * `voting_contract_abi`: [`Vec<u8>`], bytes of the voting contract abi.
+/// * `recorded_votes`: [`BTreeMap<u64, BTreeMap<Address, u8>>`], the latest accepted vote per proposal and voter.
+/// * `hidden_tallies`: [`BTreeMap<u64, Tally>`], the unpublished tally of each proposal.
+/// * `closed_proposals`: [`BTreeSet<u64>`], the proposals which no longer accept votes.
+/// * `reveal_threshold`: [`u32`], the number of closed proposals required before revealing.
+/// * `revealed_results`: [`BTreeMap<u64, Tally>`], the published tallies.
+/// * `child_results`: [`BTreeMap<u64, Tally>`], the results reported by closed voting contracts.
+/// * `proposal_voters`: [`BTreeMap<u64, Vec<Address>>`], the voters baked into each voting contract.
+/// * `max_batch_votes`: [`u32`], the largest number of votes a single `batch_vote` may contain.
+/// * `paused`: [`bool`], whether deploying voting contracts and voting is suspended.
+/// * `max_votes_per_event_group`: [`u32`], the largest number of vote calls in one event group.
+/// * `min_participants`: [`u32`], the number of voters a proposal needs before it can be deployed.
+/// * `proposal_weights`: [`BTreeMap<u64, u32>`], the weight of each proposal in the composite result.
+/// * `vote_cooldown`: [`i64`], the minimum time in milliseconds between two votes of the same voter.
+/// * `last_vote_times`: [`BTreeMap<Address, i64>`], the block production time of each voter's latest vote.
+/// * `removed_proposals`: [`BTreeSet<u64>`], the proposal ids whose voting contracts were removed.
 #[state]
 pub struct MultiVotingState {
     owner: Address,
@@ -60,6 +106,127 @@ pub struct MultiVotingState {
     voting_contracts: BTreeMap<u64, Option<Address>>,
     voting_contract_wasm: Vec<u8>,
     voting_contract_abi: Vec<u8>,
+    recorded_votes: BTreeMap<u64, BTreeMap<Address, u8>>,
+    hidden_tallies: BTreeMap<u64, Tally>,
+    closed_proposals: BTreeSet<u64>,
+    reveal_threshold: u32,
+    revealed_results: BTreeMap<u64, Tally>,
+    child_results: BTreeMap<u64, Tally>,
+    proposal_voters: BTreeMap<u64, Vec<Address>>,
+    max_batch_votes: u32,
+    paused: bool,
+    max_votes_per_event_group: u32,
+    min_participants: u32,
+    proposal_weights: BTreeMap<u64, u32>,
+    vote_cooldown: i64,
+    last_vote_times: BTreeMap<Address, i64>,
+    removed_proposals: BTreeSet<u64>,
+}
+
+impl MultiVotingState {
+    /// Determines the voters of a new proposal. Without an override all eligible voters can vote,
+    /// otherwise the override must be a non-empty subset of the eligible voters without duplicates.
+    fn voters_for_proposal(&self, voters: Option<Vec<Address>>) -> Vec<Address> {
+        match voters {
+            None => self.eligible_voters.clone(),
+            Some(voters) => {
+                assert!(!voters.is_empty(), "Voter override cannot be empty");
+                let unique: BTreeSet<&Address> = voters.iter().collect();
+                assert_eq!(unique.len(), voters.len(), "Duplicate voter in override");
+                assert!(
+                    voters.iter().all(|v| self.eligible_voters.contains(v)),
+                    "Voter override must be a subset of the eligible voters"
+                );
+                voters
+            }
+        }
+    }
+
+    /// Adds `voter` to the eligible voters, panicking if they are already eligible.
+    fn add_eligible_voter(&mut self, voter: Address) {
+        let voter_exists = self.eligible_voters.iter().any(|x| *x == voter);
+        if voter_exists {
+            panic!("Voter already exists");
+        }
+        self.eligible_voters.push(voter);
+    }
+
+    /// Removes `voter` from the eligible voters, panicking if they are not eligible.
+    fn remove_eligible_voter(&mut self, voter: Address) {
+        let index = self
+            .eligible_voters
+            .iter()
+            .position(|x| *x == voter)
+            .expect("Voter does not exist");
+        self.eligible_voters.remove(index);
+    }
+
+    /// Checks whether `voter` can vote on the proposal. Every deployed proposal only accepts the
+    /// voters baked into its voting contract, whether or not they were given as an override.
+    /// Proposals without recorded voters, which are unknown or removed, fall back to the eligible
+    /// voters so that the missing voting contract is reported instead.
+    fn can_vote_on(&self, voter: &Address, proposal_id: u64) -> bool {
+        match self.proposal_voters.get(&proposal_id) {
+            Some(voters) => voters.contains(voter),
+            None => self.eligible_voters.contains(voter),
+        }
+    }
+
+    /// Records the vote of `voter` and adds it to the hidden tally of the proposal. A previous vote
+    /// of the voter on the proposal is replaced and removed from the tally.
+    fn record_vote(&mut self, voter: Address, vote: &Vote) {
+        let previous = self
+            .recorded_votes
+            .entry(vote.proposal_id)
+            .or_default()
+            .insert(voter, vote.vote);
+
+        let tally = self.hidden_tallies.entry(vote.proposal_id).or_default();
+        match previous {
+            Some(1) => tally.yes -= 1,
+            Some(_) => tally.no -= 1,
+            None => {}
+        }
+        if vote.vote == 1 {
+            tally.yes += 1;
+        } else {
+            tally.no += 1;
+        }
+    }
+
+    /// The weight of a proposal in the composite result. Proposals without a configured weight
+    /// weigh one.
+    fn weight_of(&self, proposal_id: u64) -> u64 {
+        self.proposal_weights
+            .get(&proposal_id)
+            .copied()
+            .map_or(1, u64::from)
+    }
+
+    /// Combines the results reported by closed voting contracts into an overall yes/no. A proposal
+    /// passed if its "yes" votes outweighed its "no" votes, and the composite result is "yes" if the
+    /// passed proposals outweigh the rejected ones.
+    ///
+    /// ### Returns:
+    /// `Some(true)` for an overall "yes", `Some(false)` for an overall "no" and `None` if no
+    /// results have been reported.
+    pub fn composite_result(&self) -> Option<bool> {
+        if self.child_results.is_empty() {
+            return None;
+        }
+        let (passed, rejected) = self.child_results.iter().fold(
+            (0u64, 0u64),
+            |(passed, rejected), (proposal_id, tally)| {
+                let weight = self.weight_of(*proposal_id);
+                if tally.yes > tally.no {
+                    (passed + weight, rejected)
+                } else {
+                    (passed, rejected + weight)
+                }
+            },
+        );
+        Some(passed > rejected)
+    }
 }
 
 /// Initial function to create the initial state.
@@ -85,6 +252,21 @@ pub fn initialize(
         voting_contracts: BTreeMap::new(),
         voting_contract_wasm,
         voting_contract_abi,
+        recorded_votes: BTreeMap::new(),
+        hidden_tallies: BTreeMap::new(),
+        closed_proposals: BTreeSet::new(),
+        reveal_threshold: 0,
+        revealed_results: BTreeMap::new(),
+        child_results: BTreeMap::new(),
+        proposal_voters: BTreeMap::new(),
+        max_batch_votes: DEFAULT_MAX_BATCH_VOTES,
+        paused: false,
+        max_votes_per_event_group: DEFAULT_MAX_VOTES_PER_EVENT_GROUP,
+        min_participants: 0,
+        proposal_weights: BTreeMap::new(),
+        vote_cooldown: 0,
+        last_vote_times: BTreeMap::new(),
+        removed_proposals: BTreeSet::new(),
     };
 
     (state, vec![])
@@ -108,16 +290,13 @@ pub fn add_voter(
     voter: Address,
 ) -> (MultiVotingState, Vec<EventGroup>) {
     assert_eq!(ctx.sender, state.owner, "Only owner can add voters");
-    let voter_exists = state.eligible_voters.iter().any(|x| *x == voter);
-    if voter_exists {
-        panic!("Voter already exists");
-    }
     let mut new_state = state;
-    new_state.eligible_voters.push(voter);
+    new_state.add_eligible_voter(voter);
     (new_state, vec![])
 }
 
-/// Removes a voter from eligible voters. This voter can no longer vote on voting contracts.
+/// Removes a voter from eligible voters. This voter can no longer vote on voting contracts deployed
+/// afterwards, while the voters of already deployed voting contracts stay fixed.
 /// Only the owner of the contract can remove voters.
 ///
 /// ### Parameters:
@@ -136,17 +315,79 @@ pub fn remove_voter(
 ) -> (MultiVotingState, Vec<EventGroup>) {
     assert_eq!(ctx.sender, state.owner, "Only owner can remove voters");
     let mut new_state = state;
-    let index = new_state
-        .eligible_voters
-        .iter()
-        .position(|x| *x == voter)
-        .expect("Voter does not exist");
-    new_state.eligible_voters.remove(index);
+    new_state.remove_eligible_voter(voter);
+    (new_state, vec![])
+}
+
+/// Adds several voters to eligible voters at once. The whole batch is rejected before any voter
+/// is added if it contains a duplicate or a voter who is already eligible. Only the owner of the
+/// contract can add voters.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the action call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `voters`: [`Vec<Address>`], the voters to be added.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn add_voters(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    voters: Vec<Address>,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can add voters");
+    assert_unique_batch(&voters);
+    if voters.iter().any(|v| state.eligible_voters.contains(v)) {
+        panic!("Voter already exists");
+    }
+    let mut new_state = state;
+    for voter in voters {
+        new_state.add_eligible_voter(voter);
+    }
+    (new_state, vec![])
+}
+
+/// Removes several voters from eligible voters at once. The whole batch is rejected before any
+/// voter is removed if it contains a duplicate or a voter who is not eligible. Only the owner of
+/// the contract can remove voters.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the action call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `voters`: [`Vec<Address>`], the voters to be removed.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn remove_voters(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    voters: Vec<Address>,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can remove voters");
+    assert_unique_batch(&voters);
+    assert!(
+        voters.iter().all(|v| state.eligible_voters.contains(v)),
+        "Voter does not exist"
+    );
+    let mut new_state = state;
+    for voter in voters {
+        new_state.remove_eligible_voter(voter);
+    }
     (new_state, vec![])
 }
 
+/// Asserts that a batch of voters contains no address more than once.
+fn assert_unique_batch(voters: &[Address]) {
+    let unique: BTreeSet<&Address> = voters.iter().collect();
+    assert_eq!(unique.len(), voters.len(), "Duplicate voter in batch");
+}
+
 /// Deploys a new voting contract with given proposal id. The voting contract is deployed with
-/// eligible voters as those who can vote. The address of the new voting contract is computed
+/// eligible voters as those who can vote, unless a subset of them is given in `voters`. The address of the new voting contract is computed
 /// from the original transaction hash. Only the owner can add new voting contracts, and the
 /// proposal id has to be unique.
 /// This creates an event to the public deploy contract as well as creates a callback to
@@ -157,6 +398,7 @@ pub fn remove_voter(
 /// * `ctx`: [`ContractContext`], the context of the action call.
 /// * `state`: [`MultiVotingState`], the state before the call.
 /// * `p_id`: [`u64`], the proposal id of the new voting contract.
+/// * `voters`: [`Option<Vec<Address>>`], an optional subset of the eligible voters who decide the proposal.
 ///
 /// ### Returns:
 /// The new state of type [`MultiVotingState`].
@@ -165,12 +407,25 @@ pub fn add_voting_contract(
     ctx: ContractContext,
     state: MultiVotingState,
     p_id: u64,
+    voters: Option<Vec<Address>>,
 ) -> (MultiVotingState, Vec<EventGroup>) {
     assert_eq!(ctx.sender, state.owner, "Only owner can add contracts");
+    assert!(!state.paused, "The contract is paused");
     if state.voting_contracts.contains_key(&p_id) {
         panic!("Proposal id already exists");
     }
+    assert!(
+        !state.removed_proposals.contains(&p_id),
+        "Proposal id {} was removed and cannot be reused",
+        p_id
+    );
 
+    let proposal_voters = state.voters_for_proposal(voters);
+    assert!(
+        proposal_voters.len() >= state.min_participants as usize,
+        "A proposal needs at least {} voters",
+        state.min_participants
+    );
     let mut new_state = state;
 
     new_state.voting_contracts.insert(p_id, None);
@@ -187,8 +442,13 @@ pub fn add_voting_contract(
         .from_original_sender()
         .argument(new_state.voting_contract_wasm.clone())
         .argument(new_state.voting_contract_abi.clone())
-        .argument(create_voting_init_bytes(p_id, &new_state.eligible_voters))
+        .argument(create_voting_init_bytes(
+            p_id,
+            &proposal_voters,
+            ctx.contract_address,
+        ))
         .done();
+    new_state.proposal_voters.insert(p_id, proposal_voters);
 
     event_group
         .with_callback(SHORTNAME_ADD_VOTING_CONTRACT_CALLBACK)
@@ -227,6 +487,7 @@ pub fn add_voting_contract_callback(
     let mut new_state = state;
     if !callback_ctx.results[0].succeeded {
         new_state.voting_contracts.remove(&p_id);
+        new_state.proposal_voters.remove(&p_id);
         (new_state, vec![])
     } else {
         let mut bytes: Vec<u8> = vec![0x02];
@@ -266,6 +527,7 @@ pub fn voting_contract_exists_callback(
     let mut new_state = state;
     if !callback_ctx.results[0].succeeded {
         new_state.voting_contracts.remove(&p_id);
+        new_state.proposal_voters.remove(&p_id);
     } else {
         new_state
             .voting_contracts
@@ -297,7 +559,12 @@ pub fn vote(
 }
 
 /// Vote on on multiple contract at once. This sends a vote event to each of the voting
-/// contracts stored in `voting_contract` with the proposal ids.
+/// contracts stored in `voting_contract` with the proposal ids. Each vote accepted by its voting
+/// contract is recorded in the hidden tally of its proposal by `batch_vote_callback`.
+/// A batch may contain at most `max_batch_votes` votes.
+/// The vote events are split, in order, across event groups of at most
+/// `max_votes_per_event_group` calls each. A voter must wait `vote_cooldown` after their previous
+/// vote before voting again.
 ///
 /// ### Parameters:
 ///
@@ -306,32 +573,458 @@ pub fn vote(
 /// * `votes`: [`Vec<Vote>`], the votes.
 ///
 /// ### Returns:
-/// The unchanged state of type [`MultiVotingState`].
+/// The new state of type [`MultiVotingState`].
 #[action]
 pub fn batch_vote(
     ctx: ContractContext,
     state: MultiVotingState,
     votes: Vec<Vote>,
 ) -> (MultiVotingState, Vec<EventGroup>) {
-    let mut event_group = EventGroup::builder();
-    for vote in votes {
-        let voting_contract = state
-            .voting_contracts
-            .get(&vote.proposal_id)
-            .expect("Voting contract did not exist")
-            .expect("Voting contract did not exist");
+    assert!(!state.paused, "The contract is paused");
+    assert!(
+        votes.len() <= state.max_batch_votes as usize,
+        "A batch can contain at most {} votes",
+        state.max_batch_votes
+    );
+    if let Some(last_vote_time) = state.last_vote_times.get(&ctx.sender) {
+        assert!(
+            ctx.block_production_time >= last_vote_time + state.vote_cooldown,
+            "Voter must wait until {} before voting again",
+            last_vote_time + state.vote_cooldown
+        );
+    }
+    let mut new_state = state;
+    new_state
+        .last_vote_times
+        .insert(ctx.sender, ctx.block_production_time);
+    let chunk_size = new_state.max_votes_per_event_group as usize;
+    let mut event_groups = vec![];
+    for chunk in votes.chunks(chunk_size) {
+        let mut event_group = EventGroup::builder();
+        for vote in chunk {
+            assert!(
+                new_state.can_vote_on(&ctx.sender, vote.proposal_id),
+                "Only eligible voters can vote"
+            );
+            assert!(
+                !new_state.closed_proposals.contains(&vote.proposal_id),
+                "Proposal {} is closed",
+                vote.proposal_id
+            );
+            assert!(
+                vote.vote == 0 || vote.vote == 1,
+                "Only \"yes\" and \"no\" votes are allowed"
+            );
+            let voting_contract = new_state
+                .voting_contracts
+                .get(&vote.proposal_id)
+                .expect("Voting contract did not exist")
+                .expect("Voting contract did not exist");
+            event_group
+                .call(voting_contract, voting_contract_vote())
+                .from_original_sender()
+                .argument(vote.vote)
+                .done();
+        }
         event_group
-            .call(voting_contract, voting_contract_vote())
-            .from_original_sender()
-            .argument(vote.vote)
+            .with_callback(SHORTNAME_BATCH_VOTE_CALLBACK)
+            .argument(ctx.sender)
+            .argument(chunk.to_vec())
             .done();
+        event_groups.push(event_group.build());
     }
+    (new_state, event_groups)
+}
+
+/// Callback for the votes of `batch_vote`. Each vote whose call to its voting contract succeeded is
+/// recorded in the hidden tally of its proposal, replacing any previous vote of `voter`.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `callback_ctx`: [`CallbackContext`], the context of the callback.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `voter`: [`Address`], the voter who cast the votes.
+/// * `votes`: [`Vec<Vote>`], the votes of the event group, in the order of their calls.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[callback(shortname = 0x03)]
+pub fn batch_vote_callback(
+    ctx: ContractContext,
+    callback_ctx: CallbackContext,
+    state: MultiVotingState,
+    voter: Address,
+    votes: Vec<Vote>,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    let mut new_state = state;
+    for (vote, result) in votes.iter().zip(callback_ctx.results.iter()) {
+        if result.succeeded {
+            new_state.record_vote(voter, vote);
+        }
+    }
+    (new_state, vec![])
+}
+
+/// Closes a proposal in the registry such that no more votes can be cast on it through this contract.
+/// Only the owner can close proposals.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `proposal_id`: [`u64`], the proposal id to close.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn close_proposal(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    proposal_id: u64,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can close proposals");
+    assert!(
+        state.voting_contracts.contains_key(&proposal_id),
+        "Proposal id does not exist"
+    );
+    let mut new_state = state;
+    new_state.closed_proposals.insert(proposal_id);
+    (new_state, vec![])
+}
+
+/// Removes a deployed voting contract from the registry, such that it can no longer be voted on
+/// through this contract. The tallies and results of the proposal are kept, so the proposal id
+/// is retired and cannot be added again. Only the owner can remove voting contracts, and only
+/// once their deployment has completed, as the deploy callbacks would otherwise record the
+/// contract again.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `p_id`: [`u64`], the proposal id of the voting contract to remove.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn remove_voting_contract(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    p_id: u64,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can remove contracts");
+    let voting_contract = state
+        .voting_contracts
+        .get(&p_id)
+        .expect("Proposal id does not exist");
+    assert!(
+        voting_contract.is_some(),
+        "Voting contract is still being deployed"
+    );
+    let mut new_state = state;
+    new_state.voting_contracts.remove(&p_id);
+    new_state.proposal_voters.remove(&p_id);
+    new_state.removed_proposals.insert(p_id);
+    (new_state, vec![])
+}
+
+/// Sets the largest number of votes a single `batch_vote` may contain.
+/// Only the owner can set the cap.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `max_batch_votes`: [`u32`], the new cap, at least one.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_max_batch_votes(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    max_batch_votes: u32,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can set the batch cap");
+    assert!(max_batch_votes > 0, "The batch cap must be at least one");
+    let mut new_state = state;
+    new_state.max_batch_votes = max_batch_votes;
+    (new_state, vec![])
+}
+
+/// Sets the largest number of vote calls a single event group of `batch_vote` may contain.
+/// Only the owner can set the limit.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `max_votes_per_event_group`: [`u32`], the new limit, at least one.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_max_votes_per_event_group(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    max_votes_per_event_group: u32,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(
+        ctx.sender, state.owner,
+        "Only owner can set the event group size"
+    );
+    assert!(
+        max_votes_per_event_group > 0,
+        "The event group size must be at least one"
+    );
+    let mut new_state = state;
+    new_state.max_votes_per_event_group = max_votes_per_event_group;
+    (new_state, vec![])
+}
+
+/// Sets the number of voters a proposal needs before its voting contract can be deployed, so no
+/// deploys are wasted on proposals too few voters can decide. Only the owner can set the threshold.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `min_participants`: [`u32`], the new threshold, zero to disable it.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_min_participants(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    min_participants: u32,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(
+        ctx.sender, state.owner,
+        "Only owner can set the participant threshold"
+    );
+    let mut new_state = state;
+    new_state.min_participants = min_participants;
+    (new_state, vec![])
+}
+
+/// Sets the weight of a proposal in the composite result. Only the owner can set weights.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `proposal_id`: [`u64`], the proposal id to weigh.
+/// * `weight`: [`u32`], the new weight of the proposal.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_proposal_weight(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    proposal_id: u64,
+    weight: u32,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(
+        ctx.sender, state.owner,
+        "Only owner can set proposal weights"
+    );
+    assert!(
+        state.voting_contracts.contains_key(&proposal_id),
+        "Proposal id does not exist"
+    );
+    let mut new_state = state;
+    new_state.proposal_weights.insert(proposal_id, weight);
+    (new_state, vec![])
+}
+
+/// Reports the [`composite_result`](MultiVotingState::composite_result) of the reported proposal
+/// results as the return data of the call. The state is unchanged.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+///
+/// ### Returns:
+/// The unchanged state of type [`MultiVotingState`] and an event returning the composite result.
+#[action]
+pub fn query_composite_result(
+    ctx: ContractContext,
+    state: MultiVotingState,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    let mut event_group = EventGroup::builder();
+    event_group.return_data(state.composite_result());
     (state, vec![event_group.build()])
 }
 
-fn create_voting_init_bytes(proposal_id: u64, voters: &Vec<Address>) -> Vec<u8> {
+/// Sets the minimum time between two votes of the same voter, to slow down automated voting.
+/// Only the owner can set the cooldown.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `vote_cooldown`: [`i64`], the new cooldown in milliseconds, zero to disable it.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_vote_cooldown(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    vote_cooldown: i64,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(
+        ctx.sender, state.owner,
+        "Only owner can set the vote cooldown"
+    );
+    assert!(vote_cooldown >= 0, "The vote cooldown cannot be negative");
+    let mut new_state = state;
+    new_state.vote_cooldown = vote_cooldown;
+    (new_state, vec![])
+}
+
+/// Pauses or resumes deploying voting contracts and voting, for instance while a bug in the voting
+/// contract wasm is investigated. Managing voters remains possible while paused.
+/// Only the owner can pause the contract.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `paused`: [`bool`], whether the contract should be paused.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_paused(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    paused: bool,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can pause the contract");
+    let mut new_state = state;
+    new_state.paused = paused;
+    (new_state, vec![])
+}
+
+/// Sets the number of closed proposals required before results can be revealed.
+/// Only the owner can set the threshold.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `reveal_threshold`: [`u32`], the new threshold.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn set_reveal_threshold(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    reveal_threshold: u32,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(
+        ctx.sender, state.owner,
+        "Only owner can set the reveal threshold"
+    );
+    let mut new_state = state;
+    new_state.reveal_threshold = reveal_threshold;
+    (new_state, vec![])
+}
+
+/// Publishes the tallies of all closed proposals into `revealed_results`. Only the owner can
+/// reveal results, and only once at least `reveal_threshold` proposals have been closed.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action]
+pub fn reveal_results(
+    ctx: ContractContext,
+    state: MultiVotingState,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    assert_eq!(ctx.sender, state.owner, "Only owner can reveal results");
+    assert!(
+        state.closed_proposals.len() >= state.reveal_threshold as usize,
+        "Only {} of the required {} proposals have closed",
+        state.closed_proposals.len(),
+        state.reveal_threshold
+    );
+    let mut new_state = state;
+    for proposal_id in new_state.closed_proposals.iter() {
+        let tally = new_state
+            .hidden_tallies
+            .get(proposal_id)
+            .cloned()
+            .unwrap_or_default();
+        new_state.revealed_results.insert(*proposal_id, tally);
+    }
+    (new_state, vec![])
+}
+
+/// Receives the result of a deployed voting contract when it closes. The result is recorded in
+/// `child_results` and the proposal is marked as closed. Only the voting contract registered for
+/// the proposal can report its result.
+///
+/// ### Parameters:
+///
+/// * `ctx`: [`ContractContext`], the context of the call.
+/// * `state`: [`MultiVotingState`], the state before the call.
+/// * `proposal_id`: [`u64`], the proposal id of the closed voting contract.
+/// * `yes`: [`u64`], the summed weight of the "yes" votes.
+/// * `no`: [`u64`], the summed weight of the "no" votes.
+///
+/// ### Returns:
+/// The new state of type [`MultiVotingState`].
+#[action(shortname = 0x30)]
+pub fn child_result_callback(
+    ctx: ContractContext,
+    state: MultiVotingState,
+    proposal_id: u64,
+    yes: u64,
+    no: u64,
+) -> (MultiVotingState, Vec<EventGroup>) {
+    let voting_contract = state.voting_contracts.get(&proposal_id).copied().flatten();
+    assert_eq!(
+        voting_contract,
+        Some(ctx.sender),
+        "Only the voting contract of the proposal can report its result"
+    );
+    let mut new_state = state;
+    new_state
+        .child_results
+        .insert(proposal_id, Tally { yes, no });
+    new_state.closed_proposals.insert(proposal_id);
+    (new_state, vec![])
+}
+
+/// Creates the init bytes of a voting contract titled "Proposal <id>" in which every voter weighs
+/// the same and must vote. The voting contract accepts votes immediately and reports its result
+/// to `result_listener` through `child_result_callback` when it closes.
+fn create_voting_init_bytes(
+    proposal_id: u64,
+    voters: &Vec<Address>,
+    result_listener: Address,
+) -> Vec<u8> {
     let mut bytes: Vec<u8> = vec![0xff, 0xff, 0xff, 0xff, 0x0f];
     ReadWriteRPC::rpc_write_to(&proposal_id, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&format!("Proposal {}", proposal_id), &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&String::new(), &mut bytes).unwrap();
     ReadWriteRPC::rpc_write_to(voters, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&0i64, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&Some(result_listener), &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&None::<Vec<u64>>, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&100u32, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&None::<Address>, &mut bytes).unwrap();
+    ReadWriteRPC::rpc_write_to(&0i64, &mut bytes).unwrap();
     bytes
 }
@@ -0,0 +1,794 @@
+#[cfg(test)]
+mod test_utils {
+    use crate::MultiVotingState;
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::{CallbackContext, ContractContext, ExecutionResult};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    pub fn address(address_type: AddressType, id: u8) -> Address {
+        Address {
+            address_type,
+            identifier: [id; 20],
+        }
+    }
+
+    pub fn context(sender: Address) -> ContractContext {
+        context_at(sender, 0)
+    }
+
+    pub fn context_at(sender: Address, block_production_time: i64) -> ContractContext {
+        ContractContext {
+            contract_address: address(AddressType::PublicContract, 0xff),
+            sender,
+            block_time: 0,
+            block_production_time,
+            current_transaction: [0; 32],
+            original_transaction: [0; 32],
+        }
+    }
+
+    /// The callback context of an event group of `batch_vote`, in which the voting contract
+    /// accepted each vote marked in `accepted`.
+    pub fn votes_accepted(accepted: &[bool]) -> CallbackContext {
+        CallbackContext {
+            success: accepted.iter().all(|succeeded| *succeeded),
+            results: accepted
+                .iter()
+                .map(|succeeded| ExecutionResult {
+                    succeeded: *succeeded,
+                    return_data: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn state_with_proposals(
+        owner: Address,
+        voters: Vec<Address>,
+        ids: &[u64],
+    ) -> MultiVotingState {
+        let mut voting_contracts = BTreeMap::new();
+        for id in ids {
+            voting_contracts.insert(*id, Some(address(AddressType::PublicContract, *id as u8)));
+        }
+        MultiVotingState {
+            owner,
+            eligible_voters: voters,
+            voting_contracts,
+            voting_contract_wasm: vec![],
+            voting_contract_abi: vec![],
+            recorded_votes: BTreeMap::new(),
+            hidden_tallies: BTreeMap::new(),
+            closed_proposals: BTreeSet::new(),
+            reveal_threshold: 0,
+            revealed_results: BTreeMap::new(),
+            child_results: BTreeMap::new(),
+            proposal_voters: BTreeMap::new(),
+            max_batch_votes: 32,
+            paused: false,
+            max_votes_per_event_group: 8,
+            min_participants: 0,
+            proposal_weights: BTreeMap::new(),
+            vote_cooldown: 0,
+            last_vote_times: BTreeMap::new(),
+            removed_proposals: BTreeSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod reveal_tests {
+    use super::test_utils::{address, context, state_with_proposals, votes_accepted};
+    use crate::{
+        batch_vote, batch_vote_callback, close_proposal, reveal_results, set_reveal_threshold,
+        Tally, Vote,
+    };
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_results_hidden_until_threshold() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1, 2]);
+
+        let (state, _) = set_reveal_threshold(context(owner), state, 2);
+        let votes = vec![
+            Vote {
+                proposal_id: 1,
+                vote: 1,
+            },
+            Vote {
+                proposal_id: 2,
+                vote: 0,
+            },
+        ];
+        let (state, _) = batch_vote(context(voter), state, votes.clone());
+        let (state, _) = batch_vote_callback(
+            context(voter),
+            votes_accepted(&[true, true]),
+            state,
+            voter,
+            votes,
+        );
+        let owner_votes = vec![Vote {
+            proposal_id: 1,
+            vote: 1,
+        }];
+        let (state, _) = batch_vote(context(owner), state, owner_votes.clone());
+        let (state, _) = batch_vote_callback(
+            context(owner),
+            votes_accepted(&[true]),
+            state,
+            owner,
+            owner_votes,
+        );
+        let (state, _) = close_proposal(context(owner), state, 1);
+
+        assert!(state.revealed_results.is_empty());
+        assert_eq!(state.recorded_votes[&1].len(), 2);
+        assert_eq!(state.recorded_votes[&1][&voter], 1);
+
+        let (state, _) = close_proposal(context(owner), state, 2);
+        let (state, _) = reveal_results(context(owner), state);
+
+        assert_eq!(state.revealed_results[&1], Tally { yes: 2, no: 0 });
+        assert_eq!(state.revealed_results[&2], Tally { yes: 0, no: 1 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Only 1 of the required 2 proposals have closed")]
+    pub fn test_reveal_before_threshold_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2]);
+
+        let (state, _) = set_reveal_threshold(context(owner), state, 2);
+        let (state, _) = close_proposal(context(owner), state, 1);
+        reveal_results(context(owner), state);
+    }
+
+    #[test]
+    pub fn test_votes_are_recorded_once_accepted() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1, 2]);
+        let votes = vec![
+            Vote {
+                proposal_id: 1,
+                vote: 1,
+            },
+            Vote {
+                proposal_id: 2,
+                vote: 1,
+            },
+        ];
+
+        let (state, _) = batch_vote(context(voter), state, votes.clone());
+        assert!(state.hidden_tallies.is_empty());
+
+        // The voting contract of proposal 2 rejects the vote
+        let (state, _) = batch_vote_callback(
+            context(voter),
+            votes_accepted(&[true, false]),
+            state,
+            voter,
+            votes,
+        );
+
+        assert_eq!(state.hidden_tallies[&1], Tally { yes: 1, no: 0 });
+        assert!(!state.hidden_tallies.contains_key(&2));
+        assert!(!state.recorded_votes.contains_key(&2));
+    }
+
+    #[test]
+    pub fn test_changed_vote_replaces_previous_vote() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1]);
+        let vote = |vote: u8| {
+            vec![Vote {
+                proposal_id: 1,
+                vote,
+            }]
+        };
+
+        let (state, _) = batch_vote(context(voter), state, vote(1));
+        let (state, _) = batch_vote_callback(
+            context(voter),
+            votes_accepted(&[true]),
+            state,
+            voter,
+            vote(1),
+        );
+        let (state, _) = batch_vote(context(voter), state, vote(0));
+        let (state, _) = batch_vote_callback(
+            context(voter),
+            votes_accepted(&[true]),
+            state,
+            voter,
+            vote(0),
+        );
+
+        assert_eq!(state.recorded_votes[&1][&voter], 0);
+        assert_eq!(state.hidden_tallies[&1], Tally { yes: 0, no: 1 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal 1 is closed")]
+    pub fn test_vote_on_closed_proposal_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        let (state, _) = close_proposal(context(owner), state, 1);
+        batch_vote(
+            context(owner),
+            state,
+            vec![Vote {
+                proposal_id: 1,
+                vote: 1,
+            }],
+        );
+    }
+}
+
+#[cfg(test)]
+mod result_listener_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{child_result_callback, create_voting_init_bytes, Tally};
+    use pbc_contract_common::address::AddressType;
+    use pbc_traits::ReadWriteRPC;
+
+    #[test]
+    pub fn test_init_bytes_include_result_listener() {
+        let voters = vec![address(AddressType::Account, 1)];
+        let parent = address(AddressType::PublicContract, 0xff);
+
+        let bytes = create_voting_init_bytes(7, &voters, parent);
+
+        let mut listener_bytes: Vec<u8> = vec![1];
+        ReadWriteRPC::rpc_write_to(&parent, &mut listener_bytes).unwrap();
+        // Followed by the absent weights, a full quorum, no weight token and no deadline
+        listener_bytes.push(0);
+        listener_bytes.extend_from_slice(&100u32.to_be_bytes());
+        listener_bytes.push(0);
+        listener_bytes.extend_from_slice(&0i64.to_be_bytes());
+        assert!(bytes.ends_with(&listener_bytes));
+    }
+
+    #[test]
+    pub fn test_child_result_is_recorded() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+        let child = state.voting_contracts[&1].unwrap();
+
+        let (state, _) = child_result_callback(context(child), state, 1, 3, 2);
+
+        assert_eq!(state.child_results[&1], Tally { yes: 3, no: 2 });
+        assert!(state.closed_proposals.contains(&1));
+    }
+
+    #[test]
+    pub fn test_child_result_keeps_weights_beyond_u32() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+        let child = state.voting_contracts[&1].unwrap();
+
+        let (state, _) = child_result_callback(context(child), state, 1, 5_000_000_000, 2);
+
+        assert_eq!(
+            state.child_results[&1],
+            Tally {
+                yes: 5_000_000_000,
+                no: 2
+            }
+        );
+        assert_eq!(state.composite_result(), Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the voting contract of the proposal can report its result")]
+    pub fn test_child_result_from_other_address_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        child_result_callback(context(owner), state, 1, 3, 2);
+    }
+}
+
+#[cfg(test)]
+mod voter_override_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{add_voting_contract, batch_vote, create_voting_init_bytes, Vote};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_override_bakes_subset_into_init_bytes() {
+        let owner = address(AddressType::Account, 1);
+        let alice = address(AddressType::Account, 2);
+        let bob = address(AddressType::Account, 3);
+        let state = state_with_proposals(owner, vec![owner, alice, bob], &[]);
+
+        let subset = state.voters_for_proposal(Some(vec![alice, bob]));
+        let parent = address(AddressType::PublicContract, 0xff);
+        assert_eq!(
+            create_voting_init_bytes(1, &subset, parent),
+            create_voting_init_bytes(1, &vec![alice, bob], parent)
+        );
+
+        let (state, _) = add_voting_contract(context(owner), state, 1, Some(vec![alice, bob]));
+        assert_eq!(state.proposal_voters[&1], vec![alice, bob]);
+    }
+
+    #[test]
+    pub fn test_no_override_uses_all_eligible_voters() {
+        let owner = address(AddressType::Account, 1);
+        let alice = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, alice], &[]);
+
+        let (state, _) = add_voting_contract(context(owner), state, 1, None);
+
+        assert_eq!(state.proposal_voters[&1], vec![owner, alice]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voter override must be a subset of the eligible voters")]
+    pub fn test_non_subset_override_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let outsider = address(AddressType::Account, 9);
+        let state = state_with_proposals(owner, vec![owner], &[]);
+
+        add_voting_contract(context(owner), state, 1, Some(vec![outsider]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only eligible voters can vote")]
+    pub fn test_voter_outside_override_cannot_vote() {
+        let owner = address(AddressType::Account, 1);
+        let alice = address(AddressType::Account, 2);
+        let mut state = state_with_proposals(owner, vec![owner, alice], &[1]);
+        state.proposal_voters.insert(1, vec![alice]);
+
+        batch_vote(
+            context(owner),
+            state,
+            vec![Vote {
+                proposal_id: 1,
+                vote: 1,
+            }],
+        );
+    }
+}
+
+#[cfg(test)]
+mod batch_cap_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{batch_vote, set_max_batch_votes, Vote};
+    use pbc_contract_common::address::AddressType;
+
+    fn votes(ids: &[u64]) -> Vec<Vote> {
+        ids.iter()
+            .map(|proposal_id| Vote {
+                proposal_id: *proposal_id,
+                vote: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_batch_at_cap_accepted() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2, 3]);
+        let (state, _) = set_max_batch_votes(context(owner), state, 3);
+
+        let (state, events) = batch_vote(context(owner), state, votes(&[1, 2, 3]));
+
+        assert_eq!(events.len(), 1);
+        assert!(state.last_vote_times.contains_key(&owner));
+    }
+
+    #[test]
+    #[should_panic(expected = "A batch can contain at most 2 votes")]
+    pub fn test_batch_over_cap_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2, 3]);
+        let (state, _) = set_max_batch_votes(context(owner), state, 2);
+
+        batch_vote(context(owner), state, votes(&[1, 2, 3]));
+    }
+}
+
+#[cfg(test)]
+mod pause_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{add_voter, add_voting_contract, set_paused, vote};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    #[should_panic(expected = "The contract is paused")]
+    pub fn test_vote_blocked_while_paused() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+        let (state, _) = set_paused(context(owner), state, true);
+
+        vote(context(owner), state, 1, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is paused")]
+    pub fn test_deploy_blocked_while_paused() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[]);
+        let (state, _) = set_paused(context(owner), state, true);
+
+        add_voting_contract(context(owner), state, 1, None);
+    }
+
+    #[test]
+    pub fn test_voters_managed_and_votes_resume_after_unpause() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+        let (state, _) = set_paused(context(owner), state, true);
+        let (state, _) = add_voter(context(owner), state, voter);
+
+        let (state, _) = set_paused(context(owner), state, false);
+        let (state, events) = vote(context(voter), state, 1, 1);
+
+        assert_eq!(events.len(), 1);
+        assert!(state.last_vote_times.contains_key(&voter));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can pause the contract")]
+    pub fn test_only_owner_can_pause() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        set_paused(context(address(AddressType::Account, 2)), state, true);
+    }
+}
+
+#[cfg(test)]
+mod event_chunking_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{batch_vote, set_max_votes_per_event_group, Vote};
+    use pbc_contract_common::address::AddressType;
+
+    fn votes(count: u64) -> Vec<Vote> {
+        (1..=count)
+            .map(|proposal_id| Vote {
+                proposal_id,
+                vote: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_large_batch_split_into_event_groups() {
+        let owner = address(AddressType::Account, 1);
+        let ids: Vec<u64> = (1..=20).collect();
+        let state = state_with_proposals(owner, vec![owner], &ids);
+
+        let (state, events) = batch_vote(context(owner), state, votes(20));
+
+        assert_eq!(events.len(), 3);
+        assert!(state.last_vote_times.contains_key(&owner));
+    }
+
+    #[test]
+    pub fn test_configured_event_group_size() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2, 3, 4]);
+        let (state, _) = set_max_votes_per_event_group(context(owner), state, 2);
+
+        let (_, events) = batch_vote(context(owner), state, votes(4));
+
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "The event group size must be at least one")]
+    pub fn test_zero_event_group_size_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        set_max_votes_per_event_group(context(owner), state, 0);
+    }
+}
+
+#[cfg(test)]
+mod min_participants_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{add_voting_contract, set_min_participants};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    #[should_panic(expected = "A proposal needs at least 3 voters")]
+    pub fn test_deploy_below_threshold_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let voters = vec![owner, address(AddressType::Account, 2)];
+        let state = state_with_proposals(owner, voters, &[]);
+        let (state, _) = set_min_participants(context(owner), state, 3);
+
+        add_voting_contract(context(owner), state, 1, None);
+    }
+
+    #[test]
+    pub fn test_deploy_at_threshold_accepted() {
+        let owner = address(AddressType::Account, 1);
+        let voters = vec![owner, address(AddressType::Account, 2)];
+        let state = state_with_proposals(owner, voters, &[]);
+        let (state, _) = set_min_participants(context(owner), state, 2);
+
+        let (state, events) = add_voting_contract(context(owner), state, 1, None);
+
+        assert_eq!(events.len(), 1);
+        assert!(state.voting_contracts.contains_key(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "A proposal needs at least 2 voters")]
+    pub fn test_override_below_threshold_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let voters = vec![owner, address(AddressType::Account, 2)];
+        let state = state_with_proposals(owner, voters, &[]);
+        let (state, _) = set_min_participants(context(owner), state, 2);
+
+        add_voting_contract(context(owner), state, 1, Some(vec![owner]));
+    }
+}
+
+#[cfg(test)]
+mod composite_result_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{child_result_callback, set_proposal_weight, MultiVotingState};
+    use pbc_contract_common::address::AddressType;
+
+    fn report(state: MultiVotingState, proposal_id: u64, yes: u64, no: u64) -> MultiVotingState {
+        let voting_contract = address(AddressType::PublicContract, proposal_id as u8);
+        child_result_callback(context(voting_contract), state, proposal_id, yes, no).0
+    }
+
+    #[test]
+    pub fn test_no_results_have_no_composite() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2]);
+
+        assert_eq!(state.composite_result(), None);
+    }
+
+    #[test]
+    pub fn test_unweighted_proposals_count_equally() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2, 3]);
+
+        let state = report(state, 1, 3, 1);
+        let state = report(state, 2, 0, 2);
+        let state = report(state, 3, 2, 1);
+
+        assert_eq!(state.composite_result(), Some(true));
+    }
+
+    #[test]
+    pub fn test_heavier_proposal_decides_composite() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2, 3]);
+        let (state, _) = set_proposal_weight(context(owner), state, 2, 3);
+
+        let state = report(state, 1, 3, 1);
+        let state = report(state, 2, 0, 2);
+        let state = report(state, 3, 2, 1);
+
+        assert_eq!(state.composite_result(), Some(false));
+    }
+
+    #[test]
+    pub fn test_tied_weights_are_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2]);
+        let (state, _) = set_proposal_weight(context(owner), state, 1, 2);
+        let (state, _) = set_proposal_weight(context(owner), state, 2, 2);
+
+        let state = report(state, 1, 1, 0);
+        let state = report(state, 2, 1, 1);
+
+        assert_eq!(state.composite_result(), Some(false));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can set proposal weights")]
+    pub fn test_only_owner_can_set_weights() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1]);
+
+        set_proposal_weight(context(voter), state, 1, 2);
+    }
+}
+
+#[cfg(test)]
+mod vote_cooldown_tests {
+    use super::test_utils::{address, context, context_at, state_with_proposals};
+    use crate::{set_vote_cooldown, vote};
+    use pbc_contract_common::address::AddressType;
+
+    const COOLDOWN: i64 = 60_000;
+
+    #[test]
+    #[should_panic(expected = "Voter must wait until 61000 before voting again")]
+    pub fn test_vote_within_cooldown_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1, 2]);
+        let (state, _) = set_vote_cooldown(context(owner), state, COOLDOWN);
+
+        let (state, _) = vote(context_at(voter, 1_000), state, 1, 1);
+        vote(context_at(voter, 1_000 + COOLDOWN - 1), state, 2, 1);
+    }
+
+    #[test]
+    pub fn test_vote_after_cooldown_accepted() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1, 2]);
+        let (state, _) = set_vote_cooldown(context(owner), state, COOLDOWN);
+
+        let (state, _) = vote(context_at(voter, 1_000), state, 1, 1);
+        let (state, events) = vote(context_at(voter, 1_000 + COOLDOWN), state, 2, 1);
+
+        assert_eq!(state.last_vote_times[&voter], 1_000 + COOLDOWN);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_zero_cooldown_allows_consecutive_votes() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1, 2]);
+
+        let (state, _) = vote(context_at(voter, 1_000), state, 1, 1);
+        let (state, events) = vote(context_at(voter, 1_000), state, 2, 0);
+
+        assert_eq!(state.last_vote_times[&voter], 1_000);
+        assert_eq!(events.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod remove_voting_contract_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{add_voting_contract, remove_voting_contract, vote};
+    use pbc_contract_common::address::AddressType;
+
+    #[test]
+    pub fn test_owner_removes_voting_contract() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1, 2]);
+
+        let (state, _) = remove_voting_contract(context(owner), state, 1);
+
+        assert!(!state.voting_contracts.contains_key(&1));
+        assert!(state.voting_contracts.contains_key(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Voting contract did not exist")]
+    pub fn test_removed_voting_contract_cannot_be_voted_on() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        let (state, _) = remove_voting_contract(context(owner), state, 1);
+        vote(context(owner), state, 1, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can remove contracts")]
+    pub fn test_non_owner_removal_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let voter = address(AddressType::Account, 2);
+        let state = state_with_proposals(owner, vec![owner, voter], &[1]);
+
+        remove_voting_contract(context(voter), state, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal id does not exist")]
+    pub fn test_unknown_proposal_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+
+        remove_voting_contract(context(owner), state, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voting contract is still being deployed")]
+    pub fn test_pending_deployment_removal_rejected() {
+        let owner = address(AddressType::Account, 1);
+        let mut state = state_with_proposals(owner, vec![owner], &[]);
+        state.voting_contracts.insert(1, None);
+
+        remove_voting_contract(context(owner), state, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Proposal id 1 was removed and cannot be reused")]
+    pub fn test_removed_proposal_id_cannot_be_added_again() {
+        let owner = address(AddressType::Account, 1);
+        let state = state_with_proposals(owner, vec![owner], &[1]);
+        let (state, _) = remove_voting_contract(context(owner), state, 1);
+
+        add_voting_contract(context(owner), state, 1, None);
+    }
+}
+
+#[cfg(test)]
+mod batch_voter_tests {
+    use super::test_utils::{address, context, state_with_proposals};
+    use crate::{add_voters, remove_voters};
+    use pbc_contract_common::address::{Address, AddressType};
+
+    fn voters() -> (Address, Address, Address, Address) {
+        (
+            address(AddressType::Account, 1),
+            address(AddressType::Account, 2),
+            address(AddressType::Account, 3),
+            address(AddressType::Account, 4),
+        )
+    }
+
+    #[test]
+    pub fn test_add_and_remove_batches() {
+        let (owner, a, b, c) = voters();
+        let state = state_with_proposals(owner, vec![owner], &[]);
+
+        let (state, _) = add_voters(context(owner), state, vec![a, b, c]);
+        assert_eq!(state.eligible_voters, vec![owner, a, b, c]);
+
+        let (state, _) = remove_voters(context(owner), state, vec![c, a]);
+        assert_eq!(state.eligible_voters, vec![owner, b]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voter already exists")]
+    pub fn test_add_batch_with_existing_voter_rejected() {
+        let (owner, a, b, c) = voters();
+        let state = state_with_proposals(owner, vec![owner, b], &[]);
+
+        add_voters(context(owner), state, vec![a, b, c]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate voter in batch")]
+    pub fn test_add_batch_with_duplicate_rejected() {
+        let (owner, a, b, _) = voters();
+        let state = state_with_proposals(owner, vec![owner], &[]);
+
+        add_voters(context(owner), state, vec![a, b, a]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voter does not exist")]
+    pub fn test_remove_batch_with_missing_voter_rejected() {
+        let (owner, a, b, c) = voters();
+        let state = state_with_proposals(owner, vec![owner, a, c], &[]);
+
+        remove_voters(context(owner), state, vec![a, b, c]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate voter in batch")]
+    pub fn test_remove_batch_with_duplicate_rejected() {
+        let (owner, a, _, _) = voters();
+        let state = state_with_proposals(owner, vec![owner, a], &[]);
+
+        remove_voters(context(owner), state, vec![a, a]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can add voters")]
+    pub fn test_non_owner_batch_rejected() {
+        let (owner, a, b, _) = voters();
+        let state = state_with_proposals(owner, vec![owner], &[]);
+
+        add_voters(context(a), state, vec![b]);
+    }
+}
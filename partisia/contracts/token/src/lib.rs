@@ -1,29 +1,44 @@
 //! This is an example token smart contract.
 //!
-//! The contract has a constant total supply of tokens.
-//! The total supply is initialized together with the contract.
+//! The total supply of tokens is initialized together with the contract.
+//! Afterwards only the owner of the contract can increase it, by minting tokens to an account,
+//! up to an optional `max_supply` fixed at initialization,
+//! while any token owner can decrease it by burning their tokens, or tokens they have been approved.
 //!
 //! Any token owner can then `transfer` tokens to other accounts, or `approve` other accounts to use their tokens.
 //! If a Alice has been approved tokens from Bob, then Alice can use `transfer_from` to use Bob's tokens.
 //!
+//! The owner can cap the allowance any account may grant with `set_max_approval`, to discourage unlimited approvals.
+//!
+//! The owner can `freeze` individual accounts, which can then neither send nor receive tokens until unfrozen.
+//!
 //! The contract is inspired by the ERC20 token contract.\
 //! <https://github.com/ethereum/EIPs/blob/master/EIPS/eip-20.md>
 #![allow(unused_variables)]
 
+mod tests;
+
 #[macro_use]
 extern crate pbc_contract_codegen;
 
 use create_type_spec_derive::CreateTypeSpec;
 use read_write_rpc_derive::ReadWriteRPC;
 use read_write_state_derive::ReadWriteState;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Add;
 
 use pbc_contract_common::address::Address;
 use pbc_contract_common::context::ContractContext;
 use pbc_contract_common::events::EventGroup;
 
+/// The largest number of decimals a token can use.
+const MAX_DECIMALS: u8 = 18;
+
+/// The number of idempotency keys remembered per sender. Older keys are forgotten first.
+const MAX_IDEMPOTENCY_KEYS_PER_SENDER: usize = 32;
 
+/// The `max_approval` sentinel meaning that allowances are not capped.
+pub const NO_APPROVAL_CAP: u64 = u64::MAX;
 
 /// Custom struct for the state of the contract.
 ///
@@ -45,6 +60,14 @@ use pbc_contract_common::events::EventGroup;
 /// * `balances`: [`BTreeMap<Address, u64>`], ledger for the accounts associated with the contract.
 ///
 /// * `allowed`: [`BTreeMap<Address, BTreeMap<Address, u64>>`], allowance from an owner to a spender.
+///
+/// * `processed_keys`: [`BTreeMap<Address, Vec<[u8; 32]>>`], the most recent idempotency keys used by each sender.
+///
+/// * `max_supply`: [`Option<u64>`], the largest total supply that minting may reach, or `None` for no cap.
+///
+/// * `frozen`: [`BTreeSet<Address>`], accounts which can neither send nor receive tokens.
+///
+/// * `max_approval`: [`u64`], the largest allowance an owner may grant a spender, or [`NO_APPROVAL_CAP`].
 #[state]
 pub struct TokenContractState {
     name: String,
@@ -54,6 +77,10 @@ pub struct TokenContractState {
     total_supply: u64,
     balances: BTreeMap<Address, u64>,
     allowed: BTreeMap<Address, BTreeMap<Address, u64>>,
+    processed_keys: BTreeMap<Address, Vec<[u8; 32]>>,
+    max_supply: Option<u64>,
+    frozen: BTreeSet<Address>,
+    max_approval: u64,
 }
 
 impl TokenContractState {
@@ -91,6 +118,98 @@ impl TokenContractState {
         let allowed_from_owner = self.allowed.entry(owner).or_insert_with(BTreeMap::new);
         allowed_from_owner.insert(spender, value);
     }
+
+    /// Asserts that an owner may grant a spender an allowance of `value`.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `value`: [`u64`] The allowance to grant.
+    fn assert_within_max_approval(&self, value: u64) {
+        assert!(
+            value <= self.max_approval,
+            "Allowance cannot exceed the max approval of {}",
+            self.max_approval
+        );
+    }
+
+    /// Asserts that `account` has not been frozen by the owner.
+    ///
+    /// ### Parameters:
+    ///
+    /// * `account`: [`Address`] The account to check.
+    fn assert_not_frozen(&self, account: Address) {
+        assert!(!self.frozen.contains(&account), "Account is frozen");
+    }
+
+    /// Records `key` as processed for `sender`, forgetting the oldest key if more than
+    /// [`MAX_IDEMPOTENCY_KEYS_PER_SENDER`] are stored.
+    ///
+    /// ### Returns:
+    ///
+    /// `true` if the key was new, `false` if it has already been processed.
+    fn register_idempotency_key(&mut self, sender: Address, key: [u8; 32]) -> bool {
+        let keys = self.processed_keys.entry(sender).or_default();
+        if keys.contains(&key) {
+            return false;
+        }
+        if keys.len() == MAX_IDEMPOTENCY_KEYS_PER_SENDER {
+            keys.remove(0);
+        }
+        keys.push(key);
+        true
+    }
+
+    /// Sums the balances of every account in the ledger.
+    ///
+    /// ### Returns:
+    ///
+    /// A [`u64`] representing the amount of tokens currently held by accounts.
+    pub fn circulating_supply(&self) -> u64 {
+        self.balances.values().fold(0u64, |sum, balance| {
+            sum.checked_add(*balance)
+                .expect("Overflow when summing balances")
+        })
+    }
+
+    /// Counts the accounts holding tokens. Zero-balance entries, which `balance_of` may leave in
+    /// the ledger, are not counted.
+    ///
+    /// ### Returns:
+    ///
+    /// A [`u64`] representing the number of accounts with a nonzero balance.
+    pub fn holder_count(&self) -> u64 {
+        self.balances
+            .values()
+            .filter(|balance| **balance > 0)
+            .count() as u64
+    }
+
+    /// Summarizes the size of the ledger and the tokens it accounts for.
+    ///
+    /// ### Returns:
+    ///
+    /// The [`LedgerStats`] of the current state.
+    pub fn ledger_stats(&self) -> LedgerStats {
+        LedgerStats {
+            holders: self.holder_count(),
+            approvals: self
+                .allowed
+                .values()
+                .flat_map(|allowances| allowances.values())
+                .filter(|allowance| **allowance > 0)
+                .count() as u64,
+            balance_sum: self.circulating_supply(),
+        }
+    }
+
+    /// Checks that the ledger accounts for exactly `total_supply` tokens.
+    ///
+    /// ### Returns:
+    ///
+    /// `true` if the sum of all balances equals `total_supply`, `false` otherwise.
+    pub fn is_supply_consistent(&self) -> bool {
+        self.circulating_supply() == self.total_supply
+    }
 }
 
 /// Initial function to bootstrap the contracts state. Must return the state-struct.
@@ -104,9 +223,13 @@ impl TokenContractState {
 /// * `symbol`: [`String`], the symbol of the token. E.g. "HIX".\
 ///
 /// * `decimals`: [`u8`], the number of decimals the token uses - e.g. 8,
-/// means to divide the token amount by `100000000` to get its user representation.\
+/// means to divide the token amount by `100000000` to get its user representation.
+/// At most [`MAX_DECIMALS`].\
 ///
-/// * `total_supply`: [`u64`], current amount of tokens for the TokenContract.
+/// * `total_supply`: [`u64`], current amount of tokens for the TokenContract. Must be positive.
+///
+/// * `max_supply`: [`Option<u64>`], the largest total supply that minting may reach. If set, it must be
+/// at least `total_supply`. `None` leaves the supply uncapped.
 ///
 /// ### Returns:
 ///
@@ -118,7 +241,24 @@ pub fn initialize(
     symbol: String,
     decimals: u8,
     total_supply: u64,
+    max_supply: Option<u64>,
 ) -> (TokenContractState, Vec<EventGroup>) {
+    assert!(!name.is_empty(), "Token name cannot be empty");
+    assert!(!symbol.is_empty(), "Token symbol cannot be empty");
+    assert!(
+        decimals <= MAX_DECIMALS,
+        "Token decimals cannot exceed {}",
+        MAX_DECIMALS
+    );
+    assert!(total_supply > 0, "Token total supply must be positive");
+    if let Some(max_supply) = max_supply {
+        assert!(
+            total_supply <= max_supply,
+            "Token total supply cannot exceed the max supply of {}",
+            max_supply
+        );
+    }
+
     let mut balances = BTreeMap::new();
     balances.insert(ctx.sender, total_supply);
 
@@ -130,6 +270,10 @@ pub fn initialize(
         total_supply,
         balances,
         allowed: BTreeMap::new(),
+        processed_keys: BTreeMap::new(),
+        max_supply,
+        frozen: BTreeSet::new(),
+        max_approval: NO_APPROVAL_CAP,
     };
 
     (state, vec![])
@@ -144,6 +288,17 @@ pub struct Transfer {
     pub value: u64,
 }
 
+/// A health snapshot of the ledger, reported by `query_ledger_stats`.
+#[derive(ReadWriteRPC, CreateTypeSpec, Clone, Debug, PartialEq, Eq)]
+pub struct LedgerStats {
+    /// The number of accounts with a nonzero balance.
+    pub holders: u64,
+    /// The number of nonzero allowances from an owner to a spender.
+    pub approvals: u64,
+    /// The sum of all balances.
+    pub balance_sum: u64,
+}
+
 /// Transfers `value` amount of tokens to address `to` from the caller.
 /// The function throws if the message caller's account
 /// balance does not have enough tokens to spend.
@@ -176,6 +331,8 @@ pub fn transfer(
 /// The function throws if the message caller's account
 /// balance does not have enough tokens to spend.
 /// If the sender's account goes to 0, the sender's address is removed from state.
+/// The whole batch is rejected up front if any recipient is frozen, or if the caller's
+/// balance does not cover the sum of all transfers.
 ///
 /// ### Parameters:
 ///
@@ -194,6 +351,15 @@ pub fn bulk_transfer(
     state: TokenContractState,
     transfers: Vec<Transfer>,
 ) -> (TokenContractState, Vec<EventGroup>) {
+    let mut total: u64 = 0;
+    for t in transfers.iter() {
+        state.assert_not_frozen(t.to);
+        total = total
+            .checked_add(t.value)
+            .expect("Overflow in bulk_transfer - batch total exceeds u64::MAX");
+    }
+    let balance = state.balances.get(&context.sender).copied().unwrap_or(0);
+    assert!(balance >= total, "Insufficient balance for batch");
     let mut new_state = state;
     for t in transfers {
         new_state = core_transfer(context.sender, new_state, t.to, t.value).0;
@@ -268,6 +434,7 @@ pub fn bulk_transfer_from(
 
 /// Allows `spender` to withdraw from the owners account multiple times, up to the `value` amount.
 /// If this function is called again it overwrites the current allowance with `value`.
+/// The function throws if `value` exceeds the `max_approval` of the contract.
 ///
 /// ### Parameters:
 ///
@@ -289,6 +456,7 @@ pub fn approve(
     spender: Address,
     value: u64,
 ) -> (TokenContractState, Vec<EventGroup>) {
+    state.assert_within_max_approval(value);
     let mut new_state = state;
     new_state.update_allowance(context.sender, spender, value);
     (new_state, vec![])
@@ -296,8 +464,11 @@ pub fn approve(
 
 /// Transfers `value` amount of tokens to address `to` from the caller.
 /// The function throws if the message caller's account
-/// balance does not have enough tokens to spend.
+/// balance does not have enough tokens to spend, if `to` is the zero address,
+/// or if either account is frozen.
 /// If the sender's account goes to 0, the sender's address is removed from state.
+/// A transfer to the sender itself is allowed and leaves the ledger unchanged,
+/// but still requires the sender to hold `value` tokens.
 ///
 /// ### Parameters:
 ///
@@ -318,6 +489,9 @@ pub fn core_transfer(
     to: Address,
     value: u64,
 ) -> (TokenContractState, Vec<EventGroup>) {
+    assert_valid_recipient(to);
+    state.assert_not_frozen(sender);
+    state.assert_not_frozen(to);
     let mut new_state = state;
     let from_amount = new_state.balance_of(sender);
     let o_new_from_amount = from_amount.checked_sub(value);
@@ -337,11 +511,25 @@ pub fn core_transfer(
     (new_state, vec![])
 }
 
+/// Asserts that `to` can receive tokens. Tokens sent to the all-zero address could never be
+/// spent again, so such transfers are rejected.
+///
+/// ### Parameters:
+///
+/// * `to`: [`Address`], the address to validate.
+fn assert_valid_recipient(to: Address) {
+    assert!(
+        to.identifier != [0; 20],
+        "Cannot transfer tokens to the zero address"
+    );
+}
+
 /// Transfers `value` amount of tokens from address `from` to address `to`.\
 /// This requires that the sender is allowed to do the transfer by the `from`
 /// account through the `approve` action.
 /// The function throws if the message caller's account
-/// balance does not have enough tokens to spend, or if the tokens were not approved.
+/// balance does not have enough tokens to spend, if the tokens were not approved,
+/// or if the sender, `from` or `to` is frozen.
 ///
 /// ### Parameters:
 ///
@@ -365,6 +553,7 @@ pub fn core_transfer_from(
     to: Address,
     value: u64,
 ) -> (TokenContractState, Vec<EventGroup>) {
+    state.assert_not_frozen(sender);
     let mut new_state = state;
     let from_allowed = new_state.allowance(from, sender);
     let o_new_allowed_amount = from_allowed.checked_sub(value);
@@ -378,3 +567,568 @@ pub fn core_transfer_from(
     }
     core_transfer(from, new_state, to, value)
 }
+
+/// Sanity check of the ledger. Sums all `balances` and reports whether the result equals
+/// `total_supply`, see [`TokenContractState::is_supply_consistent`], as the return data of the call.
+/// The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the result of the check.
+#[action(shortname = 0x06)]
+pub fn verify_supply(
+    context: ContractContext,
+    state: TokenContractState,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(state.is_supply_consistent());
+    (state, vec![event_group_builder.build()])
+}
+
+/// Transfers `value` amount of tokens to address `to` from the caller, at most once per `idempotency_key`.
+/// Retrying with a key that the sender has already used is a successful no-op, which makes it safe
+/// to resubmit a transfer whose outcome is unknown.
+/// Only the last [`MAX_IDEMPOTENCY_KEYS_PER_SENDER`] keys of each sender are remembered.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `to`: [`Address`], the address to transfer to.
+///
+/// * `value`: [`u64`], amount to transfer.
+///
+/// * `idempotency_key`: [`[u8; 32]`], a key chosen by the sender that identifies this transfer.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+#[action(shortname = 0x07)]
+pub fn transfer_idempotent(
+    context: ContractContext,
+    state: TokenContractState,
+    to: Address,
+    value: u64,
+    idempotency_key: [u8; 32],
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut new_state = state;
+    if !new_state.register_idempotency_key(context.sender, idempotency_key) {
+        return (new_state, vec![]);
+    }
+    core_transfer(context.sender, new_state, to, value)
+}
+
+/// Reports the `owner` of the contract as the return data of the call, such that other contracts
+/// can check who controls the token. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the owner.
+#[action(shortname = 0x08)]
+pub fn query_owner(
+    context: ContractContext,
+    state: TokenContractState,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(state.owner);
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports the `decimals` of the token as the return data of the call, such that other contracts
+/// can scale amounts of different tokens to a common unit. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the decimals.
+#[action(shortname = 0x09)]
+pub fn query_decimals(
+    context: ContractContext,
+    state: TokenContractState,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(state.decimals);
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports the [`LedgerStats`] of the contract as the return data of the call, giving operators
+/// a snapshot of the state size and supply accounting. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the stats.
+#[action(shortname = 0x0A)]
+pub fn query_ledger_stats(
+    context: ContractContext,
+    state: TokenContractState,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(state.ledger_stats());
+    (state, vec![event_group_builder.build()])
+}
+
+/// Reports the balance of `account` as the return data of the call, such that other contracts can
+/// weigh or check holdings. Unlike [`balance_of`](TokenContractState::balance_of), no ledger entry is
+/// created for an account without a balance, so the state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `account`: [`Address`], the account to report the balance of.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the balance.
+#[action(shortname = 0x0B)]
+pub fn query_balance(
+    context: ContractContext,
+    state: TokenContractState,
+    account: Address,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let balance = state.balances.get(&account).copied().unwrap_or(0);
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(balance);
+    (state, vec![event_group_builder.build()])
+}
+
+/// Splits `total` into `recipients` equal shares. The remainder of the division goes to the
+/// first share if `remainder_to_first` is set, and is left out of the shares otherwise.
+///
+/// ### Parameters:
+///
+/// * `total`: [`u64`], the amount to split.
+///
+/// * `recipients`: [`usize`], the number of shares, at least one.
+///
+/// * `remainder_to_first`: [`bool`], whether the first share receives the remainder.
+///
+/// ### Returns
+///
+/// The shares of type [`Vec<u64>`].
+pub fn split_total(total: u64, recipients: usize, remainder_to_first: bool) -> Vec<u64> {
+    let share = total / recipients as u64;
+    let mut shares = vec![share; recipients];
+    if remainder_to_first {
+        shares[0] += total % recipients as u64;
+    }
+    shares
+}
+
+/// Distributes `total` tokens from the caller evenly across `recipients`.
+/// The remainder of the division is either given to the first recipient or kept by the caller,
+/// so exactly `total` tokens are accounted for.
+/// The function throws if the caller does not have `total` tokens.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `total`: [`u64`], the amount to distribute.
+///
+/// * `recipients`: [`Vec<Address>`], the addresses to distribute to.
+///
+/// * `remainder_to_first`: [`bool`], whether the first recipient receives the remainder instead of the caller.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+#[action(shortname = 0x0C)]
+pub fn distribute(
+    context: ContractContext,
+    state: TokenContractState,
+    total: u64,
+    recipients: Vec<Address>,
+    remainder_to_first: bool,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert!(!recipients.is_empty(), "Cannot distribute to no recipients");
+    let shares = split_total(total, recipients.len(), remainder_to_first);
+    let mut new_state = state;
+    for (to, value) in recipients.into_iter().zip(shares) {
+        new_state = core_transfer(context.sender, new_state, to, value).0;
+    }
+    (new_state, vec![])
+}
+
+/// Mints `amount` new tokens to address `to`, increasing the total supply.
+/// The function throws if the caller is not the owner of the contract, if `to` is frozen, or if the
+/// total supply would overflow or exceed `max_supply`.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `to`: [`Address`], the address to mint to.
+///
+/// * `amount`: [`u64`], amount to mint.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+#[action(shortname = 0x0D)]
+pub fn mint(
+    context: ContractContext,
+    state: TokenContractState,
+    to: Address,
+    amount: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can mint tokens"
+    );
+    state.assert_not_frozen(to);
+    let mut new_state = state;
+    new_state.total_supply = new_state
+        .total_supply
+        .checked_add(amount)
+        .expect("Overflow in mint - total supply cannot exceed u64::MAX");
+    if let Some(max_supply) = new_state.max_supply {
+        assert!(
+            new_state.total_supply <= max_supply,
+            "Mint would exceed the max supply of {}",
+            max_supply
+        );
+    }
+    let to_amount = new_state.balance_of(to);
+    new_state.balances.insert(to, to_amount + amount);
+    (new_state, vec![])
+}
+
+/// Burns `value` amount of tokens owned by address `owner`, decreasing the total supply.
+/// The function throws if `owner` is frozen or does not have enough tokens.
+/// If the owner's account goes to 0, the owner's address is removed from state.
+///
+/// ### Parameters:
+///
+/// * `owner`: [`Address`], the address to burn from.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `value`: [`u64`], amount to burn.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+pub fn core_burn(
+    owner: Address,
+    state: TokenContractState,
+    value: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    state.assert_not_frozen(owner);
+    let mut new_state = state;
+    let new_amount = new_state
+        .balance_of(owner)
+        .checked_sub(value)
+        .expect("Underflow in burn - owner did not have enough tokens");
+    new_state.total_supply = new_state
+        .total_supply
+        .checked_sub(value)
+        .expect("Underflow in burn - total supply cannot go below zero");
+    if new_amount == 0 {
+        new_state.balances.remove(&owner);
+    } else {
+        new_state.balances.insert(owner, new_amount);
+    }
+    (new_state, vec![])
+}
+
+/// Burns `value` amount of tokens from the caller.
+/// The function throws if the caller does not have enough tokens.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `value`: [`u64`], amount to burn.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+#[action(shortname = 0x0E)]
+pub fn burn(
+    context: ContractContext,
+    state: TokenContractState,
+    value: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    core_burn(context.sender, state, value)
+}
+
+/// Burns `value` amount of tokens from address `from`.\
+/// This requires that the caller is allowed to spend the tokens by the `from`
+/// account through the `approve` action.
+/// The function throws if `from` does not have enough tokens, or if the tokens were not approved.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `from`: [`Address`], the address to burn from.
+///
+/// * `value`: [`u64`], amount to burn.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated ledger.
+#[action(shortname = 0x0F)]
+pub fn burn_from(
+    context: ContractContext,
+    state: TokenContractState,
+    from: Address,
+    value: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut new_state = state;
+    let new_allowed_amount = new_state
+        .allowance(from, context.sender)
+        .checked_sub(value)
+        .expect("Underflow in burn_from - tokens has not been approved for burning");
+    new_state.update_allowance(from, context.sender, new_allowed_amount);
+    core_burn(from, new_state, value)
+}
+
+/// Increases the allowance of `spender` to withdraw from the caller's account by `added_value`.
+/// Unlike `approve`, this adjusts the current allowance instead of overwriting it, so it cannot
+/// race with a `transfer_from` of the spender.
+/// The function throws if the allowance would overflow or exceed the `max_approval` of the contract.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `spender`: [`Address`], the address of the spender.
+///
+/// * `added_value`: [`u64`], amount to add to the allowance.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated allowance.
+#[action(shortname = 0x10)]
+pub fn increase_allowance(
+    context: ContractContext,
+    state: TokenContractState,
+    spender: Address,
+    added_value: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut new_state = state;
+    let new_allowance = new_state
+        .allowance(context.sender, spender)
+        .checked_add(added_value)
+        .expect("Overflow in increase_allowance");
+    new_state.assert_within_max_approval(new_allowance);
+    new_state.update_allowance(context.sender, spender, new_allowance);
+    (new_state, vec![])
+}
+
+/// Decreases the allowance of `spender` to withdraw from the caller's account by `subtracted_value`.
+/// Unlike `approve`, this adjusts the current allowance instead of overwriting it.
+/// The allowance saturates at zero, so decreasing by more than what is left revokes it entirely.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `spender`: [`Address`], the address of the spender.
+///
+/// * `subtracted_value`: [`u64`], amount to subtract from the allowance.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with an updated allowance.
+#[action(shortname = 0x11)]
+pub fn decrease_allowance(
+    context: ContractContext,
+    state: TokenContractState,
+    spender: Address,
+    subtracted_value: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut new_state = state;
+    let new_allowance = new_state
+        .allowance(context.sender, spender)
+        .saturating_sub(subtracted_value);
+    new_state.update_allowance(context.sender, spender, new_allowance);
+    (new_state, vec![])
+}
+
+/// Transfers ownership of the contract, and with it the right to mint, to `new_owner`.
+/// The function throws if the caller is not the current owner, or if `new_owner` is the zero address.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `new_owner`: [`Address`], the new owner of the contract.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with the new owner.
+#[action(shortname = 0x12)]
+pub fn transfer_ownership(
+    context: ContractContext,
+    state: TokenContractState,
+    new_owner: Address,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can transfer ownership"
+    );
+    assert!(
+        new_owner.identifier != [0; 20],
+        "Cannot transfer ownership to the zero address"
+    );
+    let mut new_state = state;
+    new_state.owner = new_owner;
+    (new_state, vec![])
+}
+
+/// Reports the [`holder_count`](TokenContractState::holder_count) of the contract as the return data
+/// of the call. The state is left unchanged.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// ### Returns
+///
+/// The unchanged state object of type [`TokenContractState`] and an event returning the count.
+#[action(shortname = 0x13)]
+pub fn query_holder_count(
+    context: ContractContext,
+    state: TokenContractState,
+) -> (TokenContractState, Vec<EventGroup>) {
+    let mut event_group_builder = EventGroup::builder();
+    event_group_builder.return_data(state.holder_count());
+    (state, vec![event_group_builder.build()])
+}
+
+/// Freezes `account`, such that it can neither send nor receive tokens.
+/// The function throws if the caller is not the owner of the contract, or if `account` is already frozen.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `account`: [`Address`], the account to freeze.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with the account frozen.
+#[action(shortname = 0x14)]
+pub fn freeze(
+    context: ContractContext,
+    state: TokenContractState,
+    account: Address,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can freeze accounts"
+    );
+    let mut new_state = state;
+    assert!(
+        new_state.frozen.insert(account),
+        "Account is already frozen"
+    );
+    (new_state, vec![])
+}
+
+/// Unfreezes `account`, such that it can send and receive tokens again.
+/// The function throws if the caller is not the owner of the contract, or if `account` is not frozen.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `account`: [`Address`], the account to unfreeze.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with the account unfrozen.
+#[action(shortname = 0x15)]
+pub fn unfreeze(
+    context: ContractContext,
+    state: TokenContractState,
+    account: Address,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can unfreeze accounts"
+    );
+    let mut new_state = state;
+    assert!(new_state.frozen.remove(&account), "Account is not frozen");
+    (new_state, vec![])
+}
+
+/// Caps the allowance any owner may grant a spender at `max_approval`.
+/// Existing allowances above the cap are kept, but cannot be raised further.
+/// The function throws if the caller is not the owner of the contract.
+///
+/// ### Parameters:
+///
+/// * `context`: [`ContractContext`], the context for the action call.
+///
+/// * `state`: [`TokenContractState`], the current state of the contract.
+///
+/// * `max_approval`: [`u64`], the new cap, or [`NO_APPROVAL_CAP`] to remove it.
+///
+/// ### Returns
+///
+/// The new state object of type [`TokenContractState`] with the new cap.
+#[action(shortname = 0x16)]
+pub fn set_max_approval(
+    context: ContractContext,
+    state: TokenContractState,
+    max_approval: u64,
+) -> (TokenContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the max approval"
+    );
+    let mut new_state = state;
+    new_state.max_approval = max_approval;
+    (new_state, vec![])
+}
@@ -0,0 +1,868 @@
+#[cfg(test)]
+mod test_utils {
+    use crate::{TokenContractState, NO_APPROVAL_CAP};
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::ContractContext;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    pub fn account(id: u8) -> Address {
+        Address {
+            address_type: AddressType::Account,
+            identifier: [id; 20],
+        }
+    }
+
+    pub fn context(sender: Address) -> ContractContext {
+        ContractContext {
+            contract_address: Address {
+                address_type: AddressType::PublicContract,
+                identifier: [0xff; 20],
+            },
+            sender,
+            block_time: 0,
+            block_production_time: 0,
+            current_transaction: [0; 32],
+            original_transaction: [0; 32],
+        }
+    }
+
+    pub fn token_state(owner: Address, total_supply: u64) -> TokenContractState {
+        let mut balances = BTreeMap::new();
+        balances.insert(owner, total_supply);
+        TokenContractState {
+            name: "Token".to_string(),
+            decimals: 8,
+            symbol: "TOK".to_string(),
+            owner,
+            total_supply,
+            balances,
+            allowed: BTreeMap::new(),
+            processed_keys: BTreeMap::new(),
+            max_supply: None,
+            frozen: BTreeSet::new(),
+            max_approval: NO_APPROVAL_CAP,
+        }
+    }
+}
+
+#[cfg(test)]
+mod supply_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{core_transfer, core_transfer_from, verify_supply};
+
+    #[test]
+    pub fn test_supply_consistent_after_transfers() {
+        let owner = account(1);
+        let alice = account(2);
+        let bob = account(3);
+
+        let state = token_state(owner, 1000);
+        assert!(state.is_supply_consistent());
+
+        let state = core_transfer(owner, state, alice, 300).0;
+        let state = core_transfer(alice, state, bob, 100).0;
+        let mut state = core_transfer(owner, state, bob, 700).0;
+        state.update_allowance(bob, alice, 50);
+        let state = core_transfer_from(alice, state, bob, owner, 50).0;
+
+        assert_eq!(state.circulating_supply(), 1000);
+        assert!(state.is_supply_consistent());
+    }
+
+    #[test]
+    pub fn test_supply_inconsistent_ledger_detected() {
+        let owner = account(1);
+        let mut state = token_state(owner, 1000);
+        state.balances.insert(account(2), 1);
+
+        assert_eq!(state.circulating_supply(), 1001);
+        assert!(!state.is_supply_consistent());
+    }
+
+    #[test]
+    pub fn test_verify_supply_reports_instead_of_failing() {
+        let owner = account(1);
+        let mut state = token_state(owner, 1000);
+        state.balances.insert(account(2), 1);
+
+        let (state, events) = verify_supply(context(owner), state);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.total_supply, 1000);
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{transfer_idempotent, MAX_IDEMPOTENCY_KEYS_PER_SENDER};
+
+    #[test]
+    pub fn test_repeated_key_transfers_once() {
+        let owner = account(1);
+        let alice = account(2);
+        let state = token_state(owner, 1000);
+
+        let (state, _) = transfer_idempotent(context(owner), state, alice, 100, [1; 32]);
+        let (mut state, _) = transfer_idempotent(context(owner), state, alice, 100, [1; 32]);
+
+        assert_eq!(state.balance_of(alice), 100);
+        assert_eq!(state.balance_of(owner), 900);
+    }
+
+    #[test]
+    pub fn test_fresh_key_transfers() {
+        let owner = account(1);
+        let alice = account(2);
+        let state = token_state(owner, 1000);
+
+        let (state, _) = transfer_idempotent(context(owner), state, alice, 100, [1; 32]);
+        let (mut state, _) = transfer_idempotent(context(owner), state, alice, 100, [2; 32]);
+
+        assert_eq!(state.balance_of(alice), 200);
+    }
+
+    #[test]
+    pub fn test_keys_are_scoped_per_sender() {
+        let owner = account(1);
+        let alice = account(2);
+        let state = token_state(owner, 1000);
+
+        let (state, _) = transfer_idempotent(context(owner), state, alice, 100, [1; 32]);
+        let (mut state, _) = transfer_idempotent(context(alice), state, owner, 40, [1; 32]);
+
+        assert_eq!(state.balance_of(alice), 60);
+    }
+
+    #[test]
+    pub fn test_stored_keys_are_bounded() {
+        let owner = account(1);
+        let alice = account(2);
+        let mut state = token_state(owner, 1000);
+
+        for key in 0..=MAX_IDEMPOTENCY_KEYS_PER_SENDER as u8 {
+            state = transfer_idempotent(context(owner), state, alice, 1, [key; 32]).0;
+        }
+
+        let keys = &state.processed_keys[&owner];
+        assert_eq!(keys.len(), MAX_IDEMPOTENCY_KEYS_PER_SENDER);
+        assert!(!keys.contains(&[0; 32]));
+    }
+}
+
+#[cfg(test)]
+mod initialize_tests {
+    use super::test_utils::{account, context};
+    use crate::{initialize, MAX_DECIMALS};
+
+    #[test]
+    pub fn test_initialize_with_max_decimals() {
+        let owner = account(1);
+        let (mut state, _) = initialize(
+            context(owner),
+            "Token".to_string(),
+            "TOK".to_string(),
+            MAX_DECIMALS,
+            1000,
+            None,
+        );
+
+        assert_eq!(state.decimals, 18);
+        assert_eq!(state.balance_of(owner), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token decimals cannot exceed 18")]
+    pub fn test_too_many_decimals_rejected() {
+        initialize(
+            context(account(1)),
+            "Token".to_string(),
+            "TOK".to_string(),
+            19,
+            1000,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token name cannot be empty")]
+    pub fn test_empty_name_rejected() {
+        initialize(
+            context(account(1)),
+            "".to_string(),
+            "TOK".to_string(),
+            8,
+            1000,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token symbol cannot be empty")]
+    pub fn test_empty_symbol_rejected() {
+        initialize(
+            context(account(1)),
+            "Token".to_string(),
+            "".to_string(),
+            8,
+            1000,
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token total supply cannot exceed the max supply of 999")]
+    pub fn test_total_supply_above_max_supply_rejected() {
+        initialize(
+            context(account(1)),
+            "Token".to_string(),
+            "TOK".to_string(),
+            8,
+            1000,
+            Some(999),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Token total supply must be positive")]
+    pub fn test_zero_total_supply_rejected() {
+        initialize(
+            context(account(1)),
+            "Token".to_string(),
+            "TOK".to_string(),
+            8,
+            0,
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod ledger_stats_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{approve, query_ledger_stats, transfer, LedgerStats};
+
+    #[test]
+    pub fn test_holder_count_skips_zero_balances() {
+        let owner = account(1);
+        let state = token_state(owner, 100);
+        let (state, _) = transfer(context(owner), state, account(2), 40);
+        let (mut state, _) = transfer(context(owner), state, account(3), 0);
+        state.balance_of(account(4));
+
+        let nonzero_entries = state.balances.values().filter(|b| **b > 0).count() as u64;
+        assert_eq!(state.balances.len(), 4);
+        assert_eq!(state.ledger_stats().holders, nonzero_entries);
+        assert_eq!(nonzero_entries, 2);
+    }
+
+    #[test]
+    pub fn test_stats_report_approvals_and_balance_sum() {
+        let owner = account(1);
+        let state = token_state(owner, 100);
+        let (state, _) = approve(context(owner), state, account(2), 10);
+        let (state, _) = approve(context(owner), state, account(3), 0);
+
+        let (state, events) = query_ledger_stats(context(owner), state);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            state.ledger_stats(),
+            LedgerStats {
+                holders: 1,
+                approvals: 1,
+                balance_sum: 100,
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod distribute_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{distribute, split_total};
+
+    #[test]
+    pub fn test_shares_sum_to_total() {
+        for total in [0, 1, 10, 101, 9_999] {
+            for recipients in 1..=7 {
+                let shares = split_total(total, recipients, true);
+                assert_eq!(shares.iter().sum::<u64>(), total);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_remainder_to_first_recipient() {
+        let owner = account(1);
+        let recipients = vec![account(2), account(3), account(4)];
+
+        let (mut state, _) = distribute(
+            context(owner),
+            token_state(owner, 100),
+            100,
+            recipients,
+            true,
+        );
+
+        assert_eq!(state.balance_of(account(2)), 34);
+        assert_eq!(state.balance_of(account(3)), 33);
+        assert_eq!(state.balance_of(account(4)), 33);
+        assert_eq!(state.balance_of(owner), 0);
+    }
+
+    #[test]
+    pub fn test_remainder_kept_by_sender() {
+        let owner = account(1);
+        let recipients = vec![account(2), account(3), account(4)];
+
+        let (mut state, _) = distribute(
+            context(owner),
+            token_state(owner, 100),
+            100,
+            recipients,
+            false,
+        );
+
+        assert_eq!(state.balance_of(account(2)), 33);
+        assert_eq!(state.balance_of(account(4)), 33);
+        assert_eq!(state.balance_of(owner), 1);
+        assert!(state.is_supply_consistent());
+    }
+
+    #[test]
+    #[should_panic(expected = "Underflow in transfer - owner did not have enough tokens")]
+    pub fn test_distribute_more_than_balance_rejected() {
+        let owner = account(1);
+
+        distribute(
+            context(owner),
+            token_state(owner, 10),
+            11,
+            vec![account(2)],
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod mint_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::mint;
+
+    #[test]
+    pub fn test_owner_can_mint() {
+        let owner = account(1);
+        let recipient = account(2);
+
+        let (mut state, _) = mint(context(owner), token_state(owner, 100), recipient, 50);
+
+        assert_eq!(state.total_supply, 150);
+        assert_eq!(state.balance_of(recipient), 50);
+        assert!(state.is_supply_consistent());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can mint tokens")]
+    pub fn test_non_owner_cannot_mint() {
+        let owner = account(1);
+
+        mint(context(account(2)), token_state(owner, 100), account(2), 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in mint - total supply cannot exceed u64::MAX")]
+    pub fn test_mint_overflow_rejected() {
+        let owner = account(1);
+
+        mint(context(owner), token_state(owner, u64::MAX), account(2), 1);
+    }
+
+    #[test]
+    pub fn test_mint_up_to_max_supply() {
+        let owner = account(1);
+        let mut state = token_state(owner, 100);
+        state.max_supply = Some(150);
+
+        let (state, _) = mint(context(owner), state, account(2), 50);
+
+        assert_eq!(state.total_supply, 150);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint would exceed the max supply of 150")]
+    pub fn test_mint_past_max_supply_rejected() {
+        let owner = account(1);
+        let mut state = token_state(owner, 100);
+        state.max_supply = Some(150);
+
+        mint(context(owner), state, account(2), 51);
+    }
+}
+
+#[cfg(test)]
+mod burn_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{approve, burn, burn_from};
+
+    #[test]
+    pub fn test_burn_reduces_supply() {
+        let owner = account(1);
+
+        let (mut state, _) = burn(context(owner), token_state(owner, 100), 40);
+
+        assert_eq!(state.total_supply, 60);
+        assert_eq!(state.balance_of(owner), 60);
+        assert!(state.is_supply_consistent());
+    }
+
+    #[test]
+    pub fn test_burning_everything_removes_account() {
+        let owner = account(1);
+
+        let (state, _) = burn(context(owner), token_state(owner, 100), 100);
+
+        assert!(!state.balances.contains_key(&owner));
+        assert_eq!(state.total_supply, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Underflow in burn - owner did not have enough tokens")]
+    pub fn test_burning_more_than_owned_rejected() {
+        let owner = account(1);
+
+        burn(context(owner), token_state(owner, 100), 101);
+    }
+
+    #[test]
+    pub fn test_burn_approved_tokens() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, 30);
+
+        let (mut state, _) = burn_from(context(spender), state, owner, 20);
+
+        assert_eq!(state.total_supply, 80);
+        assert_eq!(state.balance_of(owner), 80);
+        assert_eq!(state.allowance(owner, spender), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Underflow in burn_from - tokens has not been approved for burning")]
+    pub fn test_burn_unapproved_tokens_rejected() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, 10);
+
+        burn_from(context(spender), state, owner, 20);
+    }
+}
+
+#[cfg(test)]
+mod allowance_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{approve, decrease_allowance, increase_allowance, transfer_from};
+
+    #[test]
+    pub fn test_interleaved_adjustments() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, 10);
+
+        let (state, _) = increase_allowance(context(owner), state, spender, 15);
+        let (state, _) = decrease_allowance(context(owner), state, spender, 5);
+        let (state, _) = transfer_from(context(spender), state, owner, spender, 8);
+        let (mut state, _) = increase_allowance(context(owner), state, spender, 3);
+
+        assert_eq!(state.allowance(owner, spender), 15);
+    }
+
+    #[test]
+    pub fn test_decrease_saturates_at_zero() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = increase_allowance(context(owner), token_state(owner, 100), spender, 5);
+
+        let (mut state, _) = decrease_allowance(context(owner), state, spender, 7);
+
+        assert_eq!(state.allowance(owner, spender), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in increase_allowance")]
+    pub fn test_increase_overflow_rejected() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, u64::MAX);
+
+        increase_allowance(context(owner), state, spender, 1);
+    }
+}
+
+#[cfg(test)]
+mod recipient_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{approve, transfer, transfer_from};
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer tokens to the zero address")]
+    pub fn test_transfer_to_zero_address_rejected() {
+        let owner = account(1);
+
+        transfer(context(owner), token_state(owner, 100), account(0), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer tokens to the zero address")]
+    pub fn test_transfer_from_to_zero_address_rejected() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, 10);
+
+        transfer_from(context(spender), state, owner, account(0), 10);
+    }
+
+    #[test]
+    pub fn test_self_transfer_leaves_ledger_unchanged() {
+        let owner = account(1);
+
+        let (mut state, _) = transfer(context(owner), token_state(owner, 100), owner, 100);
+
+        assert_eq!(state.balance_of(owner), 100);
+        assert!(state.is_supply_consistent());
+    }
+}
+
+#[cfg(test)]
+mod ownership_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{mint, transfer_ownership};
+
+    #[test]
+    pub fn test_new_owner_can_mint() {
+        let (owner, new_owner) = (account(1), account(2));
+        let (state, _) = transfer_ownership(context(owner), token_state(owner, 100), new_owner);
+
+        let (mut state, _) = mint(context(new_owner), state, new_owner, 10);
+
+        assert_eq!(state.owner, new_owner);
+        assert_eq!(state.balance_of(new_owner), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can mint tokens")]
+    pub fn test_old_owner_loses_minting_rights() {
+        let (owner, new_owner) = (account(1), account(2));
+        let (state, _) = transfer_ownership(context(owner), token_state(owner, 100), new_owner);
+
+        mint(context(owner), state, owner, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can transfer ownership")]
+    pub fn test_non_owner_cannot_transfer_ownership() {
+        let owner = account(1);
+
+        transfer_ownership(context(account(2)), token_state(owner, 100), account(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer ownership to the zero address")]
+    pub fn test_ownership_to_zero_address_rejected() {
+        let owner = account(1);
+
+        transfer_ownership(context(owner), token_state(owner, 100), account(0));
+    }
+}
+
+#[cfg(test)]
+mod owner_query_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{query_owner, transfer_ownership};
+
+    #[test]
+    pub fn test_query_reports_owner_without_change() {
+        let owner = account(1);
+        let (state, _) = transfer_ownership(context(owner), token_state(owner, 100), account(2));
+
+        let (state, events) = query_owner(context(account(3)), state);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.owner, account(2));
+        assert_eq!(state.balances[&owner], 100);
+    }
+}
+
+#[cfg(test)]
+mod decimals_query_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::query_decimals;
+
+    #[test]
+    pub fn test_query_reports_decimals_without_change() {
+        let owner = account(1);
+
+        let (state, events) = query_decimals(context(account(2)), token_state(owner, 100));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.decimals, 8);
+        assert_eq!(state.balances.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod balance_query_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{query_balance, transfer};
+
+    #[test]
+    pub fn test_query_reports_balance() {
+        let owner = account(1);
+        let (state, _) = transfer(context(owner), token_state(owner, 100), account(2), 40);
+
+        let (state, events) = query_balance(context(account(3)), state, account(2));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.balances[&account(2)], 40);
+    }
+
+    #[test]
+    pub fn test_query_of_unknown_account_adds_no_entry() {
+        let owner = account(1);
+
+        let (state, events) = query_balance(context(owner), token_state(owner, 100), account(2));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.balances.len(), 1);
+        assert!(!state.balances.contains_key(&account(2)));
+    }
+}
+
+#[cfg(test)]
+mod holder_count_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{query_holder_count, transfer};
+
+    #[test]
+    pub fn test_count_drops_when_full_balance_moves() {
+        let owner = account(1);
+        let (state, _) = transfer(context(owner), token_state(owner, 100), account(2), 40);
+        assert_eq!(state.holder_count(), 2);
+
+        let (state, _) = transfer(context(account(2)), state, owner, 40);
+        let (state, events) = query_holder_count(context(owner), state);
+
+        assert_eq!(state.holder_count(), 1);
+        assert_eq!(events.len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod freeze_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{
+        approve, bulk_transfer, core_burn, core_transfer, freeze, mint, transfer_from, unfreeze,
+        Transfer,
+    };
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_frozen_sender_cannot_transfer() {
+        let owner = account(1);
+        let alice = account(2);
+        let state = core_transfer(owner, token_state(owner, 100), alice, 50).0;
+        let (state, _) = freeze(context(owner), state, alice);
+
+        core_transfer(alice, state, owner, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_frozen_recipient_cannot_receive() {
+        let owner = account(1);
+        let alice = account(2);
+        let (state, _) = freeze(context(owner), token_state(owner, 100), alice);
+
+        core_transfer(owner, state, alice, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_frozen_spender_cannot_transfer_from() {
+        let owner = account(1);
+        let alice = account(2);
+        let bob = account(3);
+        let (state, _) = approve(context(owner), token_state(owner, 100), alice, 50);
+        let (state, _) = freeze(context(owner), state, alice);
+
+        transfer_from(context(alice), state, owner, bob, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_bulk_transfer_with_frozen_recipient_rejected() {
+        let owner = account(1);
+        let alice = account(2);
+        let bob = account(3);
+        let (state, _) = freeze(context(owner), token_state(owner, 100), bob);
+        let transfers = vec![
+            Transfer {
+                to: alice,
+                value: 10,
+            },
+            Transfer { to: bob, value: 10 },
+        ];
+
+        bulk_transfer(context(owner), state, transfers);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_frozen_account_cannot_receive_mint() {
+        let owner = account(1);
+        let alice = account(2);
+        let (state, _) = freeze(context(owner), token_state(owner, 100), alice);
+
+        mint(context(owner), state, alice, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is frozen")]
+    pub fn test_frozen_account_cannot_burn() {
+        let owner = account(1);
+        let alice = account(2);
+        let state = core_transfer(owner, token_state(owner, 100), alice, 50).0;
+        let (state, _) = freeze(context(owner), state, alice);
+
+        core_burn(alice, state, 10);
+    }
+
+    #[test]
+    pub fn test_unfrozen_account_can_transfer() {
+        let owner = account(1);
+        let alice = account(2);
+        let (state, _) = freeze(context(owner), token_state(owner, 100), alice);
+        let (state, _) = unfreeze(context(owner), state, alice);
+
+        let (mut state, _) = core_transfer(owner, state, alice, 10);
+
+        assert_eq!(state.balance_of(alice), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can freeze accounts")]
+    pub fn test_non_owner_cannot_freeze() {
+        let owner = account(1);
+        let alice = account(2);
+
+        freeze(context(alice), token_state(owner, 100), owner);
+    }
+}
+
+#[cfg(test)]
+mod bulk_transfer_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{bulk_transfer, Transfer};
+
+    #[test]
+    pub fn test_batch_within_balance() {
+        let owner = account(1);
+        let transfers = vec![
+            Transfer {
+                to: account(2),
+                value: 40,
+            },
+            Transfer {
+                to: account(3),
+                value: 60,
+            },
+        ];
+
+        let (mut state, _) = bulk_transfer(context(owner), token_state(owner, 100), transfers);
+
+        assert_eq!(state.balance_of(account(2)), 40);
+        assert_eq!(state.balance_of(account(3)), 60);
+        assert_eq!(state.balance_of(owner), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient balance for batch")]
+    pub fn test_batch_exceeding_balance_on_last_transfer_rejected() {
+        let owner = account(1);
+        let transfers = vec![
+            Transfer {
+                to: account(2),
+                value: 40,
+            },
+            Transfer {
+                to: account(3),
+                value: 50,
+            },
+            Transfer {
+                to: account(4),
+                value: 11,
+            },
+        ];
+
+        bulk_transfer(context(owner), token_state(owner, 100), transfers);
+    }
+}
+
+#[cfg(test)]
+mod max_approval_tests {
+    use super::test_utils::{account, context, token_state};
+    use crate::{approve, increase_allowance, set_max_approval, transfer_from, NO_APPROVAL_CAP};
+
+    #[test]
+    pub fn test_approval_within_cap() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = set_max_approval(context(owner), token_state(owner, 100), 50);
+
+        let (state, _) = approve(context(owner), state, spender, 50);
+        let (mut state, _) = increase_allowance(context(owner), state, spender, 0);
+
+        assert_eq!(state.allowance(owner, spender), 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "Allowance cannot exceed the max approval of 50")]
+    pub fn test_approval_above_cap_rejected() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = set_max_approval(context(owner), token_state(owner, 100), 50);
+
+        approve(context(owner), state, spender, 51);
+    }
+
+    #[test]
+    #[should_panic(expected = "Allowance cannot exceed the max approval of 50")]
+    pub fn test_increase_above_cap_rejected() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = set_max_approval(context(owner), token_state(owner, 100), 50);
+        let (state, _) = approve(context(owner), state, spender, 40);
+
+        increase_allowance(context(owner), state, spender, 11);
+    }
+
+    #[test]
+    pub fn test_allowance_above_new_cap_can_still_be_spent() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = approve(context(owner), token_state(owner, 100), spender, 80);
+        let (state, _) = set_max_approval(context(owner), state, 50);
+
+        let (mut state, _) = transfer_from(context(spender), state, owner, spender, 10);
+
+        assert_eq!(state.allowance(owner, spender), 70);
+    }
+
+    #[test]
+    pub fn test_removing_cap_allows_unlimited_approval() {
+        let (owner, spender) = (account(1), account(2));
+        let (state, _) = set_max_approval(context(owner), token_state(owner, 100), 50);
+        let (state, _) = set_max_approval(context(owner), state, NO_APPROVAL_CAP);
+
+        let (mut state, _) = approve(context(owner), state, spender, u64::MAX);
+
+        assert_eq!(state.allowance(owner, spender), u64::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can set the max approval")]
+    pub fn test_only_owner_sets_cap() {
+        let owner = account(1);
+        set_max_approval(context(account(2)), token_state(owner, 100), 50);
+    }
+}
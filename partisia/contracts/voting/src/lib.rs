@@ -1,16 +1,73 @@
 //! This is the example voting contract.
 //! For more information on how to build it see README.md
+//!
+//! Members carry a fixed weight by default. A poll initialized with a `weight_token` instead
+//! weighs each vote by the voter's balance of that token: `vote` queries the balance and the vote
+//! is registered with that weight in `vote_callback`. The weight is frozen at the voter's first vote,
+//! so changing a vote keeps it. The balance is not locked or snapshotted however: a voter can transfer
+//! the tokens after voting and the recipient votes with them again, so the same tokens may be counted
+//! more than once. Use a weight token whose holders cannot move it during the poll if that matters.
+//!
+//! Once a poll has been closed for its `retention_period`, the owner may `archive` it. This clears the
+//! individual `votes` and keeps only the final `yes_count` and `no_count`, their weights and the winner.
 #![allow(unused_variables)]
 
+mod tests;
+
 #[macro_use]
 extern crate pbc_contract_codegen;
 extern crate pbc_contract_common;
 
 use std::collections::{BTreeMap, BTreeSet};
 
-use pbc_contract_common::address::Address;
-use pbc_contract_common::context::ContractContext;
+use create_type_spec_derive::CreateTypeSpec;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use pbc_contract_common::address::{Address, Shortname};
+use pbc_contract_common::context::{CallbackContext, ContractContext};
 use pbc_contract_common::events::EventGroup;
+use pbc_traits::ReadWriteRPC;
+use read_write_rpc_derive::ReadWriteRPC;
+
+/// The maximum length in bytes of a proposal title.
+const MAX_TITLE_LENGTH: usize = 100;
+
+/// The maximum length in bytes of a proposal description.
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+
+/// The vote of a member who abstains. Abstentions count towards the quorum,
+/// but not towards the "yes" or "no" tally.
+const ABSTAIN: u8 = 2;
+
+/// Value of `closed` while the poll accepts votes.
+const POLL_OPEN: u8 = 0;
+
+/// Value of `closed` once the quorum of the parliament has voted.
+const CLOSED_QUORUM_REACHED: u8 = 1;
+
+/// Value of `closed` once the poll was closed after its deadline passed.
+const CLOSED_DEADLINE_PASSED: u8 = 2;
+
+/// Value of `closed` once an option reached the `decisive_weight` before the quorum voted.
+const CLOSED_DECIDED: u8 = 3;
+
+/// A vote collected off-chain and relayed to the contract by the `vote_operator`.
+///
+/// # Members
+///
+/// * `voter`: [`Address`] - the member of the parliament casting the vote.
+/// * `vote`: [`u8`] - the vote, `0` for "no", `1` for "yes" and [`ABSTAIN`] to abstain.
+/// * `nonce`: [`u64`] - the next unused nonce of the voter, see `signed_vote_nonces`.
+/// * `signature`: [`Vec`]<[`u8`]> - the 64 byte ECDSA signature over [`signed_vote_message`],
+///   made with the key the voter registered through `register_voting_key`.
+///
+#[derive(ReadWriteRPC, CreateTypeSpec, Clone)]
+pub struct SignedVote {
+    voter: Address,
+    vote: u8,
+    nonce: u64,
+    signature: Vec<u8>,
+}
 
 /// This is the state of the contract which is persisted on chain.
 ///
@@ -19,31 +76,311 @@ use pbc_contract_common::events::EventGroup;
 /// # Members
 ///
 /// * `proposal_id`: [`u64`] -  the identification of the proposal.
+/// * `title`: [`String`] - the title of the proposal.
+/// * `description`: [`String`] - the description of what is being voted on.
 /// * `mp_addresses`: [`Vec`]<[`Address`]> - the list of legal voters.
-/// * `votes`: [`BTreeMap`]<[`Address`], [`u8`]> - the votes that have already been cast.
-/// * `closed`: [`u8`] - bool to determine if the poll is over.
+/// * `votes`: [`BTreeMap`]<[`Address`], [`u8`]> - the votes that have already been cast, including abstentions.
+/// * `closed`: [`u8`] - [`POLL_OPEN`] while the poll is running, otherwise the reason it closed.
+/// * `start_time`: [`i64`] - the block production time in milliseconds from which votes are accepted.
+/// * `result_listener`: [`Option`]<[`Address`]> - a contract that is informed of the result when the poll closes.
+/// * `owner`: [`Address`] - the deployer of the poll, who may reopen it.
+/// * `closed_at`: [`i64`] - the block production time in milliseconds at which the poll last closed.
+/// * `reopen_window`: [`i64`] - how long in milliseconds after closing the owner may reopen the poll.
+/// * `vote_operator`: [`Option`]<[`Address`]> - the relay allowed to submit signed votes, if any.
+/// * `voting_keys`: [`BTreeMap`]<[`Address`], [`Vec`]<[`u8`]>> - the SEC1 encoded secp256k1 public keys
+///   members have registered for signed voting.
+/// * `signed_vote_nonces`: [`BTreeMap`]<[`Address`], [`u64`]> - the nonce the next signed vote of each
+///   member must carry. Absent members start at zero.
+/// * `weights`: [`BTreeMap`]<[`Address`], [`u64`]> - the voting weight of each member.
+/// * `quorum`: [`u32`] - the percentage of members that must vote before the poll closes.
+/// * `weight_token`: [`Option`]<[`Address`]> - the token whose balance weighs each vote, if any.
+/// * `deadline_millis`: [`i64`] - the block production time in milliseconds after which no votes
+///   are accepted and anyone may close the poll. Zero means the poll has no deadline.
+/// * `decisive_weight`: [`u64`] - the weight at which the "yes" or "no" option decides the poll
+///   early. Zero disables early decisions.
+/// * `delegations`: [`BTreeMap`]<[`Address`], [`Address`]> - the member each delegating member has
+///   entrusted with their vote.
+/// * `max_delegators`: [`u32`] - the number of members a single delegate may represent. Zero means
+///   no limit.
+/// * `retention_period`: [`i64`] - how long in milliseconds after closing the `votes` are kept before
+///   the owner may archive the poll.
+/// * `archived`: [`bool`] - whether the `votes` have been cleared by `archive`.
+/// * `yes_count`: [`u32`] - the final number of "yes" votes, recorded when the poll is archived.
+/// * `no_count`: [`u32`] - the final number of "no" votes, recorded when the poll is archived.
+/// * `yes_weight`: [`u64`] - the final weight of the "yes" votes, recorded when the poll is archived.
+/// * `no_weight`: [`u64`] - the final weight of the "no" votes, recorded when the poll is archived.
+/// * `archived_winner`: [`Option`]<[`u8`]> - the early-decision winner, recorded when the poll is
+///   archived.
+/// * `weighed_voters`: [`BTreeSet`]<[`Address`]> - the voters whose weight has been frozen at their
+///   first vote in a poll with a `weight_token`.
 ///
 #[state]
 pub struct VotingContractState {
     proposal_id: u64,
+    title: String,
+    description: String,
     mp_addresses: Vec<Address>,
     votes: BTreeMap<Address, u8>,
     closed: u8,
+    start_time: i64,
+    result_listener: Option<Address>,
+    owner: Address,
+    closed_at: i64,
+    reopen_window: i64,
+    vote_operator: Option<Address>,
+    voting_keys: BTreeMap<Address, Vec<u8>>,
+    signed_vote_nonces: BTreeMap<Address, u64>,
+    weights: BTreeMap<Address, u64>,
+    quorum: u32,
+    weight_token: Option<Address>,
+    deadline_millis: i64,
+    decisive_weight: u64,
+    delegations: BTreeMap<Address, Address>,
+    max_delegators: u32,
+    retention_period: i64,
+    archived: bool,
+    yes_count: u32,
+    no_count: u32,
+    yes_weight: u64,
+    no_weight: u64,
+    archived_winner: Option<u8>,
+    weighed_voters: BTreeSet<Address>,
 }
 
 impl VotingContractState {
+    fn assert_can_vote(&self, voter: Address, vote: u8, now: i64) {
+        assert_eq!(self.closed, POLL_OPEN, "The poll is closed");
+        assert!(
+            now >= self.start_time,
+            "The poll does not accept votes before {}",
+            self.start_time
+        );
+        assert!(
+            !self.deadline_passed(now),
+            "The poll does not accept votes after its deadline {}",
+            self.deadline_millis
+        );
+        assert!(
+            self.mp_addresses.contains(&voter),
+            "Only members of the parliament can vote"
+        );
+        assert!(
+            vote == 0 || vote == 1 || vote == ABSTAIN,
+            "Only \"yes\", \"no\" and \"abstain\" votes are allowed"
+        );
+    }
+
+    /// Asserts that `signed_vote` carries the next nonce of its voter and is signed by the key the
+    /// voter registered, then uses up the nonce so the signature cannot be submitted again.
+    fn consume_signed_vote(&mut self, contract_address: Address, signed_vote: &SignedVote) {
+        let next_nonce = self
+            .signed_vote_nonces
+            .get(&signed_vote.voter)
+            .copied()
+            .unwrap_or(0);
+        assert_eq!(
+            signed_vote.nonce, next_nonce,
+            "Invalid nonce on the vote of {:?}",
+            signed_vote.voter
+        );
+        let key = self
+            .voting_keys
+            .get(&signed_vote.voter)
+            .expect("The voter has not registered a voting key");
+        let verifying_key = VerifyingKey::from_sec1_bytes(key).unwrap();
+        let signature =
+            Signature::from_slice(&signed_vote.signature).expect("Malformed vote signature");
+        let message = signed_vote_message(
+            contract_address,
+            self.proposal_id,
+            self.closed_at,
+            signed_vote.vote,
+            signed_vote.nonce,
+        );
+        assert!(
+            verifying_key.verify(&message, &signature).is_ok(),
+            "Invalid signature on the vote of {:?}",
+            signed_vote.voter
+        );
+        self.signed_vote_nonces
+            .insert(signed_vote.voter, next_nonce + 1);
+    }
+
+    /// Registers `vote` and closes the poll if the quorum is reached.
+    fn cast_vote(&mut self, voter: Address, vote: u8, now: i64) -> Vec<EventGroup> {
+        self.register_vote(voter, vote);
+        self.close_if_finished(now);
+        self.result_events()
+    }
+
+    /// Registers the vote of `address` and of every member who delegated to it. Voting directly
+    /// overrides the delegation of `address`.
     fn register_vote(&mut self, address: Address, vote: u8) {
+        self.delegations.remove(&address);
         self.votes.insert(address, vote);
+        for (delegator, delegate) in self.delegations.iter() {
+            if *delegate == address {
+                self.votes.insert(*delegator, vote);
+            }
+        }
+    }
+
+    /// The number of members who have delegated their vote to `delegate`.
+    fn delegator_count(&self, delegate: Address) -> u32 {
+        self.delegations
+            .values()
+            .filter(|d| **d == delegate)
+            .count() as u32
+    }
+
+    /// The number of votes required to close the poll, `quorum` percent of the members rounded up.
+    fn required_votes(&self) -> usize {
+        (self.mp_addresses.len() * self.quorum as usize).div_ceil(100)
     }
 
-    fn close_if_finished(&mut self) {
-        if self.votes.len() == self.mp_addresses.len() {
-            self.closed = 1;
+    fn close_if_finished(&mut self, now: i64) {
+        if self.decided_option().is_some() {
+            self.closed = CLOSED_DECIDED;
+            self.closed_at = now;
+        } else if self.votes.len() >= self.required_votes() {
+            self.closed = CLOSED_QUORUM_REACHED;
+            self.closed_at = now;
         };
     }
+
+    /// The option whose weight has reached `decisive_weight`, if early decisions are enabled.
+    fn decided_option(&self) -> Option<u8> {
+        if self.decisive_weight == 0 {
+            return None;
+        }
+        let (yes, no) = self.weighted_tally();
+        if yes >= self.decisive_weight {
+            Some(1)
+        } else if no >= self.decisive_weight {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// The option that decided the poll early, if it closed because of the `decisive_weight`.
+    pub fn winner(&self) -> Option<u8> {
+        if self.archived {
+            self.archived_winner
+        } else if self.closed == CLOSED_DECIDED {
+            self.decided_option()
+        } else {
+            None
+        }
+    }
+
+    fn deadline_passed(&self, now: i64) -> bool {
+        self.deadline_millis != 0 && now > self.deadline_millis
+    }
+
+    fn register_reopen(&mut self) {
+        self.closed = POLL_OPEN;
+        self.votes.clear();
+    }
+
+    fn count_votes(&self, vote: u8) -> u32 {
+        self.votes.values().filter(|v| **v == vote).count() as u32
+    }
+
+    /// The recorded vote of `voter`, or `None` if they have not voted.
+    pub fn vote_of(&self, voter: Address) -> Option<u8> {
+        self.votes.get(&voter).copied()
+    }
+
+    /// The current result of the poll as `(yes, no)`. Abstentions are not counted.
+    /// An archived poll reports the tally recorded when it was archived.
+    pub fn tally(&self) -> (u32, u32) {
+        if self.archived {
+            (self.yes_count, self.no_count)
+        } else {
+            (self.count_votes(1), self.count_votes(0))
+        }
+    }
+
+    fn sum_weights(&self, vote: u8) -> u64 {
+        self.votes
+            .iter()
+            .filter(|(_, v)| **v == vote)
+            .map(|(voter, _)| self.weights[voter])
+            .sum()
+    }
+
+    /// The current result of the poll as the summed `(yes, no)` weights of the voters.
+    pub fn weighted_tally(&self) -> (u64, u64) {
+        if self.archived {
+            (self.yes_weight, self.no_weight)
+        } else {
+            (self.sum_weights(1), self.sum_weights(0))
+        }
+    }
+
+    /// Whether the weight of the "yes" votes exceeds the weight of the "no" votes.
+    pub fn passed(&self) -> bool {
+        let (yes, no) = self.weighted_tally();
+        yes > no
+    }
+
+    /// Creates the event informing `result_listener` of the [`weighted_tally`](Self::weighted_tally),
+    /// if the poll is closed and a listener is registered.
+    fn result_events(&self) -> Vec<EventGroup> {
+        match self.result_listener {
+            Some(listener) if self.closed != POLL_OPEN => {
+                let (yes, no) = self.weighted_tally();
+                let mut event_group = EventGroup::builder();
+                event_group
+                    .call(listener, result_listener_callback())
+                    .argument(self.proposal_id)
+                    .argument(yes)
+                    .argument(no)
+                    .done();
+                vec![event_group.build()]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// The message a member signs to cast `vote` through the `vote_operator`.
+///
+/// The message binds the vote to this contract, the proposal and the current voting round,
+/// identified by `closed_at`, so a signature cannot be replayed after the poll is reopened.
+/// The voter's `nonce` keeps a signature from being replayed within the same round.
+pub fn signed_vote_message(
+    contract_address: Address,
+    proposal_id: u64,
+    closed_at: i64,
+    vote: u8,
+    nonce: u64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(45);
+    message.extend_from_slice(&contract_address.identifier);
+    message.extend_from_slice(&proposal_id.to_be_bytes());
+    message.extend_from_slice(&closed_at.to_be_bytes());
+    message.push(vote);
+    message.extend_from_slice(&nonce.to_be_bytes());
+    message
+}
+
+/// The shortname of the action on the weight token which returns the balance of an address.
+#[inline]
+fn token_balance_of() -> Shortname {
+    Shortname::from_u32(0x0B)
+}
+
+/// The shortname of the action on a result listener which receives the result of a closed poll,
+/// as the proposal id and the summed "yes" and "no" weights, all of type [`u64`].
+#[inline]
+fn result_listener_callback() -> Shortname {
+    Shortname::from_u32(0x30)
 }
 
-/// This is the main action of the contract in which the sender can cast a vote.
+/// This is the main action of the contract in which the sender can cast a vote:
+/// `1` for "yes", `0` for "no" or [`ABSTAIN`] to abstain.
 ///
 ///
 /// # Parameters
@@ -54,7 +391,9 @@ impl VotingContractState {
 ///
 /// # Returns
 ///
-/// The return value is the new state and an empty list of events.
+/// The return value is the new state and an empty list of events. If the poll has a
+/// `weight_token` and the sender has not been weighed yet, the state is unchanged, and the event
+/// queries the balance of the sender before the vote is registered in [`vote_callback`].
 ///
 #[action]
 pub fn vote(
@@ -62,20 +401,65 @@ pub fn vote(
     state: VotingContractState,
     vote: u8,
 ) -> (VotingContractState, Vec<EventGroup>) {
-    assert_eq!(state.closed, 0, "The poll is closed");
-    assert!(
-        state.mp_addresses.contains(&context.sender),
-        "Only members of the parliament can vote"
-    );
-    assert!(
-        vote == 0 || vote == 1,
-        "Only \"yes\" and \"no\" votes are allowed"
-    );
+    state.assert_can_vote(context.sender, vote, context.block_production_time);
+
+    if let Some(weight_token) = state
+        .weight_token
+        .filter(|_| !state.weighed_voters.contains(&context.sender))
+    {
+        let mut event_group = EventGroup::builder();
+        event_group
+            .call(weight_token, token_balance_of())
+            .argument(context.sender)
+            .done();
+        event_group
+            .with_callback(SHORTNAME_VOTE_CALLBACK)
+            .argument(context.sender)
+            .argument(vote)
+            .done();
+        return (state, vec![event_group.build()]);
+    }
 
     let mut new_state = state;
-    new_state.register_vote(context.sender, vote);
-    new_state.close_if_finished();
-    (new_state, vec![])
+    let events = new_state.cast_vote(context.sender, vote, context.block_production_time);
+    (new_state, events)
+}
+
+/// Registers a vote of a poll with a `weight_token`, weighted by the balance the voter held at
+/// their first vote. Voters without a balance cannot vote.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `callback_context`: [`CallbackContext`] - the result of the balance query.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `voter`: [`Address`] - the member who voted.
+/// * `vote`: [`u8`] - the vote.
+///
+/// # Returns
+///
+/// The new state and, if the poll closed, the event informing the result listener.
+///
+#[callback(shortname = 0x01)]
+pub fn vote_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    state: VotingContractState,
+    voter: Address,
+    vote: u8,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert!(callback_context.success, "The balance query failed");
+    state.assert_can_vote(voter, vote, context.block_production_time);
+    let balance = u64::rpc_read_from(&mut callback_context.results[0].return_data.as_slice());
+    assert!(balance > 0, "Only holders of the weight token can vote");
+
+    let mut new_state = state;
+    // A second query may have been sent before the first one returned, but the weight is frozen
+    if new_state.weighed_voters.insert(voter) {
+        new_state.weights.insert(voter, balance);
+    }
+    let events = new_state.cast_vote(voter, vote, context.block_production_time);
+    (new_state, events)
 }
 
 /// Initial function to bootstrap the contract's state. Must return a the (state-struct, events).
@@ -84,7 +468,17 @@ pub fn vote(
 ///
 /// * `ctx`: [`ContractContext`] - the contract context containing sender and chain information.
 /// * `proposal_id`: [`u64`] - the id of the proposal.
+/// * `title`: [`String`] - the non-empty title of the proposal, at most [`MAX_TITLE_LENGTH`] bytes.
+/// * `description`: [`String`] - the description of the proposal, at most [`MAX_DESCRIPTION_LENGTH`] bytes.
 /// * `mp_addresses`: [`u64`] - the list of legal voters.
+/// * `start_time`: [`i64`] - the block production time in milliseconds from which votes are accepted.
+/// * `result_listener`: [`Option`]<[`Address`]> - a contract to inform of the result when the poll closes.
+/// * `weights`: [`Option`]<[`Vec`]<[`u64`]>> - the non-zero weight of each member, in the order of
+///   `mp_addresses`. Every member weighs 1 if not supplied.
+/// * `quorum`: [`u32`] - the percentage, from 1 to 100, of members that must vote before the poll closes.
+/// * `weight_token`: [`Option`]<[`Address`]> - a token whose balance weighs each vote instead of `weights`.
+/// * `deadline_millis`: [`i64`] - the block production time in milliseconds after which no votes are
+///   accepted, or zero for no deadline.
 ///
 /// # Returns
 ///
@@ -93,15 +487,43 @@ pub fn vote(
 ///
 #[init]
 pub fn initialize(
-    _ctx: ContractContext,
+    ctx: ContractContext,
     proposal_id: u64,
+    title: String,
+    description: String,
     mp_addresses: Vec<Address>,
+    start_time: i64,
+    result_listener: Option<Address>,
+    weights: Option<Vec<u64>>,
+    quorum: u32,
+    weight_token: Option<Address>,
+    deadline_millis: i64,
 ) -> (VotingContractState, Vec<EventGroup>) {
+    assert!(!title.is_empty(), "The proposal title cannot be empty");
+    assert!(
+        title.len() <= MAX_TITLE_LENGTH,
+        "The proposal title cannot exceed {} bytes",
+        MAX_TITLE_LENGTH
+    );
+    assert!(
+        description.len() <= MAX_DESCRIPTION_LENGTH,
+        "The proposal description cannot exceed {} bytes",
+        MAX_DESCRIPTION_LENGTH
+    );
     assert_ne!(
         mp_addresses.len(),
         0,
         "Cannot start a poll without parliament members"
     );
+    assert!(
+        (1..=100).contains(&quorum),
+        "The quorum must be between 1 and 100 percent"
+    );
+
+    assert!(
+        deadline_millis == 0 || deadline_millis >= start_time,
+        "The deadline cannot be before the start time"
+    );
 
     let mut address_set = BTreeSet::new();
     for mp_address in mp_addresses.iter() {
@@ -113,11 +535,512 @@ pub fn initialize(
         "Duplicate MP address in input"
     );
 
+    assert!(
+        weights.is_none() || weight_token.is_none(),
+        "Weights cannot be combined with a weight token"
+    );
+    let weights = weights.unwrap_or_else(|| vec![1; mp_addresses.len()]);
+    assert_eq!(
+        weights.len(),
+        mp_addresses.len(),
+        "There must be exactly one weight per MP address"
+    );
+    assert!(
+        weights.iter().all(|weight| *weight > 0),
+        "Weights must be positive"
+    );
+    let weights = mp_addresses.iter().copied().zip(weights).collect();
+
     let state = VotingContractState {
         proposal_id,
+        title,
+        description,
         mp_addresses,
         votes: BTreeMap::new(),
-        closed: 0,
+        closed: POLL_OPEN,
+        start_time,
+        result_listener,
+        owner: ctx.sender,
+        closed_at: 0,
+        reopen_window: 0,
+        vote_operator: None,
+        voting_keys: BTreeMap::new(),
+        signed_vote_nonces: BTreeMap::new(),
+        weights,
+        quorum,
+        weight_token,
+        deadline_millis,
+        decisive_weight: 0,
+        delegations: BTreeMap::new(),
+        max_delegators: 0,
+        retention_period: 0,
+        archived: false,
+        yes_count: 0,
+        no_count: 0,
+        yes_weight: 0,
+        no_weight: 0,
+        archived_winner: None,
+        weighed_voters: BTreeSet::new(),
     };
     (state, vec![])
 }
+
+/// Sets how long after closing the owner may reopen the poll. A window of zero disables reopening.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `reopen_window`: [`i64`] - the window in milliseconds.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn set_reopen_window(
+    context: ContractContext,
+    state: VotingContractState,
+    reopen_window: i64,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the reopen window"
+    );
+    assert!(reopen_window >= 0, "The reopen window cannot be negative");
+    let mut new_state = state;
+    new_state.reopen_window = reopen_window;
+    (new_state, vec![])
+}
+
+/// Sets the weight at which an option decides the poll before the quorum has voted. The decisive
+/// weight can only be changed before the first vote is cast.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `decisive_weight`: [`u64`] - the decisive weight, or zero to disable early decisions.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn set_decisive_weight(
+    context: ContractContext,
+    state: VotingContractState,
+    decisive_weight: u64,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the decisive weight"
+    );
+    assert!(
+        state.closed == POLL_OPEN && state.votes.is_empty(),
+        "The decisive weight cannot change once voting has started"
+    );
+    let mut new_state = state;
+    new_state.decisive_weight = decisive_weight;
+    (new_state, vec![])
+}
+
+/// Reopens a poll that closed because its quorum voted, allowing members to change their votes.
+/// The poll closes again once the quorum has voted anew.
+///
+/// Reopening is only possible within `reopen_window` of the poll closing, and never for a poll
+/// that closed for any other reason. A poll with a `result_listener` cannot be reopened, as the
+/// listener has already recorded its result and the proposal as closed.
+///
+/// Note that this places trust in the owner: a poll with a non-zero window is not final until the
+/// window has passed, and the owner decides whether it is reopened.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn reopen(
+    context: ContractContext,
+    state: VotingContractState,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can reopen the poll"
+    );
+    assert_eq!(
+        state.closed, CLOSED_QUORUM_REACHED,
+        "Only a poll closed by its members voting can be reopened"
+    );
+    assert!(!state.archived, "An archived poll cannot be reopened");
+    assert!(
+        state.result_listener.is_none(),
+        "A poll reporting to a result listener cannot be reopened"
+    );
+    assert!(
+        state.reopen_window > 0,
+        "Reopening is disabled for this poll"
+    );
+    assert!(
+        context.block_production_time <= state.closed_at + state.reopen_window,
+        "The poll can no longer be reopened"
+    );
+    let mut new_state = state;
+    new_state.register_reopen();
+    (new_state, vec![])
+}
+
+/// Sets how long after closing the `votes` of the poll are kept before it can be archived.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `retention_period`: [`i64`] - the retention period in milliseconds.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn set_retention_period(
+    context: ContractContext,
+    state: VotingContractState,
+    retention_period: i64,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the retention period"
+    );
+    assert!(
+        retention_period >= 0,
+        "The retention period cannot be negative"
+    );
+    let mut new_state = state;
+    new_state.retention_period = retention_period;
+    (new_state, vec![])
+}
+
+/// Archives a poll that has been closed for at least its `retention_period`, shrinking its state.
+/// The `votes` are cleared and the final [`tally`](VotingContractState::tally) is kept in `yes_count`
+/// and `no_count`, the [`weighted_tally`](VotingContractState::weighted_tally) in `yes_weight` and
+/// `no_weight`, and the [`winner`](VotingContractState::winner) in `archived_winner`. An archived poll
+/// cannot be reopened.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+///
+/// # Returns
+///
+/// The archived state and an empty list of events.
+///
+#[action]
+pub fn archive(
+    context: ContractContext,
+    state: VotingContractState,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can archive the poll"
+    );
+    assert_ne!(
+        state.closed, POLL_OPEN,
+        "Only a closed poll can be archived"
+    );
+    assert!(!state.archived, "The poll is already archived");
+    assert!(
+        context.block_production_time >= state.closed_at + state.retention_period,
+        "The poll cannot be archived before {}",
+        state.closed_at + state.retention_period
+    );
+
+    let (yes, no) = state.tally();
+    let (yes_weight, no_weight) = state.weighted_tally();
+    let winner = state.winner();
+    let mut new_state = state;
+    new_state.yes_count = yes;
+    new_state.no_count = no;
+    new_state.yes_weight = yes_weight;
+    new_state.no_weight = no_weight;
+    new_state.archived_winner = winner;
+    new_state.archived = true;
+    new_state.votes.clear();
+    (new_state, vec![])
+}
+
+/// Delegates the vote of the sender to another member, who then votes on their behalf until the
+/// sender votes directly. Delegations cannot be chained: a member cannot delegate to a member who has
+/// delegated, nor delegate while holding the votes of others. A delegate cannot represent more than
+/// `max_delegators` members.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `delegate`: [`Address`] - the member to entrust with the vote.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn delegate(
+    context: ContractContext,
+    state: VotingContractState,
+    delegate: Address,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(state.closed, POLL_OPEN, "The poll is closed");
+    assert!(
+        state.weight_token.is_none(),
+        "Votes weighted by a token cannot be delegated"
+    );
+    assert!(
+        state.mp_addresses.contains(&context.sender) && state.mp_addresses.contains(&delegate),
+        "Votes can only be delegated between members of the parliament"
+    );
+    assert_ne!(
+        context.sender, delegate,
+        "Members cannot delegate to themselves"
+    );
+    assert!(
+        !state.votes.contains_key(&context.sender),
+        "Members cannot delegate after voting"
+    );
+    assert!(
+        !state.delegations.contains_key(&delegate),
+        "Cannot delegate to a member who has delegated"
+    );
+    assert_eq!(
+        state.delegator_count(context.sender),
+        0,
+        "Cannot delegate while holding delegated votes"
+    );
+    assert!(
+        state.max_delegators == 0 || state.delegator_count(delegate) < state.max_delegators,
+        "A delegate can represent at most {} members",
+        state.max_delegators
+    );
+
+    let mut new_state = state;
+    new_state.delegations.insert(context.sender, delegate);
+    (new_state, vec![])
+}
+
+/// Sets how many members a single delegate may represent, preventing voting power from
+/// concentrating in one member. A limit of zero removes the cap. Existing delegations are kept.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `max_delegators`: [`u32`] - the new cap.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn set_max_delegators(
+    context: ContractContext,
+    state: VotingContractState,
+    max_delegators: u32,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the delegation cap"
+    );
+    let mut new_state = state;
+    new_state.max_delegators = max_delegators;
+    (new_state, vec![])
+}
+
+/// Sets the relay allowed to submit signed votes on behalf of members. `None` disables signed voting.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `vote_operator`: [`Option`]<[`Address`]> - the new operator.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn set_vote_operator(
+    context: ContractContext,
+    state: VotingContractState,
+    vote_operator: Option<Address>,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can set the vote operator"
+    );
+    let mut new_state = state;
+    new_state.vote_operator = vote_operator;
+    (new_state, vec![])
+}
+
+/// Registers the public key the sender signs off-chain votes with, replacing any previous key.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `public_key`: [`Vec`]<[`u8`]> - the SEC1 encoded secp256k1 public key.
+///
+/// # Returns
+///
+/// The new state and an empty list of events.
+///
+#[action]
+pub fn register_voting_key(
+    context: ContractContext,
+    state: VotingContractState,
+    public_key: Vec<u8>,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert!(
+        state.mp_addresses.contains(&context.sender),
+        "Only members of the parliament can register a voting key"
+    );
+    assert!(
+        VerifyingKey::from_sec1_bytes(&public_key).is_ok(),
+        "Invalid voting key"
+    );
+    let mut new_state = state;
+    new_state.voting_keys.insert(context.sender, public_key);
+    (new_state, vec![])
+}
+
+/// Registers votes collected off-chain, allowing members to vote without paying gas.
+/// Each vote must carry a valid signature by the key its voter registered, and is otherwise
+/// subject to the same rules as [`vote`]. Only the `vote_operator` may submit signed votes.
+/// Once a vote closes the poll, the remaining votes of the batch are ignored and their nonces are
+/// left unused.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `votes`: [`Vec`]<[`SignedVote`]> - the signed votes to register.
+///
+/// # Returns
+///
+/// The new state and, if the poll closed, the event informing the result listener.
+///
+#[action]
+pub fn submit_signed_votes(
+    context: ContractContext,
+    state: VotingContractState,
+    votes: Vec<SignedVote>,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(
+        Some(context.sender),
+        state.vote_operator,
+        "Only the vote operator can submit signed votes"
+    );
+    assert!(
+        state.weight_token.is_none(),
+        "Signed votes cannot be weighted by a token"
+    );
+
+    let mut new_state = state;
+    for signed_vote in votes.iter() {
+        new_state.assert_can_vote(
+            signed_vote.voter,
+            signed_vote.vote,
+            context.block_production_time,
+        );
+        new_state.consume_signed_vote(context.contract_address, signed_vote);
+        new_state.register_vote(signed_vote.voter, signed_vote.vote);
+        new_state.close_if_finished(context.block_production_time);
+        if new_state.closed != POLL_OPEN {
+            break;
+        }
+    }
+    let events = new_state.result_events();
+    (new_state, events)
+}
+
+/// Closes a poll whose deadline has passed, even if its quorum has not voted, so that it always
+/// reaches a terminal state. Anyone may close the poll.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+///
+/// # Returns
+///
+/// The closed state and the event informing the result listener, if any.
+///
+#[action]
+pub fn close_poll(
+    context: ContractContext,
+    state: VotingContractState,
+) -> (VotingContractState, Vec<EventGroup>) {
+    assert_eq!(state.closed, POLL_OPEN, "The poll is closed");
+    assert!(
+        state.deadline_passed(context.block_production_time),
+        "The poll cannot be closed before its deadline"
+    );
+
+    let mut new_state = state;
+    new_state.closed = CLOSED_DEADLINE_PASSED;
+    new_state.closed_at = context.block_production_time;
+    let events = new_state.result_events();
+    (new_state, events)
+}
+
+/// Reports the current [`tally`](VotingContractState::tally) of the poll as the return data of
+/// the call, so integrators can read the outcome without parsing `votes`. The state is unchanged.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+///
+/// # Returns
+///
+/// The unchanged state and an event returning the `(yes, no)` tally.
+///
+#[action]
+pub fn query_tally(
+    context: ContractContext,
+    state: VotingContractState,
+) -> (VotingContractState, Vec<EventGroup>) {
+    let mut event_group = EventGroup::builder();
+    event_group.return_data(state.tally());
+    (state, vec![event_group.build()])
+}
+
+/// Reports the [`vote_of`](VotingContractState::vote_of) `voter` as the return data of the call,
+/// so clients can confirm a recorded vote without reading all of `votes`. The state is unchanged.
+///
+/// # Parameters
+///
+/// * `context`: [`ContractContext`] - the contract context containing sender and chain information.
+/// * `state`: [`VotingContractState`] - the current state of the poll.
+/// * `voter`: [`Address`] - the address to report the vote of.
+///
+/// # Returns
+///
+/// The unchanged state and an event returning the vote, if any.
+///
+#[action]
+pub fn query_vote_of(
+    context: ContractContext,
+    state: VotingContractState,
+    voter: Address,
+) -> (VotingContractState, Vec<EventGroup>) {
+    let mut event_group = EventGroup::builder();
+    event_group.return_data(state.vote_of(voter));
+    (state, vec![event_group.build()])
+}
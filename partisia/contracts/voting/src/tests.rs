@@ -0,0 +1,1070 @@
+#[cfg(test)]
+mod test_utils {
+    use crate::{initialize, VotingContractState};
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::ContractContext;
+
+    pub fn account(id: u8) -> Address {
+        Address {
+            address_type: AddressType::Account,
+            identifier: [id; 20],
+        }
+    }
+
+    pub fn context(sender: Address, block_production_time: i64) -> ContractContext {
+        ContractContext {
+            contract_address: Address {
+                address_type: AddressType::PublicContract,
+                identifier: [0xff; 20],
+            },
+            sender,
+            block_time: 0,
+            block_production_time,
+            current_transaction: [0; 32],
+            original_transaction: [0; 32],
+        }
+    }
+
+    pub fn poll(
+        mp_addresses: Vec<Address>,
+        start_time: i64,
+        result_listener: Option<Address>,
+    ) -> VotingContractState {
+        initialize(
+            context(mp_addresses[0], 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            mp_addresses,
+            start_time,
+            result_listener,
+            None,
+            100,
+            None,
+            0,
+        )
+        .0
+    }
+}
+
+#[cfg(test)]
+mod vote_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::vote;
+    use pbc_contract_common::address::{Address, AddressType};
+
+    #[test]
+    pub fn test_vote_within_window_accepted() {
+        let mp = account(1);
+        let state = poll(vec![mp, account(2)], 1000, None);
+
+        let (state, _) = vote(context(mp, 1000), state, 1);
+
+        assert_eq!(state.votes[&mp], 1);
+    }
+
+    #[test]
+    pub fn test_abstention_counts_towards_closing() {
+        let (mp, other) = (account(1), account(2));
+        let state = poll(vec![mp, other], 0, None);
+
+        let (state, _) = vote(context(mp, 0), state, 2);
+        assert_eq!(state.closed, 0);
+        let (state, _) = vote(context(other, 0), state, 1);
+
+        assert_eq!(state.votes[&mp], 2);
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.count_votes(1), 1);
+        assert_eq!(state.count_votes(0), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only \"yes\", \"no\" and \"abstain\" votes are allowed")]
+    pub fn test_unknown_vote_rejected() {
+        let mp = account(1);
+        let state = poll(vec![mp], 0, None);
+
+        vote(context(mp, 0), state, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll does not accept votes before 1000")]
+    pub fn test_vote_before_start_time_rejected() {
+        let mp = account(1);
+        let state = poll(vec![mp], 1000, None);
+
+        vote(context(mp, 999), state, 1);
+    }
+
+    #[test]
+    pub fn test_closing_informs_result_listener() {
+        let mp = account(1);
+        let listener = Address {
+            address_type: AddressType::PublicContract,
+            identifier: [0xee; 20],
+        };
+        let state = poll(vec![mp], 0, Some(listener));
+
+        let (state, events) = vote(context(mp, 0), state, 1);
+
+        assert_eq!(state.closed, 1);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_open_poll_does_not_inform_result_listener() {
+        let mp = account(1);
+        let listener = Address {
+            address_type: AddressType::PublicContract,
+            identifier: [0xee; 20],
+        };
+        let state = poll(vec![mp, account(2)], 0, Some(listener));
+
+        let (state, events) = vote(context(mp, 0), state, 1);
+
+        assert_eq!(state.closed, 0);
+        assert!(events.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod proposal_tests {
+    use super::test_utils::{account, context};
+    use crate::initialize;
+
+    #[test]
+    pub fn test_proposal_metadata_round_trips() {
+        let mp = account(1);
+        let (state, _) = initialize(
+            context(mp, 0),
+            1,
+            "Budget 2024".to_string(),
+            "Approve the annual budget".to_string(),
+            vec![mp],
+            0,
+            None,
+            None,
+            100,
+            None,
+            0,
+        );
+
+        assert_eq!(state.title, "Budget 2024");
+        assert_eq!(state.description, "Approve the annual budget");
+    }
+
+    #[test]
+    #[should_panic(expected = "The proposal title cannot be empty")]
+    pub fn test_empty_title_rejected() {
+        let mp = account(1);
+        initialize(
+            context(mp, 0),
+            1,
+            "".to_string(),
+            "".to_string(),
+            vec![mp],
+            0,
+            None,
+            None,
+            100,
+            None,
+            0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The proposal title cannot exceed 100 bytes")]
+    pub fn test_long_title_rejected() {
+        let mp = account(1);
+        initialize(
+            context(mp, 0),
+            1,
+            "x".repeat(101),
+            "".to_string(),
+            vec![mp],
+            0,
+            None,
+            None,
+            100,
+            None,
+            0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod reopen_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{reopen, set_reopen_window, vote};
+    use pbc_contract_common::address::{Address, AddressType};
+
+    #[test]
+    pub fn test_reopen_within_window() {
+        let owner = account(1);
+        let state = poll(vec![owner], 0, None);
+        let (state, _) = set_reopen_window(context(owner, 0), state, 1000);
+        let (state, _) = vote(context(owner, 500), state, 1);
+        assert_eq!(state.closed, 1);
+
+        let (state, _) = reopen(context(owner, 1500), state);
+        assert_eq!(state.closed, 0);
+        assert!(state.votes.is_empty());
+
+        let (state, _) = vote(context(owner, 1600), state, 0);
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.votes[&owner], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll can no longer be reopened")]
+    pub fn test_reopen_outside_window_rejected() {
+        let owner = account(1);
+        let state = poll(vec![owner], 0, None);
+        let (state, _) = set_reopen_window(context(owner, 0), state, 1000);
+        let (state, _) = vote(context(owner, 500), state, 1);
+
+        reopen(context(owner, 1501), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Reopening is disabled for this poll")]
+    pub fn test_reopen_with_zero_window_rejected() {
+        let owner = account(1);
+        let state = poll(vec![owner], 0, None);
+        let (state, _) = vote(context(owner, 500), state, 1);
+
+        reopen(context(owner, 500), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only a poll closed by its members voting can be reopened")]
+    pub fn test_reopen_open_poll_rejected() {
+        let owner = account(1);
+        let state = poll(vec![owner, account(2)], 0, None);
+        let (state, _) = set_reopen_window(context(owner, 0), state, 1000);
+
+        reopen(context(owner, 0), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "A poll reporting to a result listener cannot be reopened")]
+    pub fn test_reopen_with_result_listener_rejected() {
+        let owner = account(1);
+        let listener = Address {
+            address_type: AddressType::PublicContract,
+            identifier: [0xee; 20],
+        };
+        let state = poll(vec![owner], 0, Some(listener));
+        let (state, _) = set_reopen_window(context(owner, 0), state, 1000);
+        let (state, _) = vote(context(owner, 500), state, 1);
+
+        reopen(context(owner, 1500), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can reopen the poll")]
+    pub fn test_reopen_by_non_owner_rejected() {
+        let owner = account(1);
+        let member = account(2);
+        let state = poll(vec![owner, member], 0, None);
+        let (state, _) = set_reopen_window(context(owner, 0), state, 1000);
+        let (state, _) = vote(context(owner, 0), state, 1);
+        let (state, _) = vote(context(member, 0), state, 1);
+
+        reopen(context(member, 0), state);
+    }
+}
+
+#[cfg(test)]
+mod signed_vote_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{
+        register_voting_key, set_vote_operator, signed_vote_message, submit_signed_votes, vote,
+        SignedVote, VotingContractState,
+    };
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use pbc_contract_common::address::Address;
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn sign_vote(
+        key: &SigningKey,
+        state: &VotingContractState,
+        voter: Address,
+        vote: u8,
+    ) -> SignedVote {
+        let nonce = state.signed_vote_nonces.get(&voter).copied().unwrap_or(0);
+        let message = signed_vote_message(
+            context(voter, 0).contract_address,
+            state.proposal_id,
+            state.closed_at,
+            vote,
+            nonce,
+        );
+        let signature: Signature = key.sign(&message);
+        SignedVote {
+            voter,
+            vote,
+            nonce,
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    fn relayed_poll(voters: &[(Address, &SigningKey)], operator: Address) -> VotingContractState {
+        let owner = voters[0].0;
+        let state = poll(voters.iter().map(|(voter, _)| *voter).collect(), 0, None);
+        let (mut state, _) = set_vote_operator(context(owner, 0), state, Some(operator));
+        for (voter, key) in voters {
+            let public_key = key
+                .verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec();
+            state = register_voting_key(context(*voter, 0), state, public_key).0;
+        }
+        state
+    }
+
+    #[test]
+    pub fn test_valid_signed_vote_accepted() {
+        let (mp, other, operator) = (account(1), account(2), account(9));
+        let (mp_key, other_key) = (signing_key(1), signing_key(2));
+        let state = relayed_poll(&[(mp, &mp_key), (other, &other_key)], operator);
+
+        let signed_vote = sign_vote(&mp_key, &state, mp, 1);
+        let (state, _) = submit_signed_votes(context(operator, 0), state, vec![signed_vote]);
+
+        assert_eq!(state.votes[&mp], 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature on the vote")]
+    pub fn test_forged_signed_vote_rejected() {
+        let (mp, other, operator) = (account(1), account(2), account(9));
+        let (mp_key, other_key) = (signing_key(1), signing_key(2));
+        let state = relayed_poll(&[(mp, &mp_key), (other, &other_key)], operator);
+
+        // The other member signs a vote in the name of `mp`
+        let forged_vote = sign_vote(&other_key, &state, mp, 0);
+        submit_signed_votes(context(operator, 0), state, vec![forged_vote]);
+    }
+
+    #[test]
+    pub fn test_successive_signed_votes_use_fresh_nonces() {
+        let (mp, other, operator) = (account(1), account(2), account(9));
+        let (mp_key, other_key) = (signing_key(1), signing_key(2));
+        let state = relayed_poll(&[(mp, &mp_key), (other, &other_key)], operator);
+
+        let first_vote = sign_vote(&mp_key, &state, mp, 1);
+        let (state, _) = submit_signed_votes(context(operator, 0), state, vec![first_vote]);
+        let second_vote = sign_vote(&mp_key, &state, mp, 0);
+        let (state, _) = submit_signed_votes(context(operator, 0), state, vec![second_vote]);
+
+        assert_eq!(state.votes[&mp], 0);
+        assert_eq!(state.signed_vote_nonces[&mp], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid nonce on the vote")]
+    pub fn test_replayed_signed_vote_rejected() {
+        let (mp, other, operator) = (account(1), account(2), account(9));
+        let (mp_key, other_key) = (signing_key(1), signing_key(2));
+        let state = relayed_poll(&[(mp, &mp_key), (other, &other_key)], operator);
+
+        let signed_vote = sign_vote(&mp_key, &state, mp, 1);
+        let (state, _) =
+            submit_signed_votes(context(operator, 0), state, vec![signed_vote.clone()]);
+        let (state, _) = vote(context(mp, 0), state, 0);
+
+        // The operator replays the earlier "yes" to overturn the member's direct vote
+        submit_signed_votes(context(operator, 0), state, vec![signed_vote]);
+    }
+
+    #[test]
+    pub fn test_batch_beyond_quorum_stops_once_closed() {
+        let (a, b, c, operator) = (account(1), account(2), account(3), account(9));
+        let (a_key, b_key, c_key) = (signing_key(1), signing_key(2), signing_key(3));
+        let mut state = relayed_poll(&[(a, &a_key), (b, &b_key), (c, &c_key)], operator);
+        state.quorum = 50;
+
+        // 50% of 3 members rounds up to 2 votes, so the third vote arrives after the poll closed
+        let votes = vec![
+            sign_vote(&a_key, &state, a, 1),
+            sign_vote(&b_key, &state, b, 0),
+            sign_vote(&c_key, &state, c, 1),
+        ];
+        let (state, _) = submit_signed_votes(context(operator, 0), state, votes);
+
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.votes.len(), 2);
+        assert!(!state.votes.contains_key(&c));
+        assert!(!state.signed_vote_nonces.contains_key(&c));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the vote operator can submit signed votes")]
+    pub fn test_signed_votes_from_non_operator_rejected() {
+        let (mp, operator) = (account(1), account(9));
+        let mp_key = signing_key(1);
+        let state = relayed_poll(&[(mp, &mp_key)], operator);
+
+        let signed_vote = sign_vote(&mp_key, &state, mp, 1);
+        submit_signed_votes(context(mp, 0), state, vec![signed_vote]);
+    }
+}
+
+#[cfg(test)]
+mod tally_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{query_tally, vote};
+
+    #[test]
+    pub fn test_tally_of_empty_poll() {
+        let state = poll(vec![account(1), account(2)], 0, None);
+
+        assert_eq!(state.tally(), (0, 0));
+    }
+
+    #[test]
+    pub fn test_tally_of_partially_voted_poll() {
+        let (yes_voter, abstainer) = (account(1), account(2));
+        let state = poll(vec![yes_voter, abstainer, account(3)], 0, None);
+        let (state, _) = vote(context(yes_voter, 0), state, 1);
+        let (state, _) = vote(context(abstainer, 0), state, 2);
+
+        let (state, events) = query_tally(context(account(9), 0), state);
+
+        assert_eq!(state.tally(), (1, 0));
+        assert_eq!(state.closed, 0);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_tally_of_closed_poll() {
+        let (first, second, third) = (account(1), account(2), account(3));
+        let state = poll(vec![first, second, third], 0, None);
+        let (state, _) = vote(context(first, 0), state, 1);
+        let (state, _) = vote(context(second, 0), state, 0);
+        let (state, _) = vote(context(third, 0), state, 1);
+
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.tally(), (2, 1));
+    }
+}
+
+#[cfg(test)]
+mod weight_tests {
+    use super::test_utils::{account, context};
+    use crate::{initialize, vote, VotingContractState};
+    use pbc_contract_common::address::Address;
+
+    fn weighted_poll(mp_addresses: Vec<Address>, weights: Option<Vec<u64>>) -> VotingContractState {
+        initialize(
+            context(mp_addresses[0], 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            mp_addresses,
+            0,
+            None,
+            weights,
+            100,
+            None,
+            0,
+        )
+        .0
+    }
+
+    #[test]
+    pub fn test_heavy_member_outweighs_others() {
+        let (heavy, first, second) = (account(1), account(2), account(3));
+        let state = weighted_poll(vec![heavy, first, second], Some(vec![5, 2, 2]));
+        let (state, _) = vote(context(heavy, 0), state, 1);
+        let (state, _) = vote(context(first, 0), state, 0);
+        let (state, _) = vote(context(second, 0), state, 0);
+
+        assert_eq!(state.tally(), (1, 2));
+        assert_eq!(state.weighted_tally(), (5, 4));
+        assert!(state.passed());
+    }
+
+    #[test]
+    pub fn test_weights_default_to_one() {
+        let (first, second) = (account(1), account(2));
+        let state = weighted_poll(vec![first, second], None);
+        let (state, _) = vote(context(first, 0), state, 1);
+        let (state, _) = vote(context(second, 0), state, 0);
+
+        assert_eq!(state.weighted_tally(), (1, 1));
+        assert!(!state.passed());
+    }
+
+    #[test]
+    #[should_panic(expected = "There must be exactly one weight per MP address")]
+    pub fn test_weights_length_mismatch_rejected() {
+        weighted_poll(vec![account(1), account(2)], Some(vec![1]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Weights must be positive")]
+    pub fn test_zero_weight_rejected() {
+        weighted_poll(vec![account(1), account(2)], Some(vec![1, 0]));
+    }
+}
+
+#[cfg(test)]
+mod quorum_tests {
+    use super::test_utils::{account, context};
+    use crate::{initialize, vote, VotingContractState};
+    use pbc_contract_common::address::Address;
+
+    fn quorum_poll(mp_addresses: Vec<Address>, quorum: u32) -> VotingContractState {
+        initialize(
+            context(mp_addresses[0], 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            mp_addresses,
+            0,
+            None,
+            None,
+            quorum,
+            None,
+            0,
+        )
+        .0
+    }
+
+    #[test]
+    pub fn test_poll_closes_once_quorum_votes() {
+        let members = vec![account(1), account(2), account(3)];
+        let state = quorum_poll(members.clone(), 50);
+
+        // 50% of 3 members rounds up to 2 votes
+        let (state, _) = vote(context(members[0], 0), state, 1);
+        assert_eq!(state.closed, 0);
+        let (state, _) = vote(context(members[1], 0), state, 0);
+        assert_eq!(state.closed, 1);
+    }
+
+    #[test]
+    pub fn test_full_quorum_waits_for_every_member() {
+        let members = vec![account(1), account(2)];
+        let state = quorum_poll(members.clone(), 100);
+
+        let (state, _) = vote(context(members[0], 0), state, 1);
+        assert_eq!(state.closed, 0);
+        let (state, _) = vote(context(members[1], 0), state, 2);
+        assert_eq!(state.closed, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "The quorum must be between 1 and 100 percent")]
+    pub fn test_zero_quorum_rejected() {
+        quorum_poll(vec![account(1)], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The quorum must be between 1 and 100 percent")]
+    pub fn test_quorum_above_100_rejected() {
+        quorum_poll(vec![account(1)], 101);
+    }
+}
+
+#[cfg(test)]
+mod weight_token_tests {
+    use super::test_utils::{account, context};
+    use crate::{initialize, vote, vote_callback, VotingContractState};
+    use pbc_contract_common::address::{Address, AddressType};
+    use pbc_contract_common::context::{CallbackContext, ExecutionResult};
+
+    fn token_weighted_poll(mp_addresses: Vec<Address>) -> VotingContractState {
+        let weight_token = Address {
+            address_type: AddressType::PublicContract,
+            identifier: [0xaa; 20],
+        };
+        initialize(
+            context(mp_addresses[0], 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            mp_addresses,
+            0,
+            None,
+            None,
+            100,
+            Some(weight_token),
+            0,
+        )
+        .0
+    }
+
+    fn balance_result(balance: u64) -> CallbackContext {
+        CallbackContext {
+            success: true,
+            results: vec![ExecutionResult {
+                succeeded: true,
+                return_data: balance.to_be_bytes().to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    pub fn test_vote_queries_balance_first() {
+        let mp = account(1);
+        let state = token_weighted_poll(vec![mp]);
+
+        let (state, events) = vote(context(mp, 0), state, 1);
+
+        assert!(state.votes.is_empty());
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    pub fn test_votes_weighted_by_balance() {
+        let (whale, first, second) = (account(1), account(2), account(3));
+        let state = token_weighted_poll(vec![whale, first, second]);
+
+        let (state, _) = vote_callback(context(whale, 0), balance_result(500), state, whale, 0);
+        let (state, _) = vote_callback(context(first, 0), balance_result(200), state, first, 1);
+        let (state, _) = vote_callback(context(second, 0), balance_result(250), state, second, 1);
+
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.tally(), (2, 1));
+        assert_eq!(state.weighted_tally(), (450, 500));
+        assert!(!state.passed());
+    }
+
+    #[test]
+    pub fn test_weight_frozen_at_first_vote() {
+        let (first, second) = (account(1), account(2));
+        let state = token_weighted_poll(vec![first, second]);
+
+        let (state, _) = vote_callback(context(first, 0), balance_result(200), state, first, 1);
+        let (state, events) = vote(context(first, 0), state, 0);
+        assert!(events.is_empty());
+        let (state, _) = vote_callback(context(first, 0), balance_result(900), state, first, 0);
+
+        assert_eq!(state.votes[&first], 0);
+        assert_eq!(state.weighted_tally(), (0, 200));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only holders of the weight token can vote")]
+    pub fn test_vote_without_balance_rejected() {
+        let mp = account(1);
+        let state = token_weighted_poll(vec![mp]);
+
+        vote_callback(context(mp, 0), balance_result(0), state, mp, 1);
+    }
+}
+
+#[cfg(test)]
+mod deadline_tests {
+    use super::test_utils::{account, context};
+    use crate::{close_poll, initialize, reopen, vote, VotingContractState};
+    use pbc_contract_common::address::Address;
+
+    fn deadline_poll(mp_addresses: Vec<Address>, deadline_millis: i64) -> VotingContractState {
+        initialize(
+            context(mp_addresses[0], 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            mp_addresses,
+            0,
+            None,
+            None,
+            100,
+            None,
+            deadline_millis,
+        )
+        .0
+    }
+
+    #[test]
+    pub fn test_vote_at_deadline_accepted() {
+        let mp = account(1);
+        let state = deadline_poll(vec![mp, account(2)], 1000);
+
+        let (state, _) = vote(context(mp, 1000), state, 1);
+
+        assert_eq!(state.votes.get(&mp), Some(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll does not accept votes after its deadline 1000")]
+    pub fn test_vote_after_deadline_rejected() {
+        let mp = account(1);
+        let state = deadline_poll(vec![mp, account(2)], 1000);
+
+        vote(context(mp, 1001), state, 1);
+    }
+
+    #[test]
+    pub fn test_anyone_can_close_after_deadline() {
+        let mp = account(1);
+        let state = deadline_poll(vec![mp, account(2)], 1000);
+        let (state, _) = vote(context(mp, 500), state, 1);
+
+        let (state, _) = close_poll(context(account(9), 1001), state);
+
+        assert_eq!(state.closed, 2);
+        assert_eq!(state.closed_at, 1001);
+        assert_eq!(state.tally(), (1, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll cannot be closed before its deadline")]
+    pub fn test_close_before_deadline_rejected() {
+        let state = deadline_poll(vec![account(1)], 1000);
+
+        close_poll(context(account(9), 1000), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll cannot be closed before its deadline")]
+    pub fn test_poll_without_deadline_cannot_be_closed() {
+        let state = deadline_poll(vec![account(1)], 0);
+
+        close_poll(context(account(9), i64::MAX), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll is closed")]
+    pub fn test_close_closed_poll_rejected() {
+        let mp = account(1);
+        let state = deadline_poll(vec![mp], 1000);
+        let (state, _) = vote(context(mp, 500), state, 1);
+
+        close_poll(context(account(9), 1001), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only a poll closed by its members voting can be reopened")]
+    pub fn test_deadline_closed_poll_cannot_be_reopened() {
+        let mp = account(1);
+        let state = deadline_poll(vec![mp, account(2)], 1000);
+        let (state, _) = close_poll(context(account(9), 1001), state);
+
+        reopen(context(mp, 1001), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "The deadline cannot be before the start time")]
+    pub fn test_deadline_before_start_rejected() {
+        let mp = account(1);
+        initialize(
+            context(mp, 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            vec![mp],
+            1000,
+            None,
+            None,
+            100,
+            None,
+            999,
+        );
+    }
+}
+
+#[cfg(test)]
+mod early_decision_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{set_decisive_weight, vote};
+
+    #[test]
+    pub fn test_decisive_weight_closes_poll_early() {
+        let (owner, first, second) = (account(1), account(2), account(3));
+        let state = poll(vec![owner, first, second, account(4), account(5)], 0, None);
+        let (state, _) = set_decisive_weight(context(owner, 0), state, 3);
+
+        let (state, _) = vote(context(first, 0), state, 0);
+        let (state, _) = vote(context(owner, 0), state, 1);
+        let (state, _) = vote(context(second, 0), state, 0);
+        assert_eq!(state.closed, 0);
+        assert_eq!(state.winner(), None);
+
+        let (state, _) = vote(context(account(4), 10), state, 0);
+
+        assert_eq!(state.closed, 3);
+        assert_eq!(state.closed_at, 10);
+        assert_eq!(state.winner(), Some(0));
+    }
+
+    #[test]
+    pub fn test_quorum_close_has_no_winner() {
+        let (owner, member) = (account(1), account(2));
+        let state = poll(vec![owner, member], 0, None);
+        let (state, _) = set_decisive_weight(context(owner, 0), state, 2);
+
+        let (state, _) = vote(context(owner, 0), state, 1);
+        let (state, _) = vote(context(member, 0), state, 0);
+
+        assert_eq!(state.closed, 1);
+        assert_eq!(state.winner(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The decisive weight cannot change once voting has started")]
+    pub fn test_decisive_weight_fixed_after_first_vote() {
+        let owner = account(1);
+        let state = poll(vec![owner, account(2)], 0, None);
+        let (state, _) = vote(context(owner, 0), state, 1);
+
+        set_decisive_weight(context(owner, 0), state, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can set the decisive weight")]
+    pub fn test_decisive_weight_only_by_owner() {
+        let state = poll(vec![account(1), account(2)], 0, None);
+
+        set_decisive_weight(context(account(2), 0), state, 1);
+    }
+}
+
+#[cfg(test)]
+mod delegation_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{delegate, vote};
+
+    #[test]
+    pub fn test_delegate_votes_for_delegators() {
+        let (delegator, delegate_mp, other) = (account(1), account(2), account(3));
+        let state = poll(vec![delegator, delegate_mp, other, account(4)], 0, None);
+        let (state, _) = delegate(context(delegator, 0), state, delegate_mp);
+
+        let (state, _) = vote(context(delegate_mp, 0), state, 1);
+
+        assert_eq!(state.votes.get(&delegator), Some(&1));
+        assert_eq!(state.votes.get(&delegate_mp), Some(&1));
+        assert_eq!(state.tally(), (2, 0));
+    }
+
+    #[test]
+    pub fn test_direct_vote_overrides_delegation() {
+        let (delegator, delegate_mp) = (account(1), account(2));
+        let state = poll(vec![delegator, delegate_mp, account(3)], 0, None);
+        let (state, _) = delegate(context(delegator, 0), state, delegate_mp);
+        let (state, _) = vote(context(delegate_mp, 0), state, 1);
+
+        let (state, _) = vote(context(delegator, 0), state, 0);
+
+        assert_eq!(state.tally(), (1, 1));
+        assert!(state.delegations.is_empty());
+    }
+
+    #[test]
+    pub fn test_direct_vote_before_delegate_is_kept() {
+        let (delegator, delegate_mp) = (account(1), account(2));
+        let state = poll(vec![delegator, delegate_mp, account(3)], 0, None);
+        let (state, _) = delegate(context(delegator, 0), state, delegate_mp);
+        let (state, _) = vote(context(delegator, 0), state, 0);
+
+        let (state, _) = vote(context(delegate_mp, 0), state, 1);
+
+        assert_eq!(state.votes.get(&delegator), Some(&0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot delegate to a member who has delegated")]
+    pub fn test_delegation_cycle_rejected() {
+        let (first, second) = (account(1), account(2));
+        let state = poll(vec![first, second], 0, None);
+        let (state, _) = delegate(context(first, 0), state, second);
+
+        delegate(context(second, 0), state, first);
+    }
+
+    #[test]
+    #[should_panic(expected = "Votes can only be delegated between members of the parliament")]
+    pub fn test_delegate_to_non_member_rejected() {
+        let mp = account(1);
+        let state = poll(vec![mp, account(2)], 0, None);
+
+        delegate(context(mp, 0), state, account(9));
+    }
+}
+
+#[cfg(test)]
+mod vote_receipt_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{query_vote_of, vote};
+
+    #[test]
+    pub fn test_vote_of_reports_recorded_vote() {
+        let (mp, other) = (account(1), account(2));
+        let state = poll(vec![mp, other, account(3)], 0, None);
+        let (state, _) = vote(context(mp, 0), state, 2);
+
+        assert_eq!(state.vote_of(mp), Some(2));
+        assert_eq!(state.vote_of(other), None);
+    }
+
+    #[test]
+    pub fn test_vote_of_non_member_is_none() {
+        let mp = account(1);
+        let state = poll(vec![mp, account(2)], 0, None);
+        let (state, _) = vote(context(mp, 0), state, 1);
+
+        let (state, events) = query_vote_of(context(account(9), 0), state, account(9));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(state.vote_of(account(9)), None);
+    }
+}
+
+#[cfg(test)]
+mod delegation_cap_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{delegate, set_max_delegators, VotingContractState};
+
+    fn capped_poll(max_delegators: u32) -> VotingContractState {
+        let owner = account(1);
+        let state = poll(vec![owner, account(2), account(3), account(4)], 0, None);
+        set_max_delegators(context(owner, 0), state, max_delegators).0
+    }
+
+    #[test]
+    pub fn test_delegations_within_cap_accepted() {
+        let delegate_mp = account(1);
+        let state = capped_poll(2);
+
+        let (state, _) = delegate(context(account(2), 0), state, delegate_mp);
+        let (state, _) = delegate(context(account(3), 0), state, delegate_mp);
+
+        assert_eq!(state.delegator_count(delegate_mp), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "A delegate can represent at most 2 members")]
+    pub fn test_delegation_beyond_cap_rejected() {
+        let delegate_mp = account(1);
+        let state = capped_poll(2);
+        let (state, _) = delegate(context(account(2), 0), state, delegate_mp);
+        let (state, _) = delegate(context(account(3), 0), state, delegate_mp);
+
+        delegate(context(account(4), 0), state, delegate_mp);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can set the delegation cap")]
+    pub fn test_only_owner_sets_cap() {
+        let state = poll(vec![account(1), account(2)], 0, None);
+
+        set_max_delegators(context(account(2), 0), state, 1);
+    }
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use super::test_utils::{account, context, poll};
+    use crate::{
+        archive, initialize, reopen, set_decisive_weight, set_reopen_window, set_retention_period,
+        vote, VotingContractState,
+    };
+
+    fn closed_poll() -> VotingContractState {
+        let (owner, yes, no) = (account(1), account(2), account(3));
+        let state = poll(vec![owner, yes, no], 0, None);
+        let (state, _) = set_retention_period(context(owner, 0), state, 1000);
+        let (state, _) = vote(context(owner, 0), state, 1);
+        let (state, _) = vote(context(yes, 0), state, 1);
+        let (state, _) = vote(context(no, 500), state, 0);
+        state
+    }
+
+    #[test]
+    pub fn test_archive_keeps_tally_and_clears_votes() {
+        let state = closed_poll();
+
+        let (state, _) = archive(context(account(1), 1500), state);
+
+        assert!(state.votes.is_empty());
+        assert_eq!(state.tally(), (2, 1));
+        assert_eq!(state.yes_count, 2);
+        assert_eq!(state.no_count, 1);
+    }
+
+    #[test]
+    pub fn test_archive_keeps_weighted_tally() {
+        let (owner, heavy, light) = (account(1), account(2), account(3));
+        let state = initialize(
+            context(owner, 0),
+            1,
+            "Proposal".to_string(),
+            "".to_string(),
+            vec![owner, heavy, light],
+            0,
+            None,
+            Some(vec![1, 5, 2]),
+            100,
+            None,
+            0,
+        )
+        .0;
+        let (state, _) = vote(context(owner, 0), state, 1);
+        let (state, _) = vote(context(heavy, 0), state, 0);
+        let (state, _) = vote(context(light, 0), state, 1);
+
+        let (state, _) = archive(context(owner, 0), state);
+
+        assert_eq!(state.tally(), (2, 1));
+        assert_eq!(state.weighted_tally(), (3, 5));
+        assert!(!state.passed());
+        assert_eq!(state.winner(), None);
+    }
+
+    #[test]
+    pub fn test_archive_keeps_early_decision_winner() {
+        let (owner, first, second) = (account(1), account(2), account(3));
+        let state = poll(vec![owner, first, second, account(4)], 0, None);
+        let (state, _) = set_decisive_weight(context(owner, 0), state, 2);
+        let (state, _) = vote(context(first, 0), state, 0);
+        let (state, _) = vote(context(second, 0), state, 0);
+        assert_eq!(state.winner(), Some(0));
+
+        let (state, _) = archive(context(owner, 0), state);
+
+        assert!(state.votes.is_empty());
+        assert_eq!(state.weighted_tally(), (0, 2));
+        assert_eq!(state.winner(), Some(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The poll cannot be archived before 1500")]
+    pub fn test_archive_before_retention_period_rejected() {
+        archive(context(account(1), 1499), closed_poll());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only a closed poll can be archived")]
+    pub fn test_archive_open_poll_rejected() {
+        let owner = account(1);
+        let state = poll(vec![owner, account(2)], 0, None);
+
+        archive(context(owner, 0), state);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can archive the poll")]
+    pub fn test_archive_by_non_owner_rejected() {
+        archive(context(account(2), 1500), closed_poll());
+    }
+
+    #[test]
+    #[should_panic(expected = "An archived poll cannot be reopened")]
+    pub fn test_archived_poll_cannot_be_reopened() {
+        let owner = account(1);
+        let (state, _) = set_reopen_window(context(owner, 0), closed_poll(), 10_000);
+        let (state, _) = archive(context(owner, 1500), state);
+
+        reopen(context(owner, 1500), state);
+    }
+}
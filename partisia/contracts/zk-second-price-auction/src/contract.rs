@@ -14,6 +14,21 @@
 //! 5. Once the ZK computation concludes, the winning bid will be published and the winner will be
 //! stored in the state, together with their bid.
 //!
+//! As soon as the result is opened, the winner is resolved to their registered address and stored
+//! as a [`WinnerRecord`], which is marked as settled once the result has been attested.
+//! The record can be read with `query_result`.
+//!
+//! The auction is paid in a `settlement_token`: once the winner is known, the clearing price is
+//! pulled from the winner to the owner (the seller) with `transfer_from`, which requires the winner
+//! to have approved the auction contract. If the payment fails, the settlement is marked as failed,
+//! the callback returns a [`SettlementFailure`] and the owner can retry it with `retry_settlement`.
+//! If no registered bidder won, there is nothing to settle.
+//!
+//! The seller chooses a `bid_count_policy` at initialization. Under [`BID_COUNT_PUBLIC`] the number
+//! of bids can be read with `query_bid_count` while bidding is ongoing. Under [`BID_COUNT_HIDDEN`]
+//! it is only revealed once the result has been attested. Note that each bid is still a public
+//! secret variable in the contract state, so anyone reading the state on chain can count them; the
+//! hidden policy only keeps the count out of `query_bid_count` and the published state fields.
 //!
 
 #![allow(unused_variables)]
@@ -23,8 +38,8 @@ extern crate pbc_contract_codegen;
 extern crate pbc_contract_common;
 
 use create_type_spec_derive::CreateTypeSpec;
-use pbc_contract_common::address::Address;
-use pbc_contract_common::context::ContractContext;
+use pbc_contract_common::address::{Address, Shortname};
+use pbc_contract_common::context::{CallbackContext, ContractContext};
 use pbc_contract_common::events::EventGroup;
 use pbc_contract_common::zk::{
     AttestationId, CalculationStatus, SecretVarId, ZkInputDef, ZkState, ZkStateChange,
@@ -56,6 +71,25 @@ const MIN_NUM_BIDDERS: u32 = 3;
 /// Type of tracking bid amount
 type BidAmount = i32;
 
+/// Value of `settlement_status` before the payment has been collected.
+const SETTLEMENT_PENDING: u8 = 0;
+
+/// Value of `settlement_status` once the clearing price has been paid to the owner.
+const SETTLEMENT_PAID: u8 = 1;
+
+/// Value of `settlement_status` if pulling the clearing price from the winner failed.
+const SETTLEMENT_FAILED: u8 = 2;
+
+/// Value of `settlement_status` if no registered bidder won, so there is no payment to collect.
+const SETTLEMENT_NOT_REQUIRED: u8 = 3;
+
+/// Value of `bid_count_policy` under which the number of bids is public during bidding.
+const BID_COUNT_PUBLIC: u8 = 0;
+
+/// Value of `bid_count_policy` under which the number of bids is hidden until settlement.
+/// The count can still be derived from the secret variables in the state, see the module docs.
+const BID_COUNT_HIDDEN: u8 = 1;
+
 /// This state of the contract.
 #[state]
 struct ContractState {
@@ -65,6 +99,93 @@ struct ContractState {
     registered_bidders: Vec<RegisteredBidder>,
     /// The auction result
     auction_result: Option<AuctionResult>,
+    /// The winner of the auction, once the result is opened. `None` if no registered bidder won.
+    winner_record: Option<WinnerRecord>,
+    /// The token the clearing price is paid in
+    settlement_token: Address,
+    /// Whether the clearing price has been paid, see [`SETTLEMENT_PAID`]
+    settlement_status: u8,
+    /// Whether the number of bids is public during bidding, see [`BID_COUNT_HIDDEN`]
+    bid_count_policy: u8,
+    /// The number of bids, once revealed at settlement
+    revealed_bid_count: Option<u32>,
+}
+
+impl ContractState {
+    /// Resolves the winner of `auction_result` to a [`WinnerRecord`]. Returns `None` if the
+    /// winner is not a registered bidder.
+    fn winner_record(&self, auction_result: &AuctionResult) -> Option<WinnerRecord> {
+        let winner = self
+            .registered_bidders
+            .iter()
+            .find(|x| x.bidder_id == auction_result.winner)?;
+        Some(WinnerRecord {
+            winner: winner.address,
+            clearing_price: u32::try_from(auction_result.second_highest_bid)
+                .expect("The clearing price cannot be negative"),
+            settled: false,
+        })
+    }
+
+    /// The `(from, to, amount)` of the payment settling the auction, if there is a winner.
+    fn settlement_transfer(&self) -> Option<(Address, Address, u64)> {
+        let winner_record = self.winner_record.as_ref()?;
+        Some((
+            winner_record.winner,
+            self.owner,
+            u64::from(winner_record.clearing_price),
+        ))
+    }
+
+    /// The number of bids that may currently be disclosed, given that `bids` have been placed.
+    /// Under [`BID_COUNT_HIDDEN`] this is `None` until the count is revealed at settlement.
+    fn visible_bid_count(&self, bids: u32) -> Option<u32> {
+        if self.bid_count_policy == BID_COUNT_PUBLIC {
+            Some(bids)
+        } else {
+            self.revealed_bid_count
+        }
+    }
+
+    /// Records the outcome of the settlement payment. A failed payment returns a
+    /// [`SettlementFailure`] from the callback.
+    fn record_settlement(&mut self, success: bool) -> Vec<EventGroup> {
+        if success {
+            self.settlement_status = SETTLEMENT_PAID;
+            return vec![];
+        }
+        self.settlement_status = SETTLEMENT_FAILED;
+        let Some((winner, _, clearing_price)) = self.settlement_transfer() else {
+            return vec![];
+        };
+        let mut event_group = EventGroup::builder();
+        event_group.return_data(SettlementFailure {
+            winner,
+            clearing_price,
+        });
+        vec![event_group.build()]
+    }
+
+    /// Starts the settlement, creating the events pulling the clearing price from the winner to
+    /// the owner. Without a winner the settlement is marked as not required instead.
+    fn start_settlement(&mut self) -> Vec<EventGroup> {
+        let Some((from, to, amount)) = self.settlement_transfer() else {
+            self.settlement_status = SETTLEMENT_NOT_REQUIRED;
+            return vec![];
+        };
+        self.settlement_status = SETTLEMENT_PENDING;
+        let mut event_group = EventGroup::builder();
+        event_group
+            .call(self.settlement_token, token_contract_transfer_from())
+            .argument(from)
+            .argument(to)
+            .argument(amount)
+            .done();
+        event_group
+            .with_callback(SHORTNAME_SETTLEMENT_CALLBACK)
+            .done();
+        vec![event_group.build()]
+    }
 }
 
 #[derive(Clone, ReadWriteState, CreateTypeSpec, ReadWriteRPC)]
@@ -75,6 +196,26 @@ struct AuctionResult {
     second_highest_bid: BidAmount,
 }
 
+/// The resolved outcome of the auction.
+#[derive(Clone, PartialEq, Debug, ReadWriteState, CreateTypeSpec, ReadWriteRPC)]
+struct WinnerRecord {
+    /// Address of the auction winner
+    winner: Address,
+    /// The price the winner pays, the second highest bid
+    clearing_price: u32,
+    /// Whether the result has been attested
+    settled: bool,
+}
+
+/// Returned by the settlement callback when the clearing price could not be pulled from the winner.
+#[derive(Clone, PartialEq, Debug, ReadWriteRPC)]
+struct SettlementFailure {
+    /// Address of the auction winner
+    winner: Address,
+    /// The amount that could not be pulled
+    clearing_price: u64,
+}
+
 /// Representation of a registered bidder with an address
 #[derive(Clone, ReadWriteState, CreateTypeSpec)]
 struct RegisteredBidder {
@@ -84,13 +225,29 @@ struct RegisteredBidder {
 
 /// Initializes contract
 ///
-/// Note that owner is set to whoever initializes the contact.
+/// Note that owner is set to whoever initializes the contact, and is paid the clearing price in
+/// `settlement_token`. The `bid_count_policy` is either [`BID_COUNT_PUBLIC`] or [`BID_COUNT_HIDDEN`].
 #[init]
-fn initialize(context: ContractContext, zk_state: ZkState<SecretVarMetadata>) -> ContractState {
+fn initialize(
+    context: ContractContext,
+    zk_state: ZkState<SecretVarMetadata>,
+    settlement_token: Address,
+    bid_count_policy: u8,
+) -> ContractState {
+    assert!(
+        bid_count_policy == BID_COUNT_PUBLIC || bid_count_policy == BID_COUNT_HIDDEN,
+        "Unknown bid count policy {}",
+        bid_count_policy
+    );
     ContractState {
         owner: context.sender,
         registered_bidders: Vec::new(),
         auction_result: None,
+        winner_record: None,
+        settlement_token,
+        settlement_status: SETTLEMENT_PENDING,
+        bid_count_policy,
+        revealed_bid_count: None,
     }
 }
 
@@ -254,11 +411,11 @@ fn auction_compute_complete(
 }
 
 /// Automatically called when the auction result is declassified. Updates state to contain result,
-/// and requests attestation from nodes.
+/// requests attestation from nodes and pulls the clearing price from the winner.
 #[zk_on_variables_opened]
 fn open_auction_variable(
     context: ContractContext,
-    state: ContractState,
+    mut state: ContractState,
     zk_state: ZkState<SecretVarMetadata>,
     opened_variables: Vec<SecretVarId>,
 ) -> (ContractState, Vec<EventGroup>, Vec<ZkStateChange>) {
@@ -277,12 +434,47 @@ fn open_auction_variable(
         winner: read_variable(&zk_state, opened_variables.get(0)),
         second_highest_bid: read_variable(&zk_state, opened_variables.get(1)),
     };
+    state.winner_record = state.winner_record(&auction_result);
+    let settlement_events = state.start_settlement();
 
     let attest_request = ZkStateChange::Attest {
         data_to_attest: serialize_as_big_endian(&auction_result),
     };
 
-    (state, vec![], vec![attest_request])
+    (state, settlement_events, vec![attest_request])
+}
+
+/// Handles the callback of the settlement payment. A failed payment, for instance because the
+/// winner has not approved the clearing price, is recorded so the owner can retry it, and a
+/// [`SettlementFailure`] is returned.
+#[callback(shortname = 0x10)]
+fn settlement_callback(
+    context: ContractContext,
+    callback_context: CallbackContext,
+    mut state: ContractState,
+    zk_state: ZkState<SecretVarMetadata>,
+) -> (ContractState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let events = state.record_settlement(callback_context.success);
+    (state, events, vec![])
+}
+
+/// Allows the owner to retry a failed settlement, once the winner has approved the clearing price.
+#[action(shortname = 0x03)]
+fn retry_settlement(
+    context: ContractContext,
+    mut state: ContractState,
+    zk_state: ZkState<SecretVarMetadata>,
+) -> (ContractState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    assert_eq!(
+        context.sender, state.owner,
+        "Only the owner can retry the settlement"
+    );
+    assert_eq!(
+        state.settlement_status, SETTLEMENT_FAILED,
+        "Only a failed settlement can be retried"
+    );
+    let events = state.start_settlement();
+    (state, events, vec![])
 }
 
 /// Automatically called when some data is attested
@@ -305,10 +497,59 @@ fn auction_results_attested(
     let auction_result = AuctionResult::rpc_read_from(&mut attestation.data.as_slice());
 
     state.auction_result = Some(auction_result);
+    if let Some(winner_record) = state.winner_record.as_mut() {
+        winner_record.settled = true;
+    }
+    state.revealed_bid_count = Some(bid_count(&state, &zk_state));
 
     (state, vec![], vec![ZkStateChange::ContractDone])
 }
 
+/// Reports the [`WinnerRecord`] of the auction as the return data of the call. The state is
+/// unchanged, and the record is `None` until the result is opened or if no registered bidder won.
+#[action(shortname = 0x02)]
+fn query_result(
+    context: ContractContext,
+    state: ContractState,
+    zk_state: ZkState<SecretVarMetadata>,
+) -> (ContractState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut event_group = EventGroup::builder();
+    event_group.return_data(state.winner_record.clone());
+    (state, vec![event_group.build()], vec![])
+}
+
+/// Reports the number of bids as the return data of the call, if the `bid_count_policy` allows it
+/// to be disclosed yet. The state is unchanged.
+#[action(shortname = 0x04)]
+fn query_bid_count(
+    context: ContractContext,
+    state: ContractState,
+    zk_state: ZkState<SecretVarMetadata>,
+) -> (ContractState, Vec<EventGroup>, Vec<ZkStateChange>) {
+    let mut event_group = EventGroup::builder();
+    event_group.return_data(state.visible_bid_count(bid_count(&state, &zk_state)));
+    (state, vec![event_group.build()], vec![])
+}
+
+/// The number of bids placed by registered bidders. The outputs of the computation are not counted.
+fn bid_count(state: &ContractState, zk_state: &ZkState<SecretVarMetadata>) -> u32 {
+    zk_state
+        .secret_variables
+        .iter()
+        .filter(|v| {
+            state
+                .registered_bidders
+                .iter()
+                .any(|x| x.address == v.owner)
+        })
+        .count() as u32
+}
+
+/// The `Shortname` of the `transfer_from` action of the settlement token contract.
+fn token_contract_transfer_from() -> Shortname {
+    Shortname::from_u32(0x03)
+}
+
 /// Writes some value as RPC data.
 fn serialize_as_big_endian<T: ReadWriteRPC>(it: &T) -> Vec<u8> {
     let mut output: Vec<u8> = vec![];
@@ -326,3 +567,141 @@ fn read_variable<T: ReadWriteState>(
     let buffer: Vec<u8> = variable.data.clone().unwrap();
     T::state_read_from(&mut buffer.as_slice())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AuctionResult, BidderId, ContractState, RegisteredBidder, WinnerRecord, BID_COUNT_HIDDEN,
+        BID_COUNT_PUBLIC, SETTLEMENT_FAILED, SETTLEMENT_NOT_REQUIRED, SETTLEMENT_PAID,
+        SETTLEMENT_PENDING,
+    };
+    use pbc_contract_common::address::{Address, AddressType};
+
+    fn address(id: u8) -> Address {
+        Address {
+            address_type: AddressType::Account,
+            identifier: [id; 20],
+        }
+    }
+
+    fn state_with_bidders(ids: &[i32]) -> ContractState {
+        ContractState {
+            owner: address(0),
+            registered_bidders: ids
+                .iter()
+                .map(|id| RegisteredBidder {
+                    bidder_id: BidderId { id: *id },
+                    address: address(*id as u8),
+                })
+                .collect(),
+            auction_result: None,
+            winner_record: None,
+            settlement_token: Address {
+                address_type: AddressType::PublicContract,
+                identifier: [0xee; 20],
+            },
+            settlement_status: 0,
+            bid_count_policy: BID_COUNT_PUBLIC,
+            revealed_bid_count: None,
+        }
+    }
+
+    #[test]
+    fn winner_record_resolves_address() {
+        let state = state_with_bidders(&[1, 2, 3]);
+        let auction_result = AuctionResult {
+            winner: BidderId { id: 2 },
+            second_highest_bid: 70,
+        };
+
+        assert_eq!(
+            state.winner_record(&auction_result),
+            Some(WinnerRecord {
+                winner: address(2),
+                clearing_price: 70,
+                settled: false,
+            })
+        );
+    }
+
+    #[test]
+    fn unregistered_winner_has_no_record() {
+        let state = state_with_bidders(&[1, 2, 3]);
+        let auction_result = AuctionResult {
+            winner: BidderId { id: -1 },
+            second_highest_bid: 0,
+        };
+
+        assert_eq!(state.winner_record(&auction_result), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The clearing price cannot be negative")]
+    fn negative_clearing_price_rejected() {
+        let state = state_with_bidders(&[1]);
+        let auction_result = AuctionResult {
+            winner: BidderId { id: 1 },
+            second_highest_bid: -5,
+        };
+
+        state.winner_record(&auction_result);
+    }
+
+    #[test]
+    fn settlement_pulls_clearing_price_from_winner() {
+        let mut state = state_with_bidders(&[1, 2, 3]);
+        state.winner_record = state.winner_record(&AuctionResult {
+            winner: BidderId { id: 3 },
+            second_highest_bid: 70,
+        });
+
+        assert_eq!(
+            state.settlement_transfer(),
+            Some((address(3), address(0), 70))
+        );
+        assert_eq!(state.start_settlement().len(), 1);
+        assert_eq!(state.settlement_status, SETTLEMENT_PENDING);
+    }
+
+    #[test]
+    fn insufficient_approval_marks_settlement_failed() {
+        let mut state = state_with_bidders(&[1, 2, 3]);
+        state.winner_record = state.winner_record(&AuctionResult {
+            winner: BidderId { id: 3 },
+            second_highest_bid: 70,
+        });
+
+        assert_eq!(state.record_settlement(false).len(), 1);
+        assert_eq!(state.settlement_status, SETTLEMENT_FAILED);
+
+        assert!(state.record_settlement(true).is_empty());
+        assert_eq!(state.settlement_status, SETTLEMENT_PAID);
+    }
+
+    #[test]
+    fn no_settlement_without_winner() {
+        let mut state = state_with_bidders(&[1, 2, 3]);
+
+        assert_eq!(state.settlement_transfer(), None);
+        assert!(state.start_settlement().is_empty());
+        assert_eq!(state.settlement_status, SETTLEMENT_NOT_REQUIRED);
+    }
+
+    #[test]
+    fn public_bid_count_visible_during_bidding() {
+        let state = state_with_bidders(&[1, 2, 3]);
+
+        assert_eq!(state.visible_bid_count(2), Some(2));
+    }
+
+    #[test]
+    fn hidden_bid_count_revealed_at_settlement() {
+        let mut state = state_with_bidders(&[1, 2, 3]);
+        state.bid_count_policy = BID_COUNT_HIDDEN;
+
+        assert_eq!(state.visible_bid_count(3), None);
+
+        state.revealed_bid_count = Some(3);
+        assert_eq!(state.visible_bid_count(3), Some(3));
+    }
+}
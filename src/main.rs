@@ -1,6 +1,5 @@
-mod matching_engine;
-use matching_engine::engine::MatchingEngine;
-use matching_engine::orderbook::{BidOrAsk, Order, Orderbook};
+use shoko::matching_engine::engine::{MatchingEngine, TradingPair};
+use shoko::matching_engine::orderbook::{BidOrAsk, Order, Orderbook};
 fn main() {
     let buy_order_from_mido = Order::new(BidOrAsk::Bid, 5.5);
     let buy_order_from_mehdi = Order::new(BidOrAsk::Bid, 4.4);
@@ -15,5 +14,7 @@ fn main() {
 
     println!("{:?}", orderbook);
 
-    let engine = MatchingEngine::new();
+    let mut engine = MatchingEngine::new();
+    let pair = TradingPair::new("BTC".to_string(), "USD".to_string());
+    engine.add_new_market(pair);
 }
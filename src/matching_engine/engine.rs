@@ -1,11 +1,11 @@
-use super::orderbook::Orderbook;
-use std::collections::HashMap;
+use super::orderbook::{BidOrAsk, Fill, Limit, Order, Orderbook, Price};
+use std::collections::{HashMap, HashSet};
 
 // BTCUSD
 // BTC => BASE
 // USD => QUOTE
 
-#[derive(Eq, Hash, PartialEq, Clone)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone)]
 pub struct TradingPair {
     base: String,
     quote: String,
@@ -15,21 +15,648 @@ impl TradingPair {
     pub fn new(base: String, quote: String) -> TradingPair {
         TradingPair { base, quote }
     }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+}
+
+impl std::fmt::Display for TradingPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}_{}", self.base, self.quote)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MatchingPolicy {
+    // best price first, then earliest order at that price
+    PriceTime,
+}
+
+// Everything configured for a single market
+#[derive(Debug, PartialEq, Clone)]
+pub struct MarketSpec {
+    pub tick_size: f64,
+    pub lot_size: f64,
+    pub maker_fee_bps: u32,
+    pub taker_fee_bps: u32,
+    pub matching_policy: MatchingPolicy,
+}
+
+impl Default for MarketSpec {
+    fn default() -> Self {
+        MarketSpec {
+            tick_size: 0.00001,
+            lot_size: 0.00001,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            matching_policy: MatchingPolicy::PriceTime,
+        }
+    }
+}
+
+// The number of price levels per side in a market snapshot until configured otherwise
+const DEFAULT_SNAPSHOT_DEPTH: usize = 10;
+
+// Everything a market-data gateway publishes for one tick of a market.
+// Levels are (price, resting base volume), best price first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MarketSnapshot {
+    pub best_bid: Option<(Price, f64)>,
+    pub best_ask: Option<(Price, f64)>,
+    pub bids: Vec<(Price, f64)>,
+    pub asks: Vec<(Price, f64)>,
+    pub last_trade: Option<(Price, f64)>,
+    pub mid_and_spread: Option<(f64, f64)>,
+}
+
+fn is_multiple_of(value: f64, step: f64) -> bool {
+    let steps = value / step;
+    (steps - steps.round()).abs() < 1e-6
 }
 
 pub struct MatchingEngine {
     orderbooks: HashMap<TradingPair, Orderbook>,
+    market_specs: HashMap<TradingPair, MarketSpec>,
+    // every quote market of a base asset, in the order the markets were opened
+    markets_by_base: HashMap<String, Vec<TradingPair>>,
+    // markets where placing and matching orders is suspended
+    halted_markets: HashSet<TradingPair>,
+    // price and base size of the most recent execution in each market
+    last_trades: HashMap<TradingPair, (Price, f64)>,
+    // number of price levels per side included in a market snapshot
+    snapshot_depth: usize,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatchingEngine {
     pub fn new() -> MatchingEngine {
         MatchingEngine {
             orderbooks: HashMap::new(),
+            market_specs: HashMap::new(),
+            markets_by_base: HashMap::new(),
+            halted_markets: HashSet::new(),
+            last_trades: HashMap::new(),
+            snapshot_depth: DEFAULT_SNAPSHOT_DEPTH,
         }
     }
 
     pub fn add_new_market(&mut self, pair: TradingPair) {
+        if !self.orderbooks.contains_key(&pair) {
+            self.markets_by_base
+                .entry(pair.base.clone())
+                .or_default()
+                .push(pair.clone());
+        }
+        self.market_specs
+            .insert(pair.clone(), MarketSpec::default());
         self.orderbooks.insert(pair, Orderbook::new());
         println!("opening new orderbook")
     }
+
+    pub fn configure_market(&mut self, pair: &TradingPair, spec: MarketSpec) -> Result<(), String> {
+        if spec.tick_size <= 0.0 || spec.lot_size <= 0.0 {
+            return Err("tick size and lot size must be positive".to_string());
+        }
+        match self.market_specs.get_mut(pair) {
+            Some(market_spec) => {
+                *market_spec = spec;
+                Ok(())
+            }
+            None => Err(format!(
+                "the orderbook for the given trading pair ({}) does not exist",
+                pair
+            )),
+        }
+    }
+
+    pub fn market_spec(&self, pair: &TradingPair) -> Option<MarketSpec> {
+        self.market_specs.get(pair).cloned()
+    }
+
+    pub fn markets_for_base(&self, base: &str) -> Vec<&TradingPair> {
+        match self.markets_by_base.get(base) {
+            Some(pairs) => pairs.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // All markets grouped by their base asset, e.g. BTC => [BTC_USD, BTC_EUR]
+    pub fn markets_by_base(&self) -> &HashMap<String, Vec<TradingPair>> {
+        &self.markets_by_base
+    }
+
+    pub fn halt_market(&mut self, pair: &TradingPair) -> Result<(), String> {
+        self.market_exists(pair)?;
+        self.halted_markets.insert(pair.clone());
+        Ok(())
+    }
+
+    pub fn resume_market(&mut self, pair: &TradingPair) -> Result<(), String> {
+        self.market_exists(pair)?;
+        self.halted_markets.remove(pair);
+        Ok(())
+    }
+
+    pub fn is_halted(&self, pair: &TradingPair) -> bool {
+        self.halted_markets.contains(pair)
+    }
+
+    fn market_exists(&self, pair: &TradingPair) -> Result<(), String> {
+        if self.orderbooks.contains_key(pair) {
+            Ok(())
+        } else {
+            Err(format!(
+                "the orderbook for the given trading pair ({}) does not exist",
+                pair
+            ))
+        }
+    }
+
+    fn market_open(&self, pair: &TradingPair) -> Result<(), String> {
+        self.market_exists(pair)?;
+        if self.is_halted(pair) {
+            return Err(format!("the market {} is halted", pair));
+        }
+        Ok(())
+    }
+
+    pub fn place_limit_order(
+        &mut self,
+        pair: &TradingPair,
+        price: f64,
+        order: Order,
+    ) -> Result<u64, String> {
+        self.market_open(pair)?;
+        // Resting orders are sized in the base asset, so lot sizes and minimum fills apply to it
+        let order = order.in_base_units(price);
+        let spec = &self.market_specs[pair];
+        if !is_multiple_of(price, spec.tick_size) {
+            return Err(format!(
+                "price {} is not a multiple of the tick size {}",
+                price, spec.tick_size
+            ));
+        }
+        if !is_multiple_of(order.size(), spec.lot_size) {
+            return Err(format!(
+                "size {} is not a multiple of the lot size {}",
+                order.size(),
+                spec.lot_size
+            ));
+        }
+        // An order smaller than its own minimum fill could never execute, so it is not rested
+        if let Some(min_fill) = order.min_fill().filter(|min_fill| order.size() < *min_fill) {
+            return Err(format!(
+                "size {} is below the minimum fill {}",
+                order.size(),
+                min_fill
+            ));
+        }
+        let orderbook = self.orderbooks.get_mut(pair).unwrap();
+        Ok(orderbook.add_order(price, order))
+    }
+
+    pub fn place_market_order(
+        &mut self,
+        pair: &TradingPair,
+        mut order: Order,
+    ) -> Result<Vec<Fill>, String> {
+        self.market_open(pair)?;
+        let orderbook = self.orderbooks.get_mut(pair).unwrap();
+        let mut fills = orderbook.fill_market_order(&mut order);
+        // The resting order is the maker and the market order the taker of every fill
+        let spec = &self.market_specs[pair];
+        for fill in fills.iter_mut() {
+            let notional = fill.price.to_f64() * fill.size;
+            fill.maker_fee = notional * spec.maker_fee_bps as f64 / 10_000.0;
+            fill.taker_fee = notional * spec.taker_fee_bps as f64 / 10_000.0;
+        }
+        if let Some(fill) = fills.last() {
+            self.last_trades
+                .insert(pair.clone(), (fill.price, fill.size));
+        }
+        Ok(fills)
+    }
+
+    // Cancelling stays possible while a market is halted, so traders can pull their quotes
+    pub fn cancel_order(&mut self, pair: &TradingPair, order_id: u64) -> Result<(), String> {
+        self.market_exists(pair)?;
+        let orderbook = self.orderbooks.get_mut(pair).unwrap();
+        if orderbook.cancel_order(order_id) {
+            Ok(())
+        } else {
+            Err(format!("order {} does not exist in {}", order_id, pair))
+        }
+    }
+
+    // The level and side of a resting order, or None if the market or the order does not exist
+    pub fn find_order(&self, pair: &TradingPair, order_id: u64) -> Option<(Price, BidOrAsk)> {
+        self.orderbooks.get(pair)?.find_order(order_id)
+    }
+
+    // The mid price and the spread of a market's book, or None while either side is empty
+    pub fn mid_and_spread(&self, pair: &TradingPair) -> Option<(f64, f64)> {
+        let orderbook = self.orderbooks.get(pair)?;
+        let best_bid = orderbook.best_bid()?.to_f64();
+        let best_ask = orderbook.best_ask()?.to_f64();
+        Some(((best_bid + best_ask) / 2.0, best_ask - best_bid))
+    }
+
+    pub fn set_snapshot_depth(&mut self, depth: usize) -> Result<(), String> {
+        if depth == 0 {
+            return Err("snapshot depth must be at least one level".to_string());
+        }
+        self.snapshot_depth = depth;
+        Ok(())
+    }
+
+    // The price and base size of the most recent execution in a market, if any
+    pub fn last_trade(&self, pair: &TradingPair) -> Option<(Price, f64)> {
+        self.last_trades.get(pair).copied()
+    }
+
+    // A complete tick of a market in one call: top of book, depth up to the snapshot depth,
+    // the last trade and the mid and spread, all taken from the same state of the book
+    pub fn market_snapshot(&self, pair: &TradingPair) -> Option<MarketSnapshot> {
+        let orderbook = self.orderbooks.get(pair)?;
+        let depth = |limits: Vec<&Limit>| -> Vec<(Price, f64)> {
+            limits
+                .into_iter()
+                .take(self.snapshot_depth)
+                .map(|limit| (limit.price(), limit.total_volume()))
+                .collect()
+        };
+        let bids = depth(orderbook.bid_limits());
+        let asks = depth(orderbook.ask_limits());
+        Some(MarketSnapshot {
+            best_bid: bids.first().copied(),
+            best_ask: asks.first().copied(),
+            bids,
+            asks,
+            last_trade: self.last_trade(pair),
+            mid_and_spread: self.mid_and_spread(pair),
+        })
+    }
+
+    // Runs the opening auction for a market, executing all crossing orders at the single
+    // price that maximizes matched volume. Returns that price and the matched base quantity.
+    // Nothing is executed while the market is halted.
+    pub fn opening_cross(&mut self, pair: &TradingPair) -> Option<(Price, f64)> {
+        if self.is_halted(pair) {
+            return None;
+        }
+        let cross = self.orderbooks.get_mut(pair)?.uncross()?;
+        self.last_trades.insert(pair.clone(), cross);
+        Some(cross)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::orderbook::SizeUnit;
+    use super::*;
+
+    fn btc_usd() -> TradingPair {
+        TradingPair::new("BTC".to_string(), "USD".to_string())
+    }
+
+    #[test]
+    fn placing_on_unknown_market_fails() {
+        let mut engine = MatchingEngine::new();
+        let result = engine.place_limit_order(&btc_usd(), 100.0, Order::new(BidOrAsk::Bid, 1.0));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn opening_cross_maximizes_matched_volume() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let pre_open = [
+            (BidOrAsk::Bid, 102.0, 1.0),
+            (BidOrAsk::Bid, 101.0, 4.0),
+            (BidOrAsk::Bid, 100.0, 2.0),
+            (BidOrAsk::Ask, 99.0, 3.0),
+            (BidOrAsk::Ask, 101.0, 1.0),
+            (BidOrAsk::Ask, 103.0, 6.0),
+        ];
+        for (side, price, size) in pre_open {
+            engine
+                .place_limit_order(&pair, price, Order::new(side, size))
+                .unwrap();
+        }
+
+        // Matched volume per candidate price: 99 => 3, 100 => 3, 101 => 4, 102 => 1, 103 => 0
+        let (price, matched) = engine.opening_cross(&pair).unwrap();
+
+        assert_eq!(price, Price::new(101.0));
+        assert_eq!(matched, 4.0);
+        assert_eq!(engine.opening_cross(&pair), None);
+    }
+
+    #[test]
+    fn opening_cross_on_unknown_market_is_none() {
+        let mut engine = MatchingEngine::new();
+
+        assert_eq!(engine.opening_cross(&btc_usd()), None);
+    }
+
+    #[test]
+    fn markets_are_grouped_by_base() {
+        let btc_eur = TradingPair::new("BTC".to_string(), "EUR".to_string());
+        let eth_usd = TradingPair::new("ETH".to_string(), "USD".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(btc_usd());
+        engine.add_new_market(eth_usd.clone());
+        engine.add_new_market(btc_eur.clone());
+        engine.add_new_market(btc_usd());
+
+        assert_eq!(engine.markets_for_base("BTC"), vec![&btc_usd(), &btc_eur]);
+        assert_eq!(engine.markets_for_base("ETH"), vec![&eth_usd]);
+        assert!(engine.markets_for_base("SOL").is_empty());
+        assert_eq!(engine.markets_by_base().len(), 2);
+    }
+
+    #[test]
+    fn market_spec_reflects_configuration() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        assert_eq!(engine.market_spec(&pair), Some(MarketSpec::default()));
+
+        let spec = MarketSpec {
+            tick_size: 0.5,
+            lot_size: 0.1,
+            maker_fee_bps: 2,
+            taker_fee_bps: 5,
+            matching_policy: MatchingPolicy::PriceTime,
+        };
+        engine.configure_market(&pair, spec.clone()).unwrap();
+
+        assert_eq!(engine.market_spec(&pair), Some(spec));
+        assert_eq!(
+            engine.market_spec(&TradingPair::new("ETH".to_string(), "USD".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn orders_must_respect_tick_and_lot() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let spec = MarketSpec {
+            tick_size: 0.5,
+            lot_size: 0.1,
+            ..MarketSpec::default()
+        };
+        engine.configure_market(&pair, spec).unwrap();
+
+        assert!(engine
+            .place_limit_order(&pair, 100.5, Order::new(BidOrAsk::Bid, 0.3))
+            .is_ok());
+        assert!(engine
+            .place_limit_order(&pair, 100.25, Order::new(BidOrAsk::Bid, 0.3))
+            .is_err());
+        assert!(engine
+            .place_limit_order(&pair, 100.5, Order::new(BidOrAsk::Bid, 0.35))
+            .is_err());
+    }
+
+    #[test]
+    fn halted_market_rejects_orders_but_allows_cancels() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let resting = engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .unwrap();
+
+        engine.halt_market(&pair).unwrap();
+        assert!(engine.is_halted(&pair));
+        assert!(engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .is_err());
+        assert!(engine
+            .place_market_order(&pair, Order::new(BidOrAsk::Bid, 1.0))
+            .is_err());
+        assert_eq!(engine.cancel_order(&pair, resting), Ok(()));
+
+        engine.resume_market(&pair).unwrap();
+        assert!(!engine.is_halted(&pair));
+        engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .unwrap();
+        let fills = engine
+            .place_market_order(&pair, Order::new(BidOrAsk::Bid, 1.0))
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+    }
+
+    #[test]
+    fn halted_market_skips_opening_cross() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Bid, 1.0))
+            .unwrap();
+        engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .unwrap();
+
+        engine.halt_market(&pair).unwrap();
+        assert_eq!(engine.opening_cross(&pair), None);
+
+        engine.resume_market(&pair).unwrap();
+        assert_eq!(engine.opening_cross(&pair), Some((Price::new(100.0), 1.0)));
+    }
+
+    #[test]
+    fn halting_unknown_market_fails() {
+        let mut engine = MatchingEngine::new();
+
+        assert!(engine.halt_market(&btc_usd()).is_err());
+    }
+
+    #[test]
+    fn mid_and_spread_of_two_sided_book() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        for (side, price) in [
+            (BidOrAsk::Bid, 99.0),
+            (BidOrAsk::Bid, 100.0),
+            (BidOrAsk::Ask, 101.0),
+            (BidOrAsk::Ask, 103.0),
+        ] {
+            engine
+                .place_limit_order(&pair, price, Order::new(side, 1.0))
+                .unwrap();
+        }
+
+        assert_eq!(engine.mid_and_spread(&pair), Some((100.5, 1.0)));
+    }
+
+    #[test]
+    fn mid_and_spread_of_one_sided_book_is_none() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Bid, 1.0))
+            .unwrap();
+
+        assert_eq!(engine.mid_and_spread(&pair), None);
+        assert_eq!(
+            engine.mid_and_spread(&TradingPair::new("ETH".to_string(), "USD".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn market_snapshot_is_consistent_with_the_book() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_snapshot_depth(2).unwrap();
+        for (side, price, size) in [
+            (BidOrAsk::Bid, 98.0, 3.0),
+            (BidOrAsk::Bid, 99.0, 2.0),
+            (BidOrAsk::Bid, 100.0, 1.0),
+            (BidOrAsk::Bid, 100.0, 0.5),
+            (BidOrAsk::Ask, 101.0, 2.0),
+            (BidOrAsk::Ask, 102.0, 4.0),
+            (BidOrAsk::Ask, 103.0, 5.0),
+        ] {
+            engine
+                .place_limit_order(&pair, price, Order::new(side, size))
+                .unwrap();
+        }
+        engine
+            .place_market_order(&pair, Order::new(BidOrAsk::Bid, 1.0))
+            .unwrap();
+
+        let snapshot = engine.market_snapshot(&pair).unwrap();
+
+        assert_eq!(
+            snapshot.bids,
+            vec![(Price::new(100.0), 1.5), (Price::new(99.0), 2.0)]
+        );
+        assert_eq!(
+            snapshot.asks,
+            vec![(Price::new(101.0), 1.0), (Price::new(102.0), 4.0)]
+        );
+        assert_eq!(snapshot.best_bid, Some(snapshot.bids[0]));
+        assert_eq!(snapshot.best_ask, Some(snapshot.asks[0]));
+        assert_eq!(snapshot.last_trade, Some((Price::new(101.0), 1.0)));
+        assert_eq!(snapshot.mid_and_spread, Some((100.5, 1.0)));
+        assert_eq!(snapshot.mid_and_spread, engine.mid_and_spread(&pair));
+    }
+
+    #[test]
+    fn market_snapshot_of_empty_and_unknown_markets() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let snapshot = engine.market_snapshot(&pair).unwrap();
+
+        assert!(snapshot.bids.is_empty() && snapshot.asks.is_empty());
+        assert_eq!(snapshot.best_bid, None);
+        assert_eq!(snapshot.last_trade, None);
+        assert_eq!(snapshot.mid_and_spread, None);
+        assert!(engine
+            .market_snapshot(&TradingPair::new("ETH".to_string(), "USD".to_string()))
+            .is_none());
+        assert!(engine.set_snapshot_depth(0).is_err());
+    }
+
+    #[test]
+    fn find_order_locates_resting_orders_by_id() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let id = engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .unwrap();
+
+        assert_eq!(
+            engine.find_order(&pair, id),
+            Some((Price::new(100.0), BidOrAsk::Ask))
+        );
+
+        engine.cancel_order(&pair, id).unwrap();
+        assert_eq!(engine.find_order(&pair, id), None);
+    }
+
+    #[test]
+    fn quote_sized_limit_order_is_checked_in_base_units() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let spec = MarketSpec {
+            lot_size: 0.5,
+            ..MarketSpec::default()
+        };
+        engine.configure_market(&pair, spec).unwrap();
+
+        let resting = Order::with_size_unit(BidOrAsk::Ask, 500.0, SizeUnit::Quote);
+        engine.place_limit_order(&pair, 125.0, resting).unwrap();
+        let off_lot = Order::with_size_unit(BidOrAsk::Ask, 500.0, SizeUnit::Quote);
+        assert!(engine.place_limit_order(&pair, 400.0, off_lot).is_err());
+
+        let fills = engine
+            .place_market_order(&pair, Order::new(BidOrAsk::Bid, 4.0))
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].size, 4.0);
+        assert_eq!(engine.market_snapshot(&pair).unwrap().best_ask, None);
+    }
+
+    #[test]
+    fn fills_are_charged_the_market_fees() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let spec = MarketSpec {
+            maker_fee_bps: 2,
+            taker_fee_bps: 5,
+            ..MarketSpec::default()
+        };
+        engine.configure_market(&pair, spec).unwrap();
+        engine
+            .place_limit_order(&pair, 100.0, Order::new(BidOrAsk::Ask, 1.0))
+            .unwrap();
+
+        let fills = engine
+            .place_market_order(&pair, Order::new(BidOrAsk::Bid, 1.0))
+            .unwrap();
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_fee, 0.02);
+        assert_eq!(fills[0].taker_fee, 0.05);
+    }
+
+    #[test]
+    fn limit_order_below_its_minimum_fill_is_rejected() {
+        let pair = btc_usd();
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let order = Order::new(BidOrAsk::Bid, 0.5).with_min_fill(1.0);
+
+        assert!(engine.place_limit_order(&pair, 100.0, order).is_err());
+    }
 }
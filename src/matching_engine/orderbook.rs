@@ -1,14 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BidOrAsk {
     Bid,
     Ask,
 }
+// The unit an order's size is expressed in.
+// Quote-sized market orders are converted to base size at the execution price of each fill,
+// and quote-sized limit orders at their limit price before they rest.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SizeUnit {
+    Base,
+    Quote,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Fill {
+    pub order_id: u64,
+    pub price: Price,
+    pub size: f64,
+    // fees in the quote asset, charged by the engine from the market's spec
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+}
+
 #[derive(Debug)]
 pub struct Orderbook {
     asks: HashMap<Price, Limit>,
     bids: HashMap<Price, Limit>,
+    next_order_id: u64,
+    // the level and side of every resting order, so orders can be found without scanning the book
+    order_index: HashMap<u64, (Price, BidOrAsk)>,
+}
+
+impl Default for Orderbook {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Orderbook {
@@ -16,11 +44,19 @@ impl Orderbook {
         Orderbook {
             asks: HashMap::new(),
             bids: HashMap::new(),
+            next_order_id: 1,
+            order_index: HashMap::new(),
         }
     }
 
-    pub fn add_order(&mut self, price: f64, order: Order) {
+    // Quote-sized orders are converted to base size at their limit price before resting
+    pub fn add_order(&mut self, price: f64, order: Order) -> u64 {
+        let mut order = order.in_base_units(price);
         let price = Price::new(price);
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        order.id = id;
+        self.order_index.insert(id, (price, order.bid_or_ask));
 
         match order.bid_or_ask {
             BidOrAsk::Bid => {
@@ -48,10 +84,338 @@ impl Orderbook {
                 }
             }
         }
+
+        id
+    }
+
+    // Removes a resting order from the book. Returns false if no order has the given id.
+    pub fn cancel_order(&mut self, order_id: u64) -> bool {
+        let Some((price, side)) = self.order_index.remove(&order_id) else {
+            return false;
+        };
+        let limits = match side {
+            BidOrAsk::Bid => &mut self.bids,
+            BidOrAsk::Ask => &mut self.asks,
+        };
+        let limit = limits.get_mut(&price).unwrap();
+        limit.orders.retain(|order| order.id != order_id);
+        if limit.orders.is_empty() {
+            limits.remove(&price);
+        }
+        true
+    }
+
+    // The level and side of a resting order, or None once it has been filled or cancelled
+    pub fn find_order(&self, order_id: u64) -> Option<(Price, BidOrAsk)> {
+        self.order_index.get(&order_id).copied()
+    }
+
+    // Drops the orders that a fill removed from `limit` from the index, including the ones
+    // removed without being filled because they can no longer meet their own minimum
+    fn unindex_removed(
+        order_index: &mut HashMap<u64, (Price, BidOrAsk)>,
+        limit: &Limit,
+        resting_ids: &[u64],
+    ) {
+        for id in resting_ids {
+            if !limit.orders.iter().any(|order| order.id == *id) {
+                order_index.remove(id);
+            }
+        }
+    }
+
+    // Matches a market order against the opposite side of the book, best price first.
+    // Returns the fills executed against resting orders, sized in the base asset.
+    pub fn fill_market_order(&mut self, market_order: &mut Order) -> Vec<Fill> {
+        let limits = match market_order.bid_or_ask {
+            BidOrAsk::Bid => &mut self.asks,
+            BidOrAsk::Ask => &mut self.bids,
+        };
+        let mut prices: Vec<Price> = limits.keys().copied().collect();
+        match market_order.bid_or_ask {
+            BidOrAsk::Bid => prices.sort(),
+            BidOrAsk::Ask => prices.sort_by(|a, b| b.cmp(a)),
+        }
+
+        let mut fills = Vec::new();
+        for price in prices {
+            if market_order.is_filled() {
+                break;
+            }
+            let limit = limits.get_mut(&price).unwrap();
+            let resting_ids = limit.order_ids();
+            let level_fills = limit.fill_order(market_order);
+            Orderbook::unindex_removed(&mut self.order_index, limit, &resting_ids);
+            fills.extend(level_fills);
+            if limit.orders.is_empty() {
+                limits.remove(&price);
+            }
+        }
+
+        fills
+    }
+
+    // Finds the single price that maximizes matched volume between resting bids and asks,
+    // preferring the smallest imbalance on ties and then the lowest price,
+    // and executes every crossing order at that price.
+    // The matched volume is what both sides can fill without breaking any order's minimum fill.
+    pub fn uncross(&mut self) -> Option<(Price, f64)> {
+        let mut candidates: Vec<Price> =
+            self.bids.keys().chain(self.asks.keys()).copied().collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let mut best: Option<(Price, f64, f64)> = None;
+        for price in candidates {
+            let demand = Orderbook::volume_where(&self.bids, |p| p >= price);
+            let supply = Orderbook::volume_where(&self.asks, |p| p <= price);
+            let matched = self.matchable(price, demand.min(supply));
+            let imbalance = (demand - supply).abs();
+            if matched <= 0.0 {
+                continue;
+            }
+            let is_better = match best {
+                None => true,
+                Some((_, best_matched, best_imbalance)) => {
+                    matched > best_matched
+                        || (matched == best_matched && imbalance < best_imbalance)
+                }
+            };
+            if is_better {
+                best = Some((price, matched, imbalance));
+            }
+        }
+
+        let (price, matched, _) = best?;
+        Orderbook::remove_volume(
+            &mut self.bids,
+            &mut self.order_index,
+            BidOrAsk::Bid,
+            |p| p >= price,
+            matched,
+        );
+        Orderbook::remove_volume(
+            &mut self.asks,
+            &mut self.order_index,
+            BidOrAsk::Ask,
+            |p| p <= price,
+            matched,
+        );
+        Some((price, matched))
+    }
+
+    // The largest volume up to `size` that the bids and asks crossing at `price` can both fill.
+    // Skipping an order below its minimum can leave one side short, so the volume is narrowed
+    // until both sides fill it exactly.
+    fn matchable(&self, price: Price, size: f64) -> f64 {
+        let mut size = size;
+        loop {
+            let bid_fill = Orderbook::fillable(&self.bids, BidOrAsk::Bid, |p| p >= price, size);
+            let ask_fill = Orderbook::fillable(&self.asks, BidOrAsk::Ask, |p| p <= price, bid_fill);
+            if ask_fill == size {
+                return size;
+            }
+            size = ask_fill;
+        }
+    }
+
+    // How much of `size` a sweep of the included levels would fill, following `Limit::fill_order`
+    fn fillable(
+        limits: &HashMap<Price, Limit>,
+        side: BidOrAsk,
+        include: impl Fn(Price) -> bool,
+        size: f64,
+    ) -> f64 {
+        let mut remaining = size;
+        let levels = Orderbook::levels(limits, side)
+            .into_iter()
+            .filter(|(price, _)| include(*price));
+        for order in levels.flat_map(|(_, limit)| limit.orders.iter()) {
+            if remaining <= 0.0 {
+                break;
+            }
+            if order.is_below_min_fill(remaining.min(order.size)) {
+                continue;
+            }
+            if remaining >= order.size {
+                remaining -= order.size;
+            } else {
+                remaining = 0.0;
+            }
+        }
+        size - remaining
+    }
+
+    // Summed in price order, as float addition depends on the order of its terms
+    fn volume_where(limits: &HashMap<Price, Limit>, include: impl Fn(Price) -> bool) -> f64 {
+        Orderbook::levels(limits, BidOrAsk::Ask)
+            .into_iter()
+            .filter(|(price, _)| include(*price))
+            .map(|(_, limit)| limit.total_volume())
+            .sum()
+    }
+
+    // The levels of one side in canonical order, from the most aggressive price outwards.
+    // Limits live in a HashMap, so every enumeration of levels must go through here
+    // to be reproducible across runs.
+    fn levels(limits: &HashMap<Price, Limit>, side: BidOrAsk) -> Vec<(Price, &Limit)> {
+        let mut levels: Vec<(Price, &Limit)> = limits
+            .iter()
+            .map(|(price, limit)| (*price, limit))
+            .collect();
+        match side {
+            BidOrAsk::Bid => levels.sort_by_key(|(price, _)| std::cmp::Reverse(*price)),
+            BidOrAsk::Ask => levels.sort_by_key(|(price, _)| *price),
+        }
+        levels
+    }
+
+    // Takes `size` off the included levels of one side of the book, starting from the most aggressive price
+    fn remove_volume(
+        limits: &mut HashMap<Price, Limit>,
+        order_index: &mut HashMap<u64, (Price, BidOrAsk)>,
+        side: BidOrAsk,
+        include: impl Fn(Price) -> bool,
+        size: f64,
+    ) {
+        let mut prices: Vec<Price> = limits.keys().copied().filter(|p| include(*p)).collect();
+        match side {
+            BidOrAsk::Bid => prices.sort_by(|a, b| b.cmp(a)),
+            BidOrAsk::Ask => prices.sort(),
+        }
+
+        let mut sweep = Order::new(side, size);
+        for price in prices {
+            if sweep.is_filled() {
+                break;
+            }
+            let limit = limits.get_mut(&price).unwrap();
+            let resting_ids = limit.order_ids();
+            limit.fill_order(&mut sweep);
+            Orderbook::unindex_removed(order_index, limit, &resting_ids);
+            if limit.orders.is_empty() {
+                limits.remove(&price);
+            }
+        }
+    }
+
+    // The highest resting bid, if any
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().max().copied()
+    }
+
+    // The lowest resting ask, if any
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().min().copied()
+    }
+
+    pub fn ask_limits(&self) -> Vec<&Limit> {
+        Orderbook::levels(&self.asks, BidOrAsk::Ask)
+            .into_iter()
+            .map(|(_, limit)| limit)
+            .collect()
+    }
+
+    pub fn bid_limits(&self) -> Vec<&Limit> {
+        Orderbook::levels(&self.bids, BidOrAsk::Bid)
+            .into_iter()
+            .map(|(_, limit)| limit)
+            .collect()
+    }
+
+    // A canonical text rendering of the book: bids then asks, best price first,
+    // with the resting orders of each level in time priority.
+    // Identical books always render identically, so snapshots can be compared across runs.
+    pub fn snapshot(&self) -> String {
+        let mut snapshot = String::new();
+        for (side, limits) in [(BidOrAsk::Bid, &self.bids), (BidOrAsk::Ask, &self.asks)] {
+            for (price, limit) in Orderbook::levels(limits, side) {
+                snapshot.push_str(&format!("{:?} {}:", side, price.to_f64()));
+                for order in limit.orders.iter() {
+                    snapshot.push_str(&format!(" {}@{}", order.id, order.size));
+                }
+                snapshot.push('\n');
+            }
+        }
+        snapshot
+    }
+
+    // Checks the book for broken invariants and returns every violation found
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        let mut seen_ids = HashSet::new();
+
+        for (side, limits) in [(BidOrAsk::Bid, &self.bids), (BidOrAsk::Ask, &self.asks)] {
+            for (price, limit) in Orderbook::levels(limits, side) {
+                if limit.price != price {
+                    violations.push(format!(
+                        "{:?} limit stored at {} has price {}",
+                        side,
+                        price.to_f64(),
+                        limit.price.to_f64()
+                    ));
+                }
+                if limit.orders.is_empty() {
+                    violations.push(format!("empty {:?} limit at {}", side, price.to_f64()));
+                }
+                for order in limit.orders.iter() {
+                    if order.size <= 0.0 {
+                        violations.push(format!(
+                            "order {} at {} has non-positive size {}",
+                            order.id,
+                            price.to_f64(),
+                            order.size
+                        ));
+                    }
+                    if order.bid_or_ask != side {
+                        violations.push(format!(
+                            "order {} resting on the {:?} side is a {:?}",
+                            order.id, side, order.bid_or_ask
+                        ));
+                    }
+                    if !seen_ids.insert(order.id) {
+                        violations.push(format!("duplicate order id {}", order.id));
+                    }
+                    if self.order_index.get(&order.id) != Some(&(price, side)) {
+                        violations.push(format!(
+                            "order {} at {} is not indexed at its level",
+                            order.id,
+                            price.to_f64()
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut stale_ids: Vec<&u64> = self
+            .order_index
+            .keys()
+            .filter(|order_id| !seen_ids.contains(*order_id))
+            .collect();
+        stale_ids.sort();
+        for order_id in stale_ids {
+            violations.push(format!("index entry for missing order {}", order_id));
+        }
+
+        if let (Some(best_bid), Some(best_ask)) = (self.bids.keys().max(), self.asks.keys().min()) {
+            if best_bid >= best_ask {
+                violations.push(format!(
+                    "crossed book: best bid {} is not below best ask {}",
+                    best_bid.to_f64(),
+                    best_ask.to_f64()
+                ));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
     }
 }
 
-#[derive(Debug, Eq, Hash, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Copy, Clone)]
 pub struct Price {
     integral: u64,
     fractional: u64,
@@ -59,7 +423,7 @@ pub struct Price {
 }
 
 impl Price {
-    fn new(price: f64) -> Price {
+    pub fn new(price: f64) -> Price {
         let scalar = 100000;
         let integral = price as u64;
         let fractional = ((price % 1.0) * scalar as f64) as u64;
@@ -69,6 +433,10 @@ impl Price {
             fractional,
         }
     }
+
+    pub fn to_f64(&self) -> f64 {
+        self.integral as f64 + self.fractional as f64 / self.scalar as f64
+    }
 }
 
 #[derive(Debug)]
@@ -80,7 +448,7 @@ pub struct Limit {
 impl Limit {
     fn new(price: Price) -> Limit {
         Limit {
-            price: price,
+            price,
             orders: Vec::new(),
         }
     }
@@ -88,16 +456,453 @@ impl Limit {
     fn add_order(&mut self, order: Order) {
         self.orders.push(order);
     }
+
+    fn order_ids(&self) -> Vec<u64> {
+        self.orders.iter().map(|order| order.id).collect()
+    }
+
+    pub fn price(&self) -> Price {
+        self.price
+    }
+
+    pub fn total_volume(&self) -> f64 {
+        self.orders.iter().map(|order| order.size).sum()
+    }
+
+    // Fills the market order against resting orders in time priority,
+    // skipping any fill below the minimum of either order and removing the resting orders
+    // that are completely filled or whose remainder can no longer meet their own minimum.
+    fn fill_order(&mut self, market_order: &mut Order) -> Vec<Fill> {
+        let price = self.price.to_f64();
+        let mut fills = Vec::new();
+
+        for limit_order in self.orders.iter_mut() {
+            if market_order.is_filled() {
+                break;
+            }
+
+            let wanted = match market_order.size_unit {
+                SizeUnit::Base => market_order.size,
+                SizeUnit::Quote => market_order.size / price,
+            };
+            let available = wanted.min(limit_order.size);
+            if market_order.is_below_min_fill(available) || limit_order.is_below_min_fill(available)
+            {
+                continue;
+            }
+
+            let filled = if wanted >= limit_order.size {
+                let filled = limit_order.size;
+                limit_order.size = 0.0;
+                match market_order.size_unit {
+                    SizeUnit::Base => market_order.size -= filled,
+                    SizeUnit::Quote => market_order.size -= filled * price,
+                }
+                filled
+            } else {
+                limit_order.size -= wanted;
+                market_order.size = 0.0;
+                wanted
+            };
+
+            fills.push(Fill {
+                order_id: limit_order.id,
+                price: self.price,
+                size: filled,
+                maker_fee: 0.0,
+                taker_fee: 0.0,
+            });
+        }
+
+        self.orders
+            .retain(|order| !order.is_filled() && !order.is_below_min_fill(order.size));
+        fills
+    }
 }
 
 #[derive(Debug)]
 pub struct Order {
+    id: u64,
     size: f64,
+    size_unit: SizeUnit,
     bid_or_ask: BidOrAsk,
+    // The smallest base size a single fill of this order may have
+    min_fill: Option<f64>,
 }
 
 impl Order {
     pub fn new(bid_or_ask: BidOrAsk, size: f64) -> Order {
-        Order { bid_or_ask, size }
+        Order::with_size_unit(bid_or_ask, size, SizeUnit::Base)
+    }
+
+    pub fn with_size_unit(bid_or_ask: BidOrAsk, size: f64, size_unit: SizeUnit) -> Order {
+        Order {
+            id: 0,
+            bid_or_ask,
+            size,
+            size_unit,
+            min_fill: None,
+        }
+    }
+
+    // The same order sized in the base asset, converting a quote size at `price`
+    pub fn in_base_units(mut self, price: f64) -> Order {
+        if self.size_unit == SizeUnit::Quote {
+            self.size /= price;
+            self.size_unit = SizeUnit::Base;
+        }
+        self
+    }
+
+    pub fn with_min_fill(mut self, min_fill: f64) -> Order {
+        self.min_fill = Some(min_fill);
+        self
+    }
+
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.size <= 0.0
+    }
+
+    pub fn min_fill(&self) -> Option<f64> {
+        self.min_fill
+    }
+
+    fn is_below_min_fill(&self, size: f64) -> bool {
+        self.min_fill.is_some_and(|min_fill| size < min_fill)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_book_passes() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 1.0));
+        orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 2.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 1.5));
+
+        assert_eq!(orderbook.validate(), Ok(()));
+    }
+
+    #[test]
+    fn corrupted_book_reports_violations() {
+        let mut orderbook = Orderbook::new();
+        let bid_id = orderbook.add_order(102.0, Order::new(BidOrAsk::Bid, 1.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 1.0));
+
+        let empty_price = Price::new(90.0);
+        orderbook.bids.insert(empty_price, Limit::new(empty_price));
+
+        let bid_limit = orderbook.bids.get_mut(&Price::new(102.0)).unwrap();
+        bid_limit.orders[0].size = -1.0;
+        let mut duplicate = Order::new(BidOrAsk::Bid, 1.0);
+        duplicate.id = bid_id;
+        bid_limit.orders.push(duplicate);
+
+        let violations = orderbook.validate().unwrap_err();
+
+        assert_eq!(violations.len(), 4);
+        assert!(violations.contains(&"empty Bid limit at 90".to_string()));
+        assert!(violations.contains(&format!("order {} at 102 has non-positive size -1", bid_id)));
+        assert!(violations.contains(&format!("duplicate order id {}", bid_id)));
+        assert!(violations
+            .contains(&"crossed book: best bid 102 is not below best ask 101".to_string()));
+    }
+
+    #[test]
+    fn base_sized_market_order_walks_the_book() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 2.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 2.0));
+
+        let mut market_order = Order::new(BidOrAsk::Bid, 3.0);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        assert!(market_order.is_filled());
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].price, Price::new(101.0));
+        assert_eq!(fills[1].size, 1.0);
+        assert_eq!(orderbook.ask_limits().len(), 1);
+        assert_eq!(orderbook.ask_limits()[0].total_volume(), 1.0);
+    }
+
+    #[test]
+    fn quote_sized_market_buy_fills_base_across_levels() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 2.0));
+        orderbook.add_order(125.0, Order::new(BidOrAsk::Ask, 4.0));
+
+        let mut market_order = Order::with_size_unit(BidOrAsk::Bid, 500.0, SizeUnit::Quote);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        let base_filled: f64 = fills.iter().map(|fill| fill.size).sum();
+        assert!(market_order.is_filled());
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].size, 2.0);
+        assert!((fills[1].size - 2.4).abs() < 1e-9);
+        assert!((base_filled - 4.4).abs() < 1e-9);
+        assert!((orderbook.ask_limits()[0].total_volume() - 1.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quote_sized_market_buy_exhausting_the_book_keeps_remainder() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 1.0));
+
+        let mut market_order = Order::with_size_unit(BidOrAsk::Bid, 250.0, SizeUnit::Quote);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].size, 1.0);
+        assert_eq!(market_order.size(), 150.0);
+        assert!(orderbook.ask_limits().is_empty());
+    }
+
+    #[test]
+    fn quote_sized_limit_order_rests_in_base_units() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(
+            125.0,
+            Order::with_size_unit(BidOrAsk::Ask, 500.0, SizeUnit::Quote),
+        );
+
+        assert_eq!(orderbook.ask_limits()[0].total_volume(), 4.0);
+
+        let mut market_order = Order::new(BidOrAsk::Bid, 4.0);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        assert!(market_order.is_filled());
+        assert_eq!(fills[0].size, 4.0);
+        assert!(orderbook.ask_limits().is_empty());
+    }
+
+    #[test]
+    fn uncross_picks_the_volume_maximizing_price() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(102.0, Order::new(BidOrAsk::Bid, 3.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Bid, 2.0));
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 5.0));
+        orderbook.add_order(99.0, Order::new(BidOrAsk::Ask, 2.0));
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 3.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 4.0));
+
+        // 100 and 101 both match 5, but 101 leaves the smaller imbalance
+        assert_eq!(orderbook.uncross(), Some((Price::new(101.0), 5.0)));
+
+        assert_eq!(orderbook.validate(), Ok(()));
+        assert_eq!(orderbook.bid_limits().len(), 1);
+        assert_eq!(orderbook.bid_limits()[0].price(), Price::new(100.0));
+        assert_eq!(orderbook.bid_limits()[0].total_volume(), 5.0);
+        assert_eq!(orderbook.ask_limits().len(), 1);
+        assert_eq!(orderbook.ask_limits()[0].price(), Price::new(101.0));
+        assert_eq!(orderbook.ask_limits()[0].total_volume(), 4.0);
+    }
+
+    #[test]
+    fn uncross_matches_only_what_minimum_fills_allow() {
+        let mut orderbook = Orderbook::new();
+        let large_bid =
+            orderbook.add_order(101.0, Order::new(BidOrAsk::Bid, 3.0).with_min_fill(3.0));
+        let small_bid = orderbook.add_order(101.0, Order::new(BidOrAsk::Bid, 1.0));
+        let ask = orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 2.0));
+
+        // The large bid cannot take part in a fill of 2, leaving only the small bid to match
+        assert_eq!(orderbook.uncross(), Some((Price::new(100.0), 1.0)));
+
+        assert_eq!(
+            orderbook.find_order(large_bid),
+            Some((Price::new(101.0), BidOrAsk::Bid))
+        );
+        assert_eq!(orderbook.find_order(small_bid), None);
+        assert_eq!(
+            orderbook.find_order(ask),
+            Some((Price::new(100.0), BidOrAsk::Ask))
+        );
+        assert_eq!(orderbook.bid_limits()[0].total_volume(), 3.0);
+        assert_eq!(orderbook.ask_limits()[0].total_volume(), 1.0);
+    }
+
+    #[test]
+    fn uncross_without_crossing_orders_does_nothing() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 1.0));
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 1.0));
+
+        assert_eq!(orderbook.uncross(), None);
+        assert_eq!(orderbook.bid_limits().len(), 1);
+        assert_eq!(orderbook.ask_limits().len(), 1);
+    }
+
+    #[test]
+    fn cancel_order_removes_order_and_empty_limit() {
+        let mut orderbook = Orderbook::new();
+        let first = orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 1.0));
+        let second = orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 2.0));
+
+        assert!(orderbook.cancel_order(first));
+        assert_eq!(orderbook.bid_limits()[0].total_volume(), 2.0);
+        assert!(!orderbook.cancel_order(first));
+
+        assert!(orderbook.cancel_order(second));
+        assert!(orderbook.bid_limits().is_empty());
+    }
+
+    #[test]
+    fn find_order_tracks_resting_orders() {
+        let mut orderbook = Orderbook::new();
+        let bid = orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 1.0));
+        let cancelled = orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 1.0));
+        let filled = orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 1.0));
+        let partially_filled = orderbook.add_order(102.0, Order::new(BidOrAsk::Ask, 2.0));
+
+        assert_eq!(
+            orderbook.find_order(bid),
+            Some((Price::new(99.0), BidOrAsk::Bid))
+        );
+        assert_eq!(
+            orderbook.find_order(filled),
+            Some((Price::new(101.0), BidOrAsk::Ask))
+        );
+
+        assert!(orderbook.cancel_order(cancelled));
+        let mut market_order = Order::new(BidOrAsk::Bid, 2.0);
+        orderbook.fill_market_order(&mut market_order);
+
+        assert_eq!(orderbook.find_order(cancelled), None);
+        assert_eq!(orderbook.find_order(filled), None);
+        assert_eq!(
+            orderbook.find_order(partially_filled),
+            Some((Price::new(102.0), BidOrAsk::Ask))
+        );
+        assert_eq!(orderbook.find_order(999), None);
+        assert_eq!(orderbook.validate(), Ok(()));
+    }
+
+    #[test]
+    fn order_dropped_below_its_minimum_fill_is_unindexed() {
+        let mut orderbook = Orderbook::new();
+        let below_min =
+            orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 0.5).with_min_fill(1.0));
+
+        let mut market_order = Order::new(BidOrAsk::Bid, 0.3);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        assert!(fills.is_empty());
+        assert_eq!(orderbook.find_order(below_min), None);
+        assert_eq!(orderbook.validate(), Ok(()));
+    }
+
+    #[test]
+    fn uncross_removes_executed_orders_from_the_index() {
+        let mut orderbook = Orderbook::new();
+        let bid = orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 1.0));
+        let ask = orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 3.0));
+
+        orderbook.uncross();
+
+        assert_eq!(orderbook.find_order(bid), None);
+        assert_eq!(
+            orderbook.find_order(ask),
+            Some((Price::new(100.0), BidOrAsk::Ask))
+        );
+        assert_eq!(orderbook.validate(), Ok(()));
+    }
+
+    #[test]
+    fn market_order_skips_fills_below_its_minimum() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 0.5));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 3.0));
+
+        let mut market_order = Order::new(BidOrAsk::Bid, 2.0).with_min_fill(1.0);
+        let fills = orderbook.fill_market_order(&mut market_order);
+
+        assert!(market_order.is_filled());
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, Price::new(101.0));
+        assert_eq!(fills[0].size, 2.0);
+        assert_eq!(orderbook.ask_limits()[0].total_volume(), 0.5);
+    }
+
+    #[test]
+    fn resting_order_minimum_skips_small_fills_and_drops_unfillable_remainder() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Ask, 3.0).with_min_fill(1.0));
+
+        let mut small_order = Order::new(BidOrAsk::Bid, 0.5);
+        assert!(orderbook.fill_market_order(&mut small_order).is_empty());
+        assert_eq!(small_order.size(), 0.5);
+
+        let mut adequate_order = Order::new(BidOrAsk::Bid, 2.5);
+        let fills = orderbook.fill_market_order(&mut adequate_order);
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].size, 2.5);
+        assert!(orderbook.ask_limits().is_empty());
+    }
+
+    // Places, cancels, matches and uncrosses, rendering everything observable about the run
+    fn replay_script() -> String {
+        let mut orderbook = Orderbook::new();
+        for (i, price) in [99.0, 101.5, 100.0, 98.25, 102.0, 100.5].iter().enumerate() {
+            let size = 0.1 * (i + 1) as f64;
+            orderbook.add_order(*price, Order::new(BidOrAsk::Bid, size));
+            orderbook.add_order(*price + 0.75, Order::new(BidOrAsk::Ask, size + 0.3));
+        }
+        orderbook.cancel_order(3);
+
+        let mut market_order = Order::new(BidOrAsk::Ask, 0.45);
+        let fills = orderbook.fill_market_order(&mut market_order);
+        let cross = orderbook.uncross();
+
+        // Corrupt the book so that validation reports several violations
+        for limits in [&mut orderbook.bids, &mut orderbook.asks] {
+            for limit in limits.values_mut() {
+                limit.orders[0].size = -1.0;
+            }
+        }
+        let validation = orderbook.validate();
+
+        format!(
+            "{}{:?}\n{:?}\n{:?}\n",
+            orderbook.snapshot(),
+            fills,
+            cross,
+            validation
+        )
+    }
+
+    #[test]
+    fn replaying_a_script_is_deterministic() {
+        let first = replay_script();
+
+        for _ in 0..10 {
+            assert_eq!(replay_script(), first);
+        }
+    }
+
+    #[test]
+    fn snapshot_lists_levels_best_price_first() {
+        let mut orderbook = Orderbook::new();
+        orderbook.add_order(99.0, Order::new(BidOrAsk::Bid, 1.0));
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 2.0));
+        orderbook.add_order(100.0, Order::new(BidOrAsk::Bid, 3.0));
+        orderbook.add_order(102.0, Order::new(BidOrAsk::Ask, 4.0));
+        orderbook.add_order(101.0, Order::new(BidOrAsk::Ask, 5.0));
+
+        assert_eq!(
+            orderbook.snapshot(),
+            "Bid 100: 2@2 3@3\nBid 99: 1@1\nAsk 101: 5@5\nAsk 102: 4@4\n"
+        );
     }
 }